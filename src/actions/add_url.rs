@@ -1,10 +1,16 @@
 use anyhow::Result;
 use rusqlite::Connection;
 
+use crate::actions::sitemaps;
+use crate::analysis::urls::UrlNormalizer;
 use crate::db;
 
-pub fn add_url(connection: &Connection, uri_str: &str) -> Result<()> {
-    let e = db::crawls::Crawl::new(uri_str)?;
+/// Queues a url to be crawled and, if asked to, the pages in its site's sitemaps.
+pub fn add_url(connection: &Connection, uri_str: &str, read_sitemaps: bool) -> Result<()> {
+    let e = db::crawls::Crawl::new(uri_str, &UrlNormalizer::load(connection)?)?;
     db::crawls::insert(connection, &e)?;
+    if read_sitemaps {
+        db::sitemaps::insert(connection, &sitemaps::site(&e.url)?)?;
+    }
     Ok(())
 }
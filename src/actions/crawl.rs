@@ -1,8 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use another_rust_warc::header::{FieldNames, Header, RecordID, RecordTypes};
 use another_rust_warc::reader::{Reader, find_record_by_type};
@@ -12,32 +12,57 @@ use anyhow::{Result, anyhow};
 use base64::{Engine as _, engine::general_purpose::URL_SAFE};
 use chrono::Utc;
 use chrono::format::SecondsFormat;
+use libflate::gzip;
 use reqwest::blocking::Client;
-use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use reqwest::header::{CONTENT_TYPE, HeaderName, USER_AGENT};
 use rusqlite::Connection;
-use scraper::Html;
 
-use crate::actions::index;
-use crate::db::{crawls, term_frequencies};
+use crate::actions::{feeds, sitemaps};
+use crate::analysis::Analyzer;
+use crate::analysis::fields::{self, Field};
+use crate::analysis::html::{Anchor, Page, Robots};
+use crate::analysis::simhash;
+use crate::analysis::urls::{UrlNormalizer, same_site};
+use crate::db::{self, canonicals, crawls, documents, fingerprints, links, term_frequencies};
 
 static USER_AGENT_STR: &str = "nvgs/1.0";
 
+static X_ROBOTS_TAG: HeaderName = HeaderName::from_static("x-robots-tag");
+
+/// Fields which come from the document itself, as opposed to the anchor text other pages use
+/// when linking to it.
+static DOCUMENT_FIELDS: [Field; 5] = [
+    Field::Title,
+    Field::Headings,
+    Field::Body,
+    Field::Url,
+    Field::Description,
+];
+
+/// The most read of a file fetched by `fetch`, the largest a sitemap may be uncompressed.
+static MAX_FETCH_BYTES: u64 = 50 * 1024 * 1024;
+
 fn encode_url(url: &str) -> String {
     URL_SAFE.encode(url)
 }
 
-pub fn crawl(connection: &mut Connection, path: &PathBuf, index_after: bool) -> Result<()> {
+/// Where the extracted text of a crawled page is kept.
+pub fn wet_path(path: &Path, url: &str) -> PathBuf {
+    path.join("warcs").join(format!("{}.wet", encode_url(url)))
+}
+
+pub fn crawl(connection: &mut Connection, path: &PathBuf) -> Result<()> {
     let client = Client::new();
+    let analyzer = Analyzer::load(connection)?;
+    let normalizer = UrlNormalizer::load(connection)?;
+    sitemaps::read_all(connection, &client, &normalizer)?;
+    feeds::poll_all(connection, &client, &analyzer, &normalizer)?;
     let entries = crawls::get_all_needing_update(connection)?;
 
     println!("Crawling {} pages", entries.len());
 
     for e in entries {
-        crawl_one(connection, path, &client, &e.url)?;
-    }
-
-    if index_after {
-        index::index(connection)?;
+        crawl_one(connection, path, &client, &analyzer, &normalizer, &e.url)?;
     }
     Ok(())
 }
@@ -46,6 +71,8 @@ pub fn crawl_one(
     connection: &mut Connection,
     path: &PathBuf,
     client: &Client,
+    analyzer: &Analyzer,
+    normalizer: &UrlNormalizer,
     url: &str,
 ) -> Result<()> {
     crawls::set_crawling(connection, url)?;
@@ -57,6 +84,11 @@ pub fn crawl_one(
         let mut response =
             client.execute(request.try_clone().ok_or(anyhow!("could not clone body"))?)?;
 
+        let mut robots = Robots::default();
+        for value in response.headers().get_all(&X_ROBOTS_TAG) {
+            robots.add_header(value.to_str().unwrap_or_default());
+        }
+
         let encoded_url = encode_url(url);
 
         /* We stream the result body into the warc file later we will read the file again to create
@@ -68,6 +100,7 @@ pub fn crawl_one(
         let mut warc_file = OpenOptions::new()
             .write(true)
             .append(false)
+            .truncate(true)
             .read(true)
             .create(true)
             .open(warc_path)?;
@@ -79,24 +112,29 @@ pub fn crawl_one(
          * and frequencies
          */
         println!("Writing wet...");
-        let wet_path = path.join("warcs").join(format!("{}.wet", encoded_url));
+        let wet_path = wet_path(path, url);
         let mut wet_file = OpenOptions::new()
             .write(true)
             .append(false)
+            .truncate(true)
             .read(true)
             .create(true)
             .open(wet_path)?;
-        write_wat_record(&warc_file, &mut wet_file)?;
-        wet_file.seek(SeekFrom::Start(0))?;
-
-        println!("Analyzing terms...");
-        let terms = analyze_terms(&mut wet_file, url)?;
+        let (mut page, content_type) = write_wat_record(&warc_file, url, &mut wet_file)?;
+        page.robots.noindex |= robots.noindex;
+        page.robots.nofollow |= robots.nofollow;
 
         let tx = connection.transaction()?;
-        println!("Updating term frequencies...");
-        for t in terms {
-            term_frequencies::insert(&tx, &t)?;
-        }
+        index_page(
+            &tx,
+            analyzer,
+            normalizer,
+            url,
+            &page,
+            &content_type,
+            Utc::now().timestamp(),
+        )?;
+        db::feeds::set_stub(&tx, url, false)?;
         tx.commit()?;
 
         let now = Utc::now().timestamp();
@@ -118,6 +156,28 @@ pub fn crawl_one(
     Ok(())
 }
 
+/// Fetches a file which is read for what it says rather than archived and indexed, a sitemap,
+/// robots.txt or feed, unzipping it if it was gzipped.
+pub fn fetch(client: &Client, url: &str) -> Result<String> {
+    let response = client
+        .get(url)
+        .header(USER_AGENT, USER_AGENT_STR)
+        .send()?
+        .error_for_status()?;
+
+    let mut body = vec![];
+    response.take(MAX_FETCH_BYTES).read_to_end(&mut body)?;
+    // Whether or not it was served as gzip, `.xml.gz` files often aren't
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let mut unzipped = vec![];
+        gzip::Decoder::new(&body[..])?
+            .take(MAX_FETCH_BYTES)
+            .read_to_end(&mut unzipped)?;
+        body = unzipped;
+    }
+    Ok(String::from_utf8_lossy_owned(body))
+}
+
 fn write_request_record(
     writer: &mut dyn Write,
     request: &reqwest::blocking::Request,
@@ -230,7 +290,13 @@ pub fn request_to_string(request: &reqwest::blocking::Request) -> Result<String>
     Ok(output)
 }
 
-pub fn write_wat_record(warc_file: &File, writer: &mut dyn Write) -> Result<()> {
+/// Writes the body text of the response to the wet file, returning it along with the rest of the
+/// fields we were able to extract from the document and its content type.
+pub fn write_wat_record(
+    warc_file: &File,
+    url: &str,
+    writer: &mut dyn Write,
+) -> Result<(Page, String)> {
     let mut reader = Reader::new(BufReader::new(warc_file));
 
     let record = find_record_by_type(&mut reader, RecordTypes::Response)?
@@ -241,67 +307,238 @@ pub fn write_wat_record(warc_file: &File, writer: &mut dyn Write) -> Result<()>
         .get(&FieldNames::ContentType)
         .ok_or(anyhow!("No content_type"))?;
 
-    match content_type.as_str() {
+    let page = match content_type.as_str() {
         "text/plain" => {
             let body = String::from_utf8_lossy_owned(record.content);
-            writer.write_all(body.as_bytes())?;
+            Page::from_text(&body)
         }
         "text/html" => {
             let body = String::from_utf8_lossy_owned(record.content);
-            let document = Html::parse_document(&body);
-
-            for text in document.root_element().text() {
-                let trimmed = text.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
-                for word in text.split_whitespace() {
-                    let t = word.trim();
-                    if t.is_empty() {
-                        continue;
-                    }
-                    write!(writer, "{} ", word)?;
-                }
-                write!(writer, "\n")?;
-            }
+            Page::from_html(&body, url)
         }
         _ => {
             return Err(anyhow!("Cannot process files of type: {}", content_type));
         }
     };
 
+    writeln!(writer, "{}", page.body)?;
+
+    Ok((page, content_type.to_string()))
+}
+
+/// Indexes a fetched page the way it asks to be. Pages marked `noindex` are archived but not
+/// indexed, the links on pages marked `nofollow` are ignored and pages which name another url as
+/// their canonical one are indexed under it, unless it has been crawled itself. A page can only
+/// say it's a copy of a page on another site once that page has been crawled and indexed as
+/// itself, until then the canonical page is queued and the page is indexed under its own url.
+pub fn index_page(
+    connection: &Connection,
+    analyzer: &Analyzer,
+    normalizer: &UrlNormalizer,
+    url: &str,
+    page: &Page,
+    content_type: &str,
+    crawled_at: i64,
+) -> Result<()> {
+    let canonical = page
+        .canonical
+        .as_deref()
+        .and_then(|c| normalizer.normalize(c).ok())
+        .filter(|c| c != url);
+    let (canonical, unconfirmed) = match canonical {
+        Some(c) if !same_site(url, &c) && !is_confirmed_canonical(connection, &c)? => {
+            (None, Some(c))
+        }
+        c => (c, None),
+    };
+    let canonical = canonical.as_deref();
+    match canonical {
+        Some(c) => canonicals::set(connection, url, c)?,
+        None => canonicals::delete(connection, url)?,
+    }
+
+    let target = canonical.unwrap_or(url);
+    if canonical.is_some() || page.robots.noindex {
+        unindex(connection, url)?;
+    }
+    let indexed = match canonical {
+        Some(c) => documents::get_metadata(connection, c)?.is_some(),
+        None => false,
+    };
+
+    if !page.robots.noindex && !indexed {
+        println!("Analyzing terms...");
+        let mut terms = analyze_terms(analyzer, Field::Body, &mut page.body.as_bytes(), target)?;
+        let url_text = fields::url_text(target);
+        for (field, text) in [
+            (Field::Title, &page.title),
+            (Field::Headings, &page.headings),
+            (Field::Description, &page.description),
+            (Field::Url, &url_text),
+        ] {
+            terms.extend(analyze_terms(
+                analyzer,
+                field,
+                &mut text.as_bytes(),
+                target,
+            )?);
+        }
+
+        println!("Updating term frequencies...");
+        for field in DOCUMENT_FIELDS {
+            documents::replace_field(connection, target, field, &terms)?;
+        }
+        let body = simhash::words(terms.iter().filter(|tf| tf.field == Field::Body));
+        match simhash::fingerprint(&body) {
+            Some(fingerprint) => fingerprints::set(connection, target, fingerprint)?,
+            None => fingerprints::remove(connection, target)?,
+        }
+        documents::set_metadata(
+            connection,
+            &documents::Metadata::new(target, content_type, page.language.as_deref(), crawled_at),
+        )?;
+    }
+
+    // The canonical page has the final say on what's indexed under its url
+    if let Some(c) = canonical.or(unconfirmed.as_deref())
+        && crawls::get(connection, c)?.is_none()
+    {
+        crawls::insert(connection, &crawls::Crawl::new(c, normalizer)?)?;
+    }
+
+    // Found feeds wait for someone to subscribe to them
+    for feed in &page.feeds {
+        if let Ok(feed) = normalizer.normalize(feed) {
+            db::feeds::discover(connection, &feed, url)?;
+        }
+    }
+
+    println!("Updating links...");
+    let anchors: Vec<Anchor> = if page.robots.nofollow {
+        vec![]
+    } else {
+        page.anchors
+            .iter()
+            .filter_map(|a| {
+                Some(Anchor {
+                    href: normalizer.normalize(&a.href).ok()?,
+                    text: a.text.clone(),
+                })
+            })
+            .collect()
+    };
+    update_links(connection, analyzer, url, &anchors)?;
+    // Moves the anchor text of links to a copy over to its canonical page
+    index_anchor_text(connection, analyzer, url)
+}
+
+/// A page has confirmed it's canonical once its own fetch indexed it without it naming another.
+/// What a feed said about a page that hasn't been fetched yet doesn't count.
+fn is_confirmed_canonical(connection: &Connection, url: &str) -> Result<bool> {
+    Ok(documents::get_metadata(connection, url)?.is_some()
+        && canonicals::get(connection, url)?.is_none()
+        && !db::feeds::is_stub(connection, url)?)
+}
+
+/// Replaces the links found on `source` and reindexes the anchor text of every page it used to,
+/// or now does, link to. Targets don't need to have been crawled themselves.
+pub fn update_links(
+    connection: &Connection,
+    analyzer: &Analyzer,
+    source: &str,
+    anchors: &[Anchor],
+) -> Result<()> {
+    let mut targets: BTreeSet<String> = links::get_targets_by_source(connection, source)?
+        .into_iter()
+        .collect();
+    links::delete_by_source(connection, source)?;
+
+    for a in anchors {
+        if a.href == source || a.text.is_empty() {
+            continue;
+        }
+        links::insert(connection, &links::Link::new(source, &a.href, &a.text))?;
+        targets.insert(a.href.clone());
+    }
+
+    for target in targets {
+        index_anchor_text(connection, analyzer, &target)?;
+    }
+
     Ok(())
 }
 
+/// Indexes the text of every link pointing at `target` as its anchor field. Links to a copy of a
+/// page count as links to the canonical page.
+pub fn index_anchor_text(connection: &Connection, analyzer: &Analyzer, target: &str) -> Result<()> {
+    let canonical = canonicals::get(connection, target)?;
+    if canonical.is_some() {
+        documents::replace_field(connection, target, Field::Anchor, &[])?;
+    }
+    let target = canonical.as_deref().unwrap_or(target);
+
+    let mut text = Vec::new();
+    let copies = canonicals::get_by_canonical(connection, target)?;
+    for t in [target.to_string()].into_iter().chain(copies) {
+        text.extend(
+            links::get_by_target(connection, &t)?
+                .into_iter()
+                .map(|l| l.text),
+        );
+    }
+
+    let terms = analyze_terms(
+        analyzer,
+        Field::Anchor,
+        &mut text.join("\n").as_bytes(),
+        target,
+    )?;
+
+    documents::replace_field(connection, target, Field::Anchor, &terms)
+}
+
+/// Takes a page out of the index, along with the anchor text it contributed to the pages it
+/// linked to.
+pub fn remove(connection: &Connection, analyzer: &Analyzer, url: &str) -> Result<()> {
+    unindex(connection, url)?;
+    canonicals::delete(connection, url)?;
+    update_links(connection, analyzer, url, &[])
+}
+
+/// Takes what came from the page itself out of the index.
+fn unindex(connection: &Connection, url: &str) -> Result<()> {
+    documents::delete(connection, url, &DOCUMENT_FIELDS)?;
+    documents::delete_metadata(connection, url)?;
+    fingerprints::remove(connection, url)
+}
+
 pub fn analyze_terms(
+    analyzer: &Analyzer,
+    field: Field,
     reader: &mut dyn Read,
     url: &str,
 ) -> Result<Vec<term_frequencies::TermFrequency>> {
     let lines = BufReader::new(reader).lines();
 
     let mut terms: BTreeMap<String, term_frequencies::TermFrequency> = BTreeMap::new();
-    let mut total = 0;
+    let mut total: u32 = 0;
 
     for line in lines {
         let l = line?;
-        for word in l.split_whitespace() {
-            // Lowecase and strip punctuation
-            let w = word.to_lowercase().replace(
-                &[
-                    '(', ')', ',', '\"', '.', ';', ':', '\'', '-', '~', '+', '=', '$', '%', '^',
-                    '*', '?',
-                ][..],
-                "",
-            );
+        for w in analyzer.tokenize(&l) {
+            let position = total;
             total += 1;
             if let Some(tf) = terms.get_mut(&w) {
                 tf.count += 1;
+                tf.positions.push(position);
             } else {
                 terms.insert(w.clone(), term_frequencies::TermFrequency {
+                    field,
                     term: w,
                     count: 1,
                     frequency: 0.0,
                     url: url.to_string(),
+                    positions: vec![position],
                 });
             }
         }
@@ -330,16 +567,184 @@ mod tests {
     fn test_analyze_terms() {
         let mut input = "Alpha Bravo.\n Charlie \n Delta\tEcho\nAlpha Delta\nAlpha".as_bytes();
         let url = "http://www.example.com";
-        let result = analyze_terms(&mut input, url).unwrap();
+        let result = analyze_terms(&Analyzer::default(), Field::Body, &mut input, url).unwrap();
         assert_eq!(
             vec![
-                term_frequencies::TermFrequency::new(url, "alpha", 3, 0.375),
-                term_frequencies::TermFrequency::new(url, "bravo", 1, 0.125),
-                term_frequencies::TermFrequency::new(url, "charlie", 1, 0.125),
-                term_frequencies::TermFrequency::new(url, "delta", 2, 0.25),
-                term_frequencies::TermFrequency::new(url, "echo", 1, 0.125),
+                term_frequencies::TermFrequency::new(url, Field::Body, "alpha", 3, 0.375, vec![
+                    0, 5, 7
+                ]),
+                term_frequencies::TermFrequency::new(url, Field::Body, "bravo", 1, 0.125, vec![1]),
+                term_frequencies::TermFrequency::new(url, Field::Body, "charlie", 1, 0.125, vec![
+                    2
+                ]),
+                term_frequencies::TermFrequency::new(url, Field::Body, "delta", 2, 0.25, vec![
+                    3, 6
+                ]),
+                term_frequencies::TermFrequency::new(url, Field::Body, "echo", 1, 0.125, vec![4]),
             ],
             result
         )
     }
+
+    #[test]
+    fn test_update_links() {
+        let connection = Connection::open_in_memory().unwrap();
+        crate::db::initalize_tables(&connection).unwrap();
+        let analyzer = Analyzer::default();
+        let target = "http://book.example.com/";
+
+        let anchor = |text: &str| Anchor {
+            href: target.to_string(),
+            text: text.to_string(),
+        };
+
+        update_links(&connection, &analyzer, "http://a.example.com/", &[anchor(
+            "Rust book",
+        )])
+        .unwrap();
+        update_links(&connection, &analyzer, "http://b.example.com/", &[anchor(
+            "The book",
+        )])
+        .unwrap();
+
+        let book = term_frequencies::get(&connection, target, Field::Anchor, "book")
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, book.count);
+        assert!(
+            term_frequencies::get(&connection, target, Field::Anchor, "rust")
+                .unwrap()
+                .is_some()
+        );
+
+        update_links(&connection, &analyzer, "http://a.example.com/", &[]).unwrap();
+
+        let book = term_frequencies::get(&connection, target, Field::Anchor, "book")
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, book.count);
+        assert!(
+            term_frequencies::get(&connection, target, Field::Anchor, "rust")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_index_page() {
+        let connection = Connection::open_in_memory().unwrap();
+        crate::db::initalize_tables(&connection).unwrap();
+        let analyzer = Analyzer::default();
+        let index = |url: &str, html: &str| {
+            let page = Page::from_html(html, url);
+            let normalizer = UrlNormalizer::default();
+            index_page(
+                &connection,
+                &analyzer,
+                &normalizer,
+                url,
+                &page,
+                "text/html",
+                0,
+            )
+            .unwrap();
+        };
+        let indexed = |url: &str| documents::get_metadata(&connection, url).unwrap().is_some();
+        let links_from = |url: &str| links::get_targets_by_source(&connection, url).unwrap();
+
+        index(
+            "http://a.example.com/",
+            r#"<meta name="robots" content="noindex"><a href="/b">Bravo</a>"#,
+        );
+        assert!(!indexed("http://a.example.com/"));
+        assert_eq!(
+            vec!["http://a.example.com/b"],
+            links_from("http://a.example.com/")
+        );
+
+        index(
+            "http://a.example.com/",
+            r#"<meta name="robots" content="nofollow"><a href="/b">Bravo</a>"#,
+        );
+        assert!(indexed("http://a.example.com/"));
+        assert!(links_from("http://a.example.com/").is_empty());
+
+        // Copies are indexed under the canonical url until it's crawled itself
+        index(
+            "http://a.example.com/print",
+            r#"<link rel="canonical" href="/article"><p>Charlie</p>"#,
+        );
+        index("http://a.example.com/", r#"<a href="/print">Delta</a>"#);
+        assert!(!indexed("http://a.example.com/print"));
+        assert!(indexed("http://a.example.com/article"));
+        assert!(
+            crawls::get(&connection, "http://a.example.com/article")
+                .unwrap()
+                .is_some()
+        );
+        for (field, term) in [(Field::Body, "charlie"), (Field::Anchor, "delta")] {
+            assert!(
+                term_frequencies::get(&connection, "http://a.example.com/article", field, term)
+                    .unwrap()
+                    .is_some()
+            );
+        }
+
+        index("http://a.example.com/article", "<p>Echo</p>");
+        index(
+            "http://a.example.com/print",
+            r#"<link rel="canonical" href="/article"><p>Foxtrot</p>"#,
+        );
+        let body = |term: &str| {
+            term_frequencies::get(
+                &connection,
+                "http://a.example.com/article",
+                Field::Body,
+                term,
+            )
+            .unwrap()
+            .is_some()
+        };
+        assert!(body("echo"));
+        assert!(!body("foxtrot"));
+
+        // Another site can't put its text under a page it hasn't been confirmed a copy of
+        index(
+            "http://b.example.org/copy",
+            r#"<link rel="canonical" href="http://a.example.com/news"><p>Golf</p>"#,
+        );
+        assert!(indexed("http://b.example.org/copy"));
+        assert!(!indexed("http://a.example.com/news"));
+        assert_eq!(
+            None,
+            canonicals::get(&connection, "http://b.example.org/copy").unwrap()
+        );
+        assert!(
+            crawls::get(&connection, "http://a.example.com/news")
+                .unwrap()
+                .is_some()
+        );
+
+        // Once the canonical page has been crawled as itself it's honored
+        index("http://a.example.com/news", "<p>Hotel</p>");
+        index(
+            "http://b.example.org/copy",
+            r#"<link rel="canonical" href="http://a.example.com/news"><p>Golf</p>"#,
+        );
+        assert!(!indexed("http://b.example.org/copy"));
+        assert_eq!(
+            Some("http://a.example.com/news".to_string()),
+            canonicals::get(&connection, "http://b.example.org/copy").unwrap()
+        );
+        assert!(
+            term_frequencies::get(
+                &connection,
+                "http://a.example.com/news",
+                Field::Body,
+                "golf"
+            )
+            .unwrap()
+            .is_none()
+        );
+    }
 }
@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use rusqlite::Connection;
+
+use crate::actions::search::{self, Document, SearchOptions};
+use crate::ranking::RankingFunction;
+
+/// The relevance of urls to each query, graded from 0 for not relevant upwards.
+pub type Judgments = BTreeMap<String, HashMap<String, u32>>;
+
+#[derive(Debug, PartialEq)]
+pub struct Metrics {
+    pub ndcg: f64,
+    pub mrr: f64,
+}
+
+/// Judgments are read from tab separated lines of `query`, `url` and `grade`. Blank lines and
+/// lines starting with `#` are ignored.
+pub fn read_judgments(reader: &mut dyn Read) -> Result<Judgments> {
+    let mut judgments = Judgments::new();
+
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let l = line?;
+        if l.trim().is_empty() || l.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = l.split('\t');
+        let (Some(query), Some(url), Some(grade), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("Invalid judgment on line {}: {}", i + 1, l));
+        };
+
+        judgments
+            .entry(query.to_string())
+            .or_default()
+            .insert(url.to_string(), grade.trim().parse()?);
+    }
+
+    Ok(judgments)
+}
+
+fn dcg(grades: impl Iterator<Item = u32>) -> f64 {
+    grades
+        .enumerate()
+        .map(|(i, g)| (2f64.powi(g as i32) - 1.0) / (i as f64 + 2.0).log2())
+        .sum()
+}
+
+/// Normalized discounted cumulative gain of the first `k` results.
+pub fn ndcg(results: &[Document], grades: &HashMap<String, u32>, k: usize) -> f64 {
+    let mut ideal: Vec<u32> = grades.values().copied().collect();
+    ideal.sort_by(|a, b| b.cmp(a));
+
+    let best = dcg(ideal.into_iter().take(k));
+    if best == 0.0 {
+        return 0.0;
+    }
+
+    let actual = dcg(results
+        .iter()
+        .take(k)
+        .map(|d| grades.get(&d.url).copied().unwrap_or(0)));
+    actual / best
+}
+
+/// One over the rank of the first relevant result.
+pub fn reciprocal_rank(results: &[Document], grades: &HashMap<String, u32>) -> f64 {
+    results
+        .iter()
+        .position(|d| grades.get(&d.url).is_some_and(|g| *g > 0))
+        .map_or(0.0, |i| 1.0 / (i as f64 + 1.0))
+}
+
+pub fn evaluate_ranking(
+    connection: &Connection,
+    judgments: &Judgments,
+    ranking: RankingFunction,
+    k: usize,
+) -> Result<Metrics> {
+    let options = SearchOptions {
+        ranking: Some(ranking),
+        ..Default::default()
+    };
+
+    let mut ndcg_total = 0.0;
+    let mut mrr_total = 0.0;
+
+    for (query, grades) in judgments {
+        let terms: Vec<String> = query.split_whitespace().map(|t| t.to_string()).collect();
+        let results = search::execute(connection, &terms, &options)?.documents;
+        ndcg_total += ndcg(&results, grades, k);
+        mrr_total += reciprocal_rank(&results, grades);
+    }
+
+    let count = judgments.len().max(1) as f64;
+    Ok(Metrics {
+        ndcg: ndcg_total / count,
+        mrr: mrr_total / count,
+    })
+}
+
+/// Runs every judged query against each ranking function so they can be compared on the same
+/// index.
+pub fn evaluate(connection: &Connection, judgments_path: &Path, k: usize) -> Result<()> {
+    let judgments = read_judgments(&mut File::open(judgments_path)?)?;
+
+    println!("Evaluating {} queries", judgments.len());
+    println!("\tranking\tndcg@{}\tmrr", k);
+    for ranking in RankingFunction::ALL {
+        let metrics = evaluate_ranking(connection, &judgments, ranking, k)?;
+        println!(
+            "\t{}\t{:.4}\t{:.4}",
+            ranking.as_str(),
+            metrics.ndcg,
+            metrics.mrr
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::fields::Field;
+    use crate::db;
+    use crate::db::term_frequencies::TermFrequency;
+
+    #[test]
+    fn test_read_judgments() {
+        let mut input =
+            "# query\turl\tgrade\nrust book\thttp://a\t2\n\nrust book\thttp://b\t0\n".as_bytes();
+        let judgments = read_judgments(&mut input).unwrap();
+        assert_eq!(1, judgments.len());
+        assert_eq!(Some(&2), judgments["rust book"].get("http://a"));
+
+        let mut input = "rust book\thttp://a\n".as_bytes();
+        assert!(read_judgments(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let grades: HashMap<String, u32> = [("http://a".to_string(), 2)].into_iter().collect();
+        let first = vec![
+            Document::new("http://a", 1.0),
+            Document::new("http://b", 0.5),
+        ];
+        let second = vec![
+            Document::new("http://b", 1.0),
+            Document::new("http://a", 0.5),
+        ];
+
+        assert_eq!(1.0, ndcg(&first, &grades, 10));
+        assert!(ndcg(&second, &grades, 10) < 1.0);
+        assert_eq!(0.0, ndcg(&second, &grades, 1));
+        assert_eq!(1.0, reciprocal_rank(&first, &grades));
+        assert_eq!(0.5, reciprocal_rank(&second, &grades));
+    }
+
+    /// Raw term frequency rewards a document repeating one of the query terms over and over, BM25
+    /// saturates it. This only checks that the two functions differ the way they're meant to on a
+    /// made up collection, not which ranks a real one better.
+    #[test]
+    fn test_bm25_saturates_term_frequency() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        let documents = [
+            ("http://spam.example.com", vec![("rust", (0..20).collect())]),
+            ("http://good.example.com", vec![
+                ("rust", vec![0]),
+                ("book", vec![5]),
+                ("guide", (1..5).chain(6..10).collect()),
+            ]),
+            ("http://cats.example.com", vec![("cats", (0..10).collect())]),
+            ("http://dogs.example.com", vec![("dogs", (0..10).collect())]),
+        ];
+
+        for (url, terms) in documents {
+            let length: usize = terms.iter().map(|(_, ps)| ps.len()).sum();
+            let terms: Vec<TermFrequency> = terms
+                .into_iter()
+                .map(|(term, positions)| {
+                    let count = positions.len() as u64;
+                    let frequency = count as f64 / length as f64;
+                    TermFrequency::new(url, Field::Body, term, count, frequency, positions)
+                })
+                .collect();
+            db::documents::replace_field(&connection, url, Field::Body, &terms).unwrap();
+        }
+
+        let mut input = "rust book\thttp://good.example.com\t2\n".as_bytes();
+        let judgments = read_judgments(&mut input).unwrap();
+
+        let tf_idf = evaluate_ranking(&connection, &judgments, RankingFunction::TfIdf, 10).unwrap();
+        let bm25 = evaluate_ranking(&connection, &judgments, RankingFunction::Bm25, 10).unwrap();
+        assert_eq!(0.5, tf_idf.mrr);
+        assert_eq!(1.0, bm25.mrr);
+        assert!(bm25.ndcg > tf_idf.ndcg);
+    }
+}
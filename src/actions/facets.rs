@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Days};
+use reqwest::Url;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::db::documents;
+
+/// The most values of a facet returned, the ones with the most results.
+static MAX_FACET_VALUES: usize = 10;
+
+/// The date buckets, each counting the documents crawled in the given number of days up to and
+/// including today.
+static CRAWLED_BUCKETS: [(&str, u64); 4] = [
+    ("past day", 1),
+    ("past week", 7),
+    ("past month", 30),
+    ("past year", 365),
+];
+
+/// The site a document belongs to, its host without any `www.`.
+pub fn site(url: &str) -> String {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+        .unwrap_or_else(|| url.to_string());
+    match host.strip_prefix("www.") {
+        Some(h) => h.to_string(),
+        None => host,
+    }
+}
+
+/// One value of a facet and how many results have it.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: usize,
+    /// Narrows the results down to those with this value, either added to the query or passed
+    /// with the next search's filters.
+    pub filter: String,
+}
+
+impl FacetValue {
+    fn new(value: &str, count: usize, filter: String) -> Self {
+        Self {
+            value: value.to_string(),
+            count,
+            filter,
+        }
+    }
+}
+
+/// Counts of the results by where they came from. Documents we haven't crawled ourselves only
+/// count towards their site.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Facets {
+    pub site: Vec<FacetValue>,
+    pub language: Vec<FacetValue>,
+    pub content_type: Vec<FacetValue>,
+    /// Cumulative, a document crawled today counts towards every bucket.
+    pub crawled: Vec<FacetValue>,
+}
+
+impl Facets {
+    pub fn count<'a>(
+        connection: &Connection,
+        urls: impl IntoIterator<Item = &'a str>,
+        now: i64,
+    ) -> Result<Self> {
+        let mut sites: HashMap<String, usize> = HashMap::new();
+        let mut languages: HashMap<String, usize> = HashMap::new();
+        let mut content_types: HashMap<String, usize> = HashMap::new();
+        let mut crawled_at = Vec::new();
+
+        let urls: Vec<&str> = urls.into_iter().collect();
+        let mut metadata = documents::get_metadata_by_urls(connection, &urls)?;
+        for url in urls {
+            *sites.entry(site(url)).or_default() += 1;
+            let Some(metadata) = metadata.remove(url) else {
+                continue;
+            };
+            if let Some(language) = metadata.language {
+                *languages.entry(language).or_default() += 1;
+            }
+            *content_types
+                .entry(metadata.content_type.to_lowercase())
+                .or_default() += 1;
+            crawled_at.push(metadata.crawled_at);
+        }
+
+        // Bucketed by whole days to agree with the dates `after:` takes
+        let today = DateTime::from_timestamp(now, 0)
+            .unwrap_or_default()
+            .date_naive();
+        let mut crawled = Vec::new();
+        for (name, days) in CRAWLED_BUCKETS {
+            let since = today - Days::new(days - 1);
+            let start = since.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+            let count = crawled_at.iter().filter(|at| **at >= start).count();
+            if count > 0 {
+                crawled.push(FacetValue::new(
+                    name,
+                    count,
+                    format!("after:{}", since.format("%Y-%m-%d")),
+                ));
+            }
+        }
+
+        Ok(Self {
+            site: top_values(sites, "site"),
+            language: top_values(languages, "lang"),
+            content_type: top_values(content_types, "type"),
+            crawled,
+        })
+    }
+}
+
+fn top_values(counts: HashMap<String, usize>, filter: &str) -> Vec<FacetValue> {
+    let mut values: Vec<(String, usize)> = counts.into_iter().collect();
+    values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    values.truncate(MAX_FACET_VALUES);
+    values
+        .into_iter()
+        .map(|(value, count)| {
+            let filter = format!("{}:{}", filter, value);
+            FacetValue::new(&value, count, filter)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::db::documents::Metadata;
+
+    #[test]
+    fn test_count() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        // 2024-01-31 12:00:00 UTC
+        let now = 1706702400;
+        let day = 24 * 60 * 60;
+        for (url, content_type, language, crawled_at) in [
+            ("http://www.a.com/1", "text/html", Some("en"), now),
+            ("http://a.com/2", "text/html", Some("fr"), now - 3 * day),
+            ("http://b.com/", "text/plain", None, now - 100 * day),
+        ] {
+            let metadata = Metadata::new(url, content_type, language, crawled_at);
+            documents::set_metadata(&connection, &metadata).unwrap();
+        }
+
+        let urls = [
+            "http://www.a.com/1",
+            "http://a.com/2",
+            "http://b.com/",
+            "http://c.com/",
+        ];
+        let facets = Facets::count(&connection, urls, now).unwrap();
+
+        assert_eq!(
+            vec![
+                FacetValue::new("a.com", 2, "site:a.com".into()),
+                FacetValue::new("b.com", 1, "site:b.com".into()),
+                FacetValue::new("c.com", 1, "site:c.com".into()),
+            ],
+            facets.site
+        );
+        assert_eq!(
+            vec![
+                FacetValue::new("en", 1, "lang:en".into()),
+                FacetValue::new("fr", 1, "lang:fr".into()),
+            ],
+            facets.language
+        );
+        assert_eq!(
+            vec![
+                FacetValue::new("text/html", 2, "type:text/html".into()),
+                FacetValue::new("text/plain", 1, "type:text/plain".into()),
+            ],
+            facets.content_type
+        );
+        assert_eq!(
+            vec![
+                FacetValue::new("past day", 1, "after:2024-01-31".into()),
+                FacetValue::new("past week", 2, "after:2024-01-25".into()),
+                FacetValue::new("past month", 2, "after:2024-01-02".into()),
+                FacetValue::new("past year", 3, "after:2023-02-01".into()),
+            ],
+            facets.crawled
+        );
+    }
+}
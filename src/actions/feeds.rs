@@ -0,0 +1,244 @@
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::blocking::Client;
+use rusqlite::Connection;
+
+use crate::actions::crawl::{self, fetch};
+use crate::analysis::Analyzer;
+use crate::analysis::feed::{Feed, Item};
+use crate::analysis::html::Page;
+use crate::analysis::urls::{UrlNormalizer, same_site};
+use crate::db::{crawls, documents, feeds};
+
+pub fn subscribe(connection: &Connection, url: &str) -> Result<()> {
+    let url = UrlNormalizer::load(connection)?.normalize(url)?;
+    feeds::subscribe(connection, &url)
+}
+
+/// Forgets a feed, whether subscribed to or found.
+pub fn unsubscribe(connection: &Connection, url: &str) -> Result<()> {
+    feeds::delete(connection, &UrlNormalizer::load(connection)?.lookup(url))
+}
+
+pub fn list(connection: &Connection) -> Result<()> {
+    for feed in feeds::get_all(connection)? {
+        match (feed.subscribed, feed.source) {
+            (false, Some(source)) => println!("{}\tfound on {}", feed.url, source),
+            _ => println!("{}", feed.url),
+        }
+    }
+    Ok(())
+}
+
+/// Polls every subscription not polled within the hour. Feeds which can't be read are skipped
+/// until the next time.
+pub fn poll_all(
+    connection: &mut Connection,
+    client: &Client,
+    analyzer: &Analyzer,
+    normalizer: &UrlNormalizer,
+) -> Result<()> {
+    for feed in feeds::get_all_needing_update(connection)? {
+        println!("Polling feed {}", feed.url);
+        let polled = match poll(connection, client, analyzer, normalizer, &feed.url) {
+            Ok(url) => url,
+            Err(e) => {
+                println!("Failed to poll feed: {}", feed.url);
+                println!("\tError:{}", e);
+                feed.url
+            }
+        };
+        feeds::set_polled(connection, &polled, Utc::now().timestamp())?;
+    }
+    Ok(())
+}
+
+/// Fetches a feed and adds its new entries. A subscription to a page rather than a feed becomes
+/// one to the feed the page links to. Returns the url of the feed polled.
+pub fn poll(
+    connection: &mut Connection,
+    client: &Client,
+    analyzer: &Analyzer,
+    normalizer: &UrlNormalizer,
+    url: &str,
+) -> Result<String> {
+    let body = fetch(client, url)?;
+    let (url, feed) = match Feed::parse(&body, url) {
+        Ok(feed) => (url.to_string(), feed),
+        Err(e) => {
+            let page = Page::from_html(&body, url);
+            let Some(found) = page
+                .feeds
+                .first()
+                .and_then(|f| normalizer.normalize(f).ok())
+            else {
+                return Err(e);
+            };
+            let feed = Feed::parse(&fetch(client, &found)?, &found)?;
+            feeds::delete(connection, url)?;
+            feeds::subscribe(connection, &found)?;
+            println!("Subscribed to {} instead", found);
+            (found, feed)
+        }
+    };
+
+    let tx = connection.transaction()?;
+    let added = add_items(&tx, analyzer, normalizer, &url, &feed.items)?;
+    tx.commit()?;
+    println!("Queued {} new entries", added);
+    Ok(url)
+}
+
+/// Queues the entries of a feed not already queued to be crawled. Until they are, what the feed
+/// says about entries on its own site is indexed under their url, the title as the title and the
+/// summary as the description. A feed only speaks for its own site, entries elsewhere are just
+/// queued. Returns the number of new entries.
+pub fn add_items(
+    connection: &Connection,
+    analyzer: &Analyzer,
+    normalizer: &UrlNormalizer,
+    feed: &str,
+    items: &[Item],
+) -> Result<usize> {
+    let now = Utc::now().timestamp();
+    let mut added = 0;
+    for item in items {
+        let Ok(url) = normalizer.normalize(&item.url) else {
+            continue;
+        };
+        feeds::add_item(connection, feed, &url)?;
+        if crawls::get(connection, &url)?.is_some() {
+            continue;
+        }
+        crawls::insert(connection, &crawls::Crawl::new(&url, normalizer)?)?;
+        added += 1;
+
+        if same_site(feed, &url) && documents::get_metadata(connection, &url)?.is_none() {
+            let page = Page {
+                title: item.title.clone(),
+                description: item.summary.clone(),
+                ..Default::default()
+            };
+            // Entries link to web pages, which is what they'll be indexed as once crawled
+            crawl::index_page(
+                connection,
+                analyzer,
+                normalizer,
+                &url,
+                &page,
+                "text/html",
+                item.updated.unwrap_or(now),
+            )?;
+            feeds::set_stub(connection, &url, true)?;
+        }
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::search::{self, SearchOptions};
+    use crate::db;
+
+    #[test]
+    fn test_add_items() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let analyzer = Analyzer::default();
+        let normalizer = UrlNormalizer::default();
+        let feed = "https://example.com/feed.xml";
+        subscribe(&connection, feed).unwrap();
+
+        // Already crawled, the page has the final say on what's indexed
+        let crawled = documents::Metadata::new("https://example.com/about", "text/html", None, 1);
+        documents::set_metadata(&connection, &crawled).unwrap();
+
+        let item = |url: &str, title: &str, summary: &str| Item {
+            url: url.to_string(),
+            title: title.to_string(),
+            summary: summary.to_string(),
+            updated: Some(1709294400),
+        };
+        let items = vec![
+            item(
+                "https://example.com/posts/first/?utm_source=rss",
+                "Sourdough starter",
+                "Feeding a starter",
+            ),
+            item("https://example.com/about", "About sourdough", ""),
+            item("https://elsewhere.com/story", "Sourdough elsewhere", ""),
+        ];
+        assert_eq!(
+            3,
+            add_items(&connection, &analyzer, &normalizer, feed, &items).unwrap()
+        );
+        // Only new entries are added
+        assert_eq!(
+            0,
+            add_items(&connection, &analyzer, &normalizer, feed, &items).unwrap()
+        );
+
+        for url in [
+            "https://example.com/posts/first",
+            "https://example.com/about",
+            "https://elsewhere.com/story",
+        ] {
+            assert_eq!(
+                -1,
+                crawls::get(&connection, url).unwrap().unwrap().last_updated
+            );
+        }
+        let metadata = documents::get_metadata(&connection, "https://example.com/posts/first")
+            .unwrap()
+            .unwrap();
+        assert_eq!(1709294400, metadata.crawled_at);
+        assert!(feeds::is_stub(&connection, "https://example.com/posts/first").unwrap());
+        assert!(!feeds::is_stub(&connection, "https://example.com/about").unwrap());
+        // A feed only speaks for its own site
+        assert_eq!(
+            None,
+            documents::get_metadata(&connection, "https://elsewhere.com/story").unwrap()
+        );
+
+        // What the feed said doesn't confirm the page as canonical for another site
+        let copy = Page {
+            body: "Copied bread".to_string(),
+            canonical: Some("https://example.com/posts/first".to_string()),
+            ..Default::default()
+        };
+        let copy_url = "https://copies.com/first";
+        crawl::index_page(
+            &connection,
+            &analyzer,
+            &normalizer,
+            copy_url,
+            &copy,
+            "text/html",
+            1,
+        )
+        .unwrap();
+        assert_eq!(None, db::canonicals::get(&connection, copy_url).unwrap());
+
+        // Another feed listing the same entry doesn't queue it again
+        let other = "https://other.com/feed.xml";
+        assert_eq!(
+            0,
+            add_items(&connection, &analyzer, &normalizer, other, &items).unwrap()
+        );
+
+        let urls = |query: &str| -> Vec<String> {
+            search::execute(&connection, &[query.to_string()], &SearchOptions::default())
+                .unwrap()
+                .documents
+                .into_iter()
+                .map(|d| d.url)
+                .collect()
+        };
+        assert_eq!(vec!["https://example.com/posts/first"], urls("sourdough"));
+        assert_eq!(vec!["https://example.com/posts/first"], urls("feeding"));
+
+        unsubscribe(&connection, feed).unwrap();
+        assert!(feeds::get_all(&connection).unwrap().is_empty());
+    }
+}
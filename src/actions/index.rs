@@ -1,10 +1,12 @@
 use anyhow::Result;
 use rusqlite::Connection;
 
-use crate::db::tf_idf;
+use crate::db::documents;
 
+/// Documents are searchable as soon as they're crawled, this only recomputes the collection
+/// statistics from scratch in case they've drifted.
 pub fn index(connection: &Connection) -> Result<()> {
     println!("indexing...");
-    tf_idf::index(connection)?;
+    documents::rebuild_stats(connection)?;
     Ok(())
 }
@@ -4,9 +4,11 @@ use std::path::PathBuf;
 use anyhow::{Result, anyhow};
 use rusqlite::Connection;
 
+use crate::actions::migrate;
+use crate::analysis::Analyzer;
 use crate::db;
 
-pub fn init(path: &PathBuf) -> Result<()> {
+pub fn init(path: &PathBuf, analyzer: &Analyzer) -> Result<()> {
     if !path.exists() {
         fs::create_dir(path)?;
     }
@@ -24,6 +26,8 @@ pub fn init(path: &PathBuf) -> Result<()> {
 
     let connection = Connection::open(path.join("nvgs.db"))?;
     db::initalize_tables(&connection)?;
+    migrate::mark_current(&connection)?;
+    analyzer.save(&connection)?;
 
     Ok(())
 }
@@ -0,0 +1,862 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::actions::crawl;
+use crate::analysis::Analyzer;
+use crate::analysis::fields::Field;
+use crate::analysis::simhash;
+use crate::analysis::urls::UrlNormalizer;
+use crate::db::{
+    canonicals, collection_stats, crawls, documents, feeds, field_lengths, field_stats,
+    fingerprints, links, queries, settings, sitemaps, spelling, synonyms, term_frequencies,
+    term_stats, votes,
+};
+
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Changes to an index made by an older version, in the order they were added. The index's
+/// `user_version` counts how many have been applied, new ones go on the end. Indexes from before
+/// migrations were recorded are all at zero whatever their schema, so each one checks for what
+/// it adds.
+static MIGRATIONS: &[Migration] = &[
+    add_settings,
+    add_positions,
+    add_fields,
+    add_stats,
+    add_tables,
+    normalize_urls,
+    add_crawl_schedule,
+    add_feeds,
+    remove_short_fingerprints,
+    key_feed_items_by_feed,
+];
+
+fn version(connection: &Connection) -> Result<usize> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+fn set_version(connection: &Connection, version: usize) -> Result<()> {
+    connection.pragma_update(None, "user_version", version as i64)?;
+    Ok(())
+}
+
+/// Brings an index up to date, each migration in a transaction of its own.
+pub fn migrate(connection: &mut Connection) -> Result<()> {
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version(connection)?) {
+        let tx = connection.transaction()?;
+        migration(&tx)?;
+        set_version(&tx, i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// New indexes are created up to date.
+pub fn mark_current(connection: &Connection) -> Result<()> {
+    set_version(connection, MIGRATIONS.len())
+}
+
+fn has_table(connection: &Connection, table: &str) -> Result<bool> {
+    let count: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn has_column(connection: &Connection, table: &str, column: &str) -> Result<bool> {
+    let count: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Indexes got settings of their own. Terms in an older index weren't accent folded, so it keeps
+/// on not folding them rather than taking the default.
+fn add_settings(connection: &Connection) -> Result<()> {
+    if !has_table(connection, "settings")? {
+        settings::create_table(connection)?;
+        Analyzer::new(false).save(connection)?;
+    }
+    Ok(())
+}
+
+/// Terms got their positions in the document. Those indexed before are left without, which
+/// phrase queries take as not knowing rather than as not matching.
+fn add_positions(connection: &Connection) -> Result<()> {
+    if !has_column(connection, "term_frequencies", "positions")? {
+        connection.execute(
+            "ALTER TABLE term_frequencies ADD COLUMN positions BLOB",
+            params![],
+        )?;
+    }
+    Ok(())
+}
+
+/// Terms got indexed by the field they're in, which is part of the key so the table is built
+/// again. Everything indexed before was the body text.
+fn add_fields(connection: &Connection) -> Result<()> {
+    if has_column(connection, "term_frequencies", "field")? {
+        return Ok(());
+    }
+    connection.execute(
+        "CREATE TABLE term_frequencies_fields (
+            url String NOT NULL,
+            field String NOT NULL,
+            term TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            frequency REAL NOT NULL,
+            positions BLOB NOT NULL,
+            PRIMARY KEY (url, field, term)
+        )",
+        params![],
+    )?;
+    connection.execute(
+        "INSERT INTO
+            term_frequencies_fields (url, field, term, count, frequency, positions)
+        SELECT
+            url, ?1, term, count, frequency, COALESCE(positions, X'')
+        FROM
+            term_frequencies
+        ",
+        params![Field::Body],
+    )?;
+    connection.execute("DROP TABLE term_frequencies", params![])?;
+    connection.execute(
+        "ALTER TABLE term_frequencies_fields RENAME TO term_frequencies",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Ranking moved from tf-idf scores computed after crawling to BM25 at query time, which needs
+/// the length of every field and statistics for the collection kept alongside the postings.
+/// They're worked out from the postings already there.
+fn add_stats(connection: &Connection) -> Result<()> {
+    if !has_table(connection, "field_lengths")? {
+        field_lengths::create_table(connection)?;
+        connection.execute(
+            "INSERT INTO
+                field_lengths (url, field, length)
+            SELECT
+                url, field, SUM(count)
+            FROM
+                term_frequencies
+            GROUP BY
+                url, field
+            ",
+            params![],
+        )?;
+    }
+    if !has_table(connection, "term_stats")? {
+        term_stats::create_table(connection)?;
+        field_stats::create_table(connection)?;
+        collection_stats::create_table(connection)?;
+        term_stats::rebuild(connection)?;
+        field_stats::rebuild(connection)?;
+        collection_stats::rebuild(connection)?;
+    }
+    connection.execute("DROP TABLE IF EXISTS tf_idf", params![])?;
+    Ok(())
+}
+
+/// The tables added alongside links, documents, votes, spelling corrections, suggestions,
+/// synonyms, near duplicates and canonical urls, which start out empty. The spelling index is
+/// worked out from the vocabulary already there.
+fn add_tables(connection: &Connection) -> Result<()> {
+    let tables: [(&str, Migration); 8] = [
+        ("links", links::create_table),
+        ("documents", documents::create_table),
+        ("votes", votes::create_table),
+        ("spelling_deletions", spelling::create_table),
+        ("queries", queries::create_table),
+        ("synonyms", synonyms::create_table),
+        ("fingerprints", fingerprints::create_table),
+        ("canonicals", canonicals::create_table),
+    ];
+    for (table, create_table) in tables {
+        if has_table(connection, table)? {
+            continue;
+        }
+        create_table(connection)?;
+        if table == "spelling_deletions" {
+            spelling::rebuild(connection)?;
+        }
+    }
+    Ok(())
+}
+
+/// Urls were stored as they were written, so the same page could be crawled under several of
+/// them. Merges their crawls into one for the normalized url, which keeps the crawl time of a
+/// crawl already under it and is otherwise crawled again. Pages indexed under another spelling
+/// of their url are taken out of the index, and links, votes and canonical urls move over to the
+/// normalized url.
+///
+/// Written in SQL against the tables as they are at this point rather than with the functions
+/// which keep them, those follow the tables as they change in later versions.
+fn normalize_urls(connection: &Connection) -> Result<()> {
+    let normalizer = UrlNormalizer::load(connection)?;
+    let analyzer = Analyzer::load(connection)?;
+    let normalized = |url: &str| match normalizer.normalize(url) {
+        Ok(n) if n != url => Some(n),
+        _ => None,
+    };
+
+    let mut statement = connection.prepare("SELECT url, last_updated FROM crawls")?;
+    let rows: Vec<(String, i64)> = statement
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .flatten()
+        .collect();
+
+    // The pages whose anchor text has to be indexed again at the end
+    let mut linked = BTreeSet::new();
+
+    let mut merged: BTreeMap<String, i64> = BTreeMap::new();
+    for (url, last_updated) in &rows {
+        match normalized(url) {
+            Some(n) => {
+                connection.execute("DELETE FROM crawls WHERE url = ?1", params![url])?;
+                linked.extend(unindex(connection, url)?);
+                merged.entry(n).or_insert(-1);
+            }
+            None => {
+                merged.insert(url.clone(), *last_updated);
+            }
+        }
+    }
+    for (url, last_updated) in merged {
+        connection.execute(
+            "INSERT INTO
+                crawls (url, status, last_updated)
+            VALUES
+                (?1, ?2, ?3)
+            ON CONFLICT
+                (url)
+            DO UPDATE
+            SET
+                last_updated = ?3
+            ",
+            params![url, crawls::Status::Ready, last_updated],
+        )?;
+    }
+
+    let mut statement = connection.prepare("SELECT DISTINCT target FROM links")?;
+    let targets: Vec<String> = statement
+        .query_map(params![], |row| row.get(0))?
+        .flatten()
+        .collect();
+    for target in targets {
+        let Some(n) = normalized(&target) else {
+            continue;
+        };
+        connection.execute(
+            "UPDATE OR IGNORE links SET target = ?2 WHERE target = ?1",
+            params![target, n],
+        )?;
+        connection.execute("DELETE FROM links WHERE target = ?1", params![target])?;
+        // Pages only known from links have nothing but their anchor text indexed
+        unindex(connection, &target)?;
+        linked.insert(n);
+    }
+
+    for table in ["votes", "canonicals"] {
+        let mut statement = connection.prepare(&format!("SELECT DISTINCT url FROM {}", table))?;
+        let urls: Vec<String> = statement
+            .query_map(params![], |row| row.get(0))?
+            .flatten()
+            .collect();
+        for url in urls {
+            let Some(n) = normalized(&url) else {
+                continue;
+            };
+            connection.execute(
+                &format!("UPDATE OR IGNORE {} SET url = ?2 WHERE url = ?1", table),
+                params![url, n],
+            )?;
+            connection.execute(&format!("DELETE FROM {} WHERE url = ?1", table), params![
+                url
+            ])?;
+        }
+    }
+    let mut statement = connection.prepare("SELECT DISTINCT canonical FROM canonicals")?;
+    let canonicals: Vec<String> = statement
+        .query_map(params![], |row| row.get(0))?
+        .flatten()
+        .collect();
+    for canonical in canonicals {
+        if let Some(n) = normalized(&canonical) {
+            connection.execute(
+                "UPDATE canonicals SET canonical = ?2 WHERE canonical = ?1",
+                params![canonical, n],
+            )?;
+        }
+    }
+
+    for url in linked {
+        let url = normalized(&url).unwrap_or(url);
+        index_anchor_text(connection, &analyzer, &url)?;
+    }
+
+    // Rather than keeping the statistics up to date through all of the above
+    term_stats::rebuild(connection)?;
+    field_stats::rebuild(connection)?;
+    collection_stats::rebuild(connection)?;
+    spelling::rebuild(connection)
+}
+
+/// Takes everything indexed under a url out of the index, for `normalize_urls`. Returns the pages
+/// it linked to.
+fn unindex(connection: &Connection, url: &str) -> Result<Vec<String>> {
+    let mut statement =
+        connection.prepare("SELECT DISTINCT target FROM links WHERE source = ?1")?;
+    let targets: Vec<String> = statement
+        .query_map(params![url], |row| row.get(0))?
+        .flatten()
+        .collect();
+    for table in [
+        "term_frequencies",
+        "field_lengths",
+        "documents",
+        "fingerprints",
+        "canonicals",
+    ] {
+        connection.execute(&format!("DELETE FROM {} WHERE url = ?1", table), params![
+            url
+        ])?;
+    }
+    connection.execute("DELETE FROM links WHERE source = ?1", params![url])?;
+    Ok(targets)
+}
+
+/// Indexes the text of the links to a page, and to its copies, as its anchor field, for
+/// `normalize_urls`. Links to a copy count towards its canonical page. The collection statistics
+/// are left to be rebuilt afterwards.
+fn index_anchor_text(connection: &Connection, analyzer: &Analyzer, url: &str) -> Result<()> {
+    let canonical: Option<String> = connection
+        .query_row(
+            "SELECT canonical FROM canonicals WHERE url = ?1",
+            params![url],
+            |row| row.get(0),
+        )
+        .optional()?;
+    delete_field(connection, url, Field::Anchor)?;
+    let url = match &canonical {
+        Some(c) => {
+            delete_field(connection, c, Field::Anchor)?;
+            c.as_str()
+        }
+        None => url,
+    };
+
+    let mut statement = connection.prepare(
+        "SELECT
+            text
+        FROM
+            links
+        WHERE
+            target = ?1 OR
+            target IN (SELECT url FROM canonicals WHERE canonical = ?1)
+        ",
+    )?;
+    let text: Vec<String> = statement
+        .query_map(params![url], |row| row.get(0))?
+        .flatten()
+        .collect();
+    let terms = crawl::analyze_terms(
+        analyzer,
+        Field::Anchor,
+        &mut text.join("\n").as_bytes(),
+        url,
+    )?;
+
+    for t in &terms {
+        connection.execute(
+            "INSERT INTO
+                term_frequencies (url, field, term, count, frequency, positions)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6)
+            ",
+            params![
+                t.url,
+                t.field,
+                t.term,
+                t.count,
+                t.frequency,
+                term_frequencies::encode_positions(&t.positions)
+            ],
+        )?;
+    }
+    let length: u64 = terms.iter().map(|t| t.count).sum();
+    if length > 0 {
+        connection.execute(
+            "INSERT INTO field_lengths (url, field, length) VALUES (?1, ?2, ?3)",
+            params![url, Field::Anchor, length],
+        )?;
+    }
+    Ok(())
+}
+
+fn delete_field(connection: &Connection, url: &str, field: Field) -> Result<()> {
+    for table in ["term_frequencies", "field_lengths"] {
+        connection.execute(
+            &format!("DELETE FROM {} WHERE url = ?1 AND field = ?2", table),
+            params![url, field],
+        )?;
+    }
+    Ok(())
+}
+
+/// Crawls got the `lastmod` and `changefreq` of their sitemap entries, and sites can have their
+/// sitemaps read.
+fn add_crawl_schedule(connection: &Connection) -> Result<()> {
+    for (column, definition) in [("last_modified", "INTEGER"), ("change_frequency", "STRING")] {
+        if !has_column(connection, "crawls", column)? {
+            connection.execute(
+                &format!("ALTER TABLE crawls ADD COLUMN {} {}", column, definition),
+                params![],
+            )?;
+        }
+    }
+    if !has_table(connection, "sitemaps")? {
+        sitemaps::create_table(connection)?;
+    }
+    Ok(())
+}
+
+/// Feeds can be subscribed to.
+fn add_feeds(connection: &Connection) -> Result<()> {
+    if !has_table(connection, "feeds")? {
+        feeds::create_table(connection)?;
+    }
+    Ok(())
+}
+
+/// Pages too short to fingerprint were fingerprinted all the same, which put unrelated ones in
+/// the same cluster.
+fn remove_short_fingerprints(connection: &Connection) -> Result<()> {
+    if !has_table(connection, "fingerprints")? || !has_table(connection, "field_lengths")? {
+        return Ok(());
+    }
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            fingerprints.url
+        FROM
+            fingerprints
+        LEFT JOIN
+            field_lengths ON field_lengths.url = fingerprints.url AND field_lengths.field = ?1
+        WHERE
+            IFNULL(field_lengths.length, 0) < ?2
+        ",
+    )?;
+    let urls: Vec<String> = statement
+        .query_map(params![Field::Body, simhash::MIN_WORDS], |row| row.get(0))?
+        .flatten()
+        .collect();
+    for url in urls {
+        fingerprints::remove(connection, &url)?;
+    }
+    Ok(())
+}
+
+/// Entries of feeds were only recorded for the first feed listing them, they're kept per feed
+/// now and marked when what's indexed under them came from the feed, which is part of the key
+/// so the table is built again. Entries never crawled with something indexed were stubs.
+fn key_feed_items_by_feed(connection: &Connection) -> Result<()> {
+    if !has_table(connection, "feed_items")? || has_column(connection, "feed_items", "stub")? {
+        return Ok(());
+    }
+    connection.execute("DROP INDEX IF EXISTS feed_items_feed", params![])?;
+    connection.execute(
+        "ALTER TABLE feed_items RENAME TO feed_items_by_url",
+        params![],
+    )?;
+    feeds::create_items_table(connection)?;
+    connection.execute(
+        "INSERT INTO
+            feed_items (url, feed, stub)
+        SELECT
+            url,
+            feed,
+            EXISTS (SELECT 1 FROM documents WHERE url = feed_items_by_url.url) AND
+                EXISTS (
+                    SELECT 1 FROM crawls WHERE url = feed_items_by_url.url AND last_updated = -1
+                )
+        FROM
+            feed_items_by_url
+        ",
+        params![],
+    )?;
+    connection.execute("DROP TABLE feed_items_by_url", params![])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::search::{self, SearchOptions};
+    use crate::analysis::html::Anchor;
+    use crate::db;
+
+    #[test]
+    fn test_normalize_urls() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let analyzer = Analyzer::default();
+
+        // As they would have been stored before urls were normalized
+        for (url, last_updated) in [
+            ("http://example.com/a", 100),
+            ("http://Example.com/a#x", 200),
+            ("http://example.com/a/?utm_source=feed", 300),
+            ("http://example.com/b/", 400),
+        ] {
+            crawls::insert(&connection, &crawls::Crawl {
+                url: url.to_string(),
+                status: crawls::Status::Ready,
+                last_updated,
+                last_modified: None,
+                change_frequency: None,
+            })
+            .unwrap();
+        }
+        let metadata = documents::Metadata::new("http://example.com/b/", "text/html", None, 400);
+        documents::set_metadata(&connection, &metadata).unwrap();
+        crawl::update_links(&connection, &analyzer, "http://example.com/a", &[Anchor {
+            href: "http://example.com/b/".to_string(),
+            text: "bravo".to_string(),
+        }])
+        .unwrap();
+        votes::set(&connection, "http://example.com/b/", "alice", 1).unwrap();
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(MIGRATIONS.len(), version(&connection).unwrap());
+
+        let mut statement = connection
+            .prepare("SELECT url, last_updated FROM crawls ORDER BY url")
+            .unwrap();
+        let rows: Vec<(String, i64)> = statement
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .flatten()
+            .collect();
+        drop(statement);
+        assert_eq!(
+            vec![
+                ("http://example.com/a".to_string(), 100),
+                ("http://example.com/b".to_string(), -1),
+            ],
+            rows
+        );
+        assert!(
+            documents::get_metadata(&connection, "http://example.com/b/")
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            vec!["bravo"],
+            links::get_by_target(&connection, "http://example.com/b")
+                .unwrap()
+                .into_iter()
+                .map(|l| l.text)
+                .collect::<Vec<_>>()
+        );
+        assert!(
+            term_frequencies::get(&connection, "http://example.com/b", Field::Anchor, "bravo")
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            term_frequencies::get(&connection, "http://example.com/b/", Field::Anchor, "bravo")
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            1,
+            votes::get_total(&connection, "http://example.com/b").unwrap()
+        );
+
+        // Already up to date, nothing runs again
+        migrate(&mut connection).unwrap();
+    }
+
+    /// An index made before any of the migrations, with the tables it had then.
+    #[test]
+    fn test_migrate_baseline() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        for sql in [
+            "CREATE TABLE crawls (
+                url STRING NOT NULL,
+                status STRING NOT NULL,
+                last_updated INTEGER NOT NULL,
+                PRIMARY KEY (url)
+            )",
+            "CREATE TABLE term_frequencies (
+                url String NOT NULL,
+                term String NOT NULL,
+                count INTEGER NOT NULL,
+                frequency REAL NOT NULL,
+                PRIMARY KEY (url, term)
+            )",
+            "CREATE TABLE tf_idf (
+                url STRING NOT NULL,
+                term STRING NOT NULL,
+                score REAL NOT NULL,
+                PRIMARY KEY (url, term)
+            )",
+            "CREATE TABLE users (
+                username STRING NOT NULL,
+                password_hash STRING NOT NULL,
+                PRIMARY KEY (username)
+            )",
+            "INSERT INTO crawls (url, status, last_updated) VALUES
+                ('http://example.com/bread', 'ready', 100),
+                ('http://example.com/cake/', 'ready', 200)",
+            "INSERT INTO term_frequencies (url, term, count, frequency) VALUES
+                ('http://example.com/bread', 'sourdough', 2, 0.5),
+                ('http://example.com/bread', 'café', 2, 0.5),
+                ('http://example.com/cake/', 'sourdough', 1, 1.0)",
+            "INSERT INTO tf_idf (url, term, score) VALUES ('http://example.com/bread', 'sourdough', 0.1)",
+        ] {
+            connection.execute(sql, params![]).unwrap();
+        }
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(MIGRATIONS.len(), version(&connection).unwrap());
+        assert!(!has_table(&connection, "tf_idf").unwrap());
+
+        let urls = |query: &str| -> Vec<String> {
+            search::execute(&connection, &[query.to_string()], &SearchOptions::default())
+                .unwrap()
+                .documents
+                .into_iter()
+                .map(|d| d.url)
+                .collect()
+        };
+        // Indexed under a url which isn't normalized, it waits to be crawled again
+        assert_eq!(vec!["http://example.com/bread"], urls("sourdough"));
+        assert_eq!(
+            -1,
+            crawls::get(&connection, "http://example.com/cake")
+                .unwrap()
+                .unwrap()
+                .last_updated
+        );
+        // Terms weren't accent folded
+        assert_eq!(vec!["http://example.com/bread"], urls("café"));
+        assert!(urls("cafe").is_empty());
+        // Without positions a phrase matches documents with all of its terms
+        assert_eq!(vec!["http://example.com/bread"], urls("\"sourdough café\""));
+    }
+
+    #[test]
+    fn test_add_fields() {
+        let connection = Connection::open_in_memory().unwrap();
+        // The term_frequencies table as it was before positions and fields
+        connection
+            .execute(
+                "CREATE TABLE term_frequencies (
+                    url String NOT NULL,
+                    term String NOT NULL,
+                    count INTEGER NOT NULL,
+                    frequency REAL NOT NULL,
+                    PRIMARY KEY (url, term)
+                )",
+                params![],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO term_frequencies (url, term, count, frequency) VALUES ('http://example.com/', 'alpha', 2, 0.5)",
+                params![],
+            )
+            .unwrap();
+
+        add_positions(&connection).unwrap();
+        add_fields(&connection).unwrap();
+        // Nothing to do the second time
+        add_positions(&connection).unwrap();
+        add_fields(&connection).unwrap();
+
+        assert_eq!(
+            Some(term_frequencies::TermFrequency::new(
+                "http://example.com/",
+                Field::Body,
+                "alpha",
+                2,
+                0.5,
+                vec![]
+            )),
+            term_frequencies::get(&connection, "http://example.com/", Field::Body, "alpha")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_add_stats() {
+        let connection = Connection::open_in_memory().unwrap();
+        term_frequencies::create_table(&connection).unwrap();
+        for (url, term, count) in [
+            ("http://a.example.com/", "alpha", 2),
+            ("http://a.example.com/", "bravo", 1),
+            ("http://b.example.com/", "alpha", 3),
+        ] {
+            term_frequencies::insert(
+                &connection,
+                &term_frequencies::TermFrequency::new(url, Field::Body, term, count, 0.5, vec![]),
+            )
+            .unwrap();
+        }
+
+        add_stats(&connection).unwrap();
+        add_stats(&connection).unwrap();
+
+        assert_eq!(
+            2,
+            collection_stats::get_document_count(&connection).unwrap()
+        );
+        assert_eq!(
+            2,
+            term_stats::get(&connection, Field::Body, "alpha").unwrap()
+        );
+        assert_eq!(
+            Some(3),
+            field_lengths::get(&connection, "http://a.example.com/", Field::Body).unwrap()
+        );
+        assert_eq!(
+            Some(&3.0),
+            field_stats::get_average_lengths(&connection)
+                .unwrap()
+                .get(&Field::Body)
+        );
+    }
+
+    #[test]
+    fn test_add_crawl_schedule() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        // The crawls table as it was before sitemaps
+        connection
+            .execute(
+                "CREATE TABLE crawls (
+                    url STRING NOT NULL,
+                    status STRING NOT NULL,
+                    last_updated INTEGER NOT NULL,
+                    PRIMARY KEY (url)
+                )",
+                params![],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO crawls (url, status, last_updated) VALUES ('http://example.com/', 'ready', 100)",
+                params![],
+            )
+            .unwrap();
+        set_version(&connection, 6).unwrap();
+
+        migrate(&mut connection).unwrap();
+
+        let crawl = crawls::get(&connection, "http://example.com/")
+            .unwrap()
+            .unwrap();
+        assert_eq!(100, crawl.last_updated);
+        assert_eq!(None, crawl.last_modified);
+        assert_eq!(None, crawl.change_frequency);
+        sitemaps::insert(&connection, "http://example.com/").unwrap();
+        assert_eq!(
+            vec!["http://example.com/"],
+            sitemaps::get_all_needing_update(&connection).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remove_short_fingerprints() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let long = "word ".repeat(simhash::MIN_WORDS);
+        documents::index(&connection, "http://long.com/", Field::Body, &long);
+        documents::index(
+            &connection,
+            "http://short.com/",
+            Field::Body,
+            "page not found",
+        );
+        for url in ["http://long.com/", "http://short.com/", "http://gone.com/"] {
+            fingerprints::set(&connection, url, 0x0123_4567_89ab_cdef).unwrap();
+        }
+
+        remove_short_fingerprints(&connection).unwrap();
+        assert!(
+            fingerprints::get(&connection, "http://long.com/")
+                .unwrap()
+                .is_some()
+        );
+        for url in ["http://short.com/", "http://gone.com/"] {
+            assert_eq!(None, fingerprints::get(&connection, url).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_key_feed_items_by_feed() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        // The table as it was keyed by url
+        connection
+            .execute_batch(
+                "DROP TABLE feed_items;
+                CREATE TABLE feed_items (
+                    url TEXT NOT NULL,
+                    feed TEXT NOT NULL,
+                    PRIMARY KEY (url)
+                );
+                CREATE INDEX feed_items_feed ON feed_items (feed);
+                INSERT INTO feed_items (url, feed) VALUES
+                    ('https://example.com/stub', 'https://example.com/feed'),
+                    ('https://example.com/crawled', 'https://example.com/feed');",
+            )
+            .unwrap();
+        for (url, last_updated) in [
+            ("https://example.com/stub", -1),
+            ("https://example.com/crawled", 100),
+        ] {
+            crawls::insert(&connection, &crawls::Crawl {
+                url: url.to_string(),
+                status: crawls::Status::Ready,
+                last_updated,
+                last_modified: None,
+                change_frequency: None,
+            })
+            .unwrap();
+            documents::set_metadata(
+                &connection,
+                &documents::Metadata::new(url, "text/html", None, 1),
+            )
+            .unwrap();
+        }
+
+        key_feed_items_by_feed(&connection).unwrap();
+        key_feed_items_by_feed(&connection).unwrap();
+        assert!(feeds::is_stub(&connection, "https://example.com/stub").unwrap());
+        assert!(!feeds::is_stub(&connection, "https://example.com/crawled").unwrap());
+
+        // Another feed listing the same entry is kept too
+        feeds::add_item(
+            &connection,
+            "https://other.com/feed",
+            "https://example.com/stub",
+        )
+        .unwrap();
+        let count: i64 = connection
+            .query_row("SELECT COUNT(*) FROM feed_items", params![], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(3, count);
+    }
+}
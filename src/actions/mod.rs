@@ -1,6 +1,15 @@
 pub mod add_url;
 pub mod add_user;
 pub mod crawl;
+pub mod evaluate;
+pub mod facets;
+pub mod feeds;
 pub mod index;
 pub mod init;
+pub mod migrate;
+pub mod related;
 pub mod search;
+pub mod set;
+pub mod sitemaps;
+pub mod suggest;
+pub mod synonyms;
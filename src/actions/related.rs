@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, anyhow};
+use rusqlite::Connection;
+
+use crate::actions::search::Document;
+use crate::analysis::fields::{Field, FieldBoosts};
+use crate::analysis::urls::UrlNormalizer;
+use crate::db::{collection_stats, fingerprints, term_frequencies, term_stats};
+use crate::ranking::tf_idf;
+
+/// The most distinctive terms of the document looked for in others.
+static MAX_TERMS: usize = 25;
+
+/// How many candidates per result are compared in full, the ones sharing the most weight in the
+/// document's distinctive terms.
+static CANDIDATES_PER_RESULT: usize = 5;
+
+/// Documents at least this similar are copies of the document rather than related to it.
+static NEAR_DUPLICATE_SIMILARITY: f64 = 0.95;
+
+/// A document's terms weighted by tf-idf, summed over its fields by their boosts.
+type Vector = HashMap<String, f64>;
+
+struct Vectors<'a> {
+    connection: &'a Connection,
+    document_count: u64,
+    boosts: FieldBoosts,
+}
+
+impl Vectors<'_> {
+    fn get(&self, url: &str) -> Result<Vector> {
+        let mut fields = Vec::with_capacity(Field::ALL.len());
+        for field in Field::ALL {
+            fields.push((
+                field,
+                term_frequencies::get_by_url_and_field(self.connection, url, field)?,
+            ));
+        }
+        let terms: HashSet<&str> = fields
+            .iter()
+            .flat_map(|(_, tfs)| tfs.iter().map(|tf| tf.term.as_str()))
+            .collect();
+        let terms: Vec<&str> = terms.into_iter().collect();
+        let document_frequencies = term_stats::get_by_terms(self.connection, &terms)?;
+
+        let mut vector = Vector::new();
+        for (field, tfs) in fields {
+            for tf in tfs {
+                let df = document_frequencies
+                    .get(&(field, tf.term.clone()))
+                    .copied()
+                    .unwrap_or(0);
+                let weight = tf_idf::score(tf.frequency, df, self.document_count);
+                *vector.entry(tf.term).or_default() += weight * self.boosts.get(field);
+            }
+        }
+        Ok(vector)
+    }
+}
+
+fn norm(vector: &Vector) -> f64 {
+    vector.values().map(|w| w * w).sum::<f64>().sqrt()
+}
+
+fn cosine(a: &Vector, b: &Vector) -> f64 {
+    let dot: f64 = a.iter().filter_map(|(t, w)| b.get(t).map(|v| w * v)).sum();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Documents similar to one already in the index, scored by the cosine similarity of their
+/// tf-idf vectors. Candidates are the documents containing its most distinctive terms, the
+/// document itself and copies of it are left out. Nothing if the document isn't in the index.
+pub fn find(connection: &Connection, url: &str, limit: usize) -> Result<Option<Vec<Document>>> {
+    let url = &UrlNormalizer::load(connection)?.lookup(url);
+    let vectors = Vectors {
+        connection,
+        document_count: collection_stats::get_document_count(connection)?,
+        boosts: FieldBoosts::load(connection)?,
+    };
+    let source = vectors.get(url)?;
+    if source.is_empty() {
+        return Ok(None);
+    }
+
+    let mut terms: Vec<(&String, &f64)> = source.iter().filter(|(_, w)| **w > 0.0).collect();
+    terms.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    terms.truncate(MAX_TERMS);
+
+    // Copies found when crawling are left out along with the document itself
+    let mut duplicates = vec![url.to_string()];
+    if let Some(f) = fingerprints::get(connection, url)? {
+        duplicates.extend(
+            fingerprints::get_by_cluster(connection, &f.cluster)?
+                .into_iter()
+                .map(|f| f.url),
+        );
+    }
+
+    // Scored by what they share of the distinctive terms, only the best are compared in full
+    let mut shared: HashMap<String, f64> = HashMap::new();
+    for (term, weight) in terms {
+        for tf in term_frequencies::get_by_term(connection, term)? {
+            if !duplicates.contains(&tf.url) {
+                *shared.entry(tf.url).or_default() += weight * tf.frequency;
+            }
+        }
+    }
+    let mut candidates: Vec<(String, f64)> = shared.into_iter().collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    candidates.truncate(limit.saturating_mul(CANDIDATES_PER_RESULT));
+
+    let mut related = Vec::with_capacity(candidates.len());
+    for (candidate, _) in candidates {
+        let similarity = cosine(&source, &vectors.get(&candidate)?);
+        if similarity > 0.0 && similarity < NEAR_DUPLICATE_SIMILARITY {
+            related.push(Document::new(&candidate, similarity));
+        }
+    }
+    related.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.url.cmp(&b.url)));
+    related.truncate(limit);
+    Ok(Some(related))
+}
+
+pub fn related(connection: &Connection, url: &str, limit: usize) -> Result<()> {
+    let documents =
+        find(connection, url, limit)?.ok_or_else(|| anyhow!("Not in the index: {}", url))?;
+    for document in documents {
+        println!("\t{}\t{}", document.url, document.score);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::db::documents::index;
+
+    #[test]
+    fn test_find() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://rust.com/",
+            Field::Body,
+            "rust borrow checker lifetimes",
+        );
+        index(
+            &connection,
+            "http://copy.com/",
+            Field::Body,
+            "rust borrow checker lifetimes",
+        );
+        index(
+            &connection,
+            "http://close.com/",
+            Field::Body,
+            "rust borrow checker traits",
+        );
+        index(
+            &connection,
+            "http://far.com/",
+            Field::Body,
+            "rust game servers",
+        );
+        index(
+            &connection,
+            "http://other.com/",
+            Field::Body,
+            "gardening tomatoes",
+        );
+
+        let related = find(&connection, "http://rust.com/", 10).unwrap().unwrap();
+        assert_eq!(
+            vec!["http://close.com/", "http://far.com/"],
+            related.iter().map(|d| d.url.as_str()).collect::<Vec<_>>()
+        );
+        assert!(related[0].score > related[1].score);
+
+        let related = find(&connection, "http://rust.com/", 1).unwrap().unwrap();
+        assert_eq!(1, related.len());
+
+        assert_eq!(None, find(&connection, "http://missing.com/", 10).unwrap());
+    }
+}
@@ -1,15 +1,52 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use chrono::Utc;
 use rusqlite::Connection;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::db::tf_idf;
+use crate::actions::crawl;
+use crate::actions::facets::{Facets, site};
+use crate::analysis::Analyzer;
+use crate::analysis::fields::{Field, FieldBoosts};
+use crate::analysis::snippets::{self, Snippet};
+use crate::db::documents::{self, Metadata};
+use crate::db::{
+    collection_stats, field_lengths, field_stats, fingerprints, settings, spelling,
+    term_frequencies, term_stats,
+};
+use crate::query::synonyms::Synonyms;
+use crate::query::{self, Filter, Query};
+use crate::ranking::bm25::Bm25;
+use crate::ranking::explanation::Explanation;
+use crate::ranking::features::{DomainTrust, Features};
+use crate::ranking::ranker::{Candidate, LinearRanker, Ranker};
+use crate::ranking::{RankingFunction, tf_idf};
 
 #[derive(Serialize, Debug, PartialEq)]
 pub struct Document {
     pub url: String,
     pub score: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub snippets: Vec<Snippet>,
+    /// How many results from the same site were pushed back to later pages to make room for
+    /// other sites, only set on the site's best result on the page.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub more_from_site: usize,
+    /// How many near duplicates of this result were left out, mirrors and other copies of it.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub similar_pages: usize,
+    /// How the score was arrived at, when asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 impl Document {
@@ -17,44 +54,1161 @@ impl Document {
         Self {
             url: url.to_string(),
             score,
+            snippets: vec![],
+            more_from_site: 0,
+            similar_pages: 0,
+            explanation: None,
         }
     }
 }
 
-pub fn execute(connection: &Connection, terms: &Vec<String>) -> Result<Vec<Document>> {
-    // a map of (url, term) pairs to a score
-    // each subsequent term adds a smaller amount
-    // to the total score.
-    let mut scored_results: HashMap<String, f64> = HashMap::new();
+static MIN_RESULTS_KEY: &str = "search.min_results";
 
-    for (i, t) in terms.iter().map(|t| t.to_lowercase()).enumerate() {
-        let top = tf_idf::get_top_by_term(connection, &t, 100)?;
-        for e in top {
-            let key = e.url;
-            if let Some(score) = scored_results.get_mut(&key) {
-                let scale: f64 = 1.0 / i as f64;
-                *score += e.score * scale;
-                //scored_results.insert((e.url, e.term), score + (e.score * scale));
-            } else {
-                scored_results.insert(key, e.score);
+/// Results from a single site shown before the rest are left out, unless a search says otherwise.
+pub static DEFAULT_PER_SITE: usize = 2;
+
+/// The most snippets shown with a single result.
+static MAX_SNIPPETS: usize = 3;
+
+/// The most terms a prefix or fuzzy term expands to, the ones in the most documents are kept.
+static MAX_EXPANSIONS: usize = 50;
+
+/// How much a term matched by a prefix, or by each edit of a fuzzy term, counts for compared to
+/// typing it out in full.
+static EXPANSION_WEIGHT: f64 = 0.5;
+
+/// Fewer than a page of results.
+static DEFAULT_MIN_RESULTS: usize = 10;
+
+/// How the parts of a query combine.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    /// Documents have to match every part of the query, unless that finds fewer than
+    /// `search.min_results` in which case partial matches are let back in.
+    #[default]
+    And,
+    /// Documents matching any part of the query.
+    Or,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 2] = [Operator::And, Operator::Or];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::And => "and",
+            Operator::Or => "or",
+        }
+    }
+}
+
+impl TryFrom<&str> for Operator {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, std::string::String> {
+        Operator::ALL
+            .into_iter()
+            .find(|o| o.as_str() == value)
+            .ok_or(format!("Invalid operator: {}", value))
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct SearchOptions {
+    /// Overrides the index's default ranking function.
+    pub ranking: Option<RankingFunction>,
+    #[serde(default)]
+    pub operator: Operator,
+    /// How many of the best results to skip over.
+    #[serde(default)]
+    pub offset: usize,
+    /// How many results to return after the offset, all of them if unset.
+    pub limit: Option<usize>,
+    /// The index directory, when set the results on the page get snippets of the text stored
+    /// there at crawl time.
+    #[serde(skip)]
+    pub snippets_from: Option<PathBuf>,
+    /// The most results from any one site on each page of `limit` results, all of them if
+    /// unset. Searches restricted with `site:` always get every result.
+    pub per_site: Option<usize>,
+    /// Filters every result has to match on top of the query, `site:example.com` or any of the
+    /// other filters the query syntax has.
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Whether to count the results by site, language, content type and crawl date.
+    #[serde(default)]
+    pub facets: bool,
+    /// Whether to explain the score of each result on the page.
+    #[serde(default)]
+    pub explain: bool,
+}
+
+/// A page of results.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Results {
+    /// The number of results on every page, not just this one. Documents left out for coming
+    /// from a site with too many results aren't counted.
+    pub total: usize,
+    pub documents: Vec<Document>,
+    /// The query with rare words replaced by more common ones close to them, "did you mean".
+    pub suggestion: Option<String>,
+    /// Counts of every matching document, not just this page, when asked for.
+    pub facets: Option<Facets>,
+}
+
+/// The score of a single field of a document.
+struct Hit {
+    url: String,
+    field: Field,
+    score: f64,
+}
+
+/// Everything needed to score terms, loaded once per query.
+enum Scorer {
+    TfIdf {
+        document_count: u64,
+    },
+    Bm25 {
+        bm25: Bm25,
+        document_count: u64,
+        average_lengths: HashMap<Field, f64>,
+    },
+}
+
+impl Scorer {
+    fn load(connection: &Connection, ranking: RankingFunction) -> Result<Self> {
+        let document_count = collection_stats::get_document_count(connection)?;
+        match ranking {
+            RankingFunction::TfIdf => Ok(Scorer::TfIdf { document_count }),
+            RankingFunction::Bm25 => Ok(Scorer::Bm25 {
+                bm25: Bm25::load(connection)?,
+                document_count,
+                average_lengths: field_stats::get_average_lengths(connection)?,
+            }),
+        }
+    }
+
+    /// Scores a term in one field of a document given the length of the field and the number of
+    /// documents containing the term in it.
+    fn score(&self, tf: &term_frequencies::TermFrequency, df: u64, length: u64) -> f64 {
+        match self {
+            Scorer::TfIdf { document_count } => tf_idf::score(tf.frequency, df, *document_count),
+            Scorer::Bm25 {
+                bm25,
+                document_count,
+                average_lengths,
+            } => bm25.score(
+                tf.count,
+                df,
+                *document_count,
+                length,
+                average_lengths.get(&tf.field).copied().unwrap_or(0.0),
+            ),
+        }
+    }
+
+    /// The same as `score` along with the values that went into it.
+    fn explain(&self, tf: &term_frequencies::TermFrequency, df: u64, length: u64) -> Explanation {
+        let score = self.score(tf, df, length);
+        match self {
+            Scorer::TfIdf { document_count } => Explanation::with_details(
+                score,
+                &format!("tf-idf of \"{}\", frequency × idf", tf.term),
+                vec![
+                    Explanation::new(tf.frequency, "frequency"),
+                    Explanation::with_details(
+                        tf_idf::idf(*document_count, df),
+                        "idf, log10(documents / df)",
+                        vec![
+                            Explanation::new(*document_count as f64, "documents"),
+                            Explanation::new(df as f64, "df"),
+                        ],
+                    ),
+                ],
+            ),
+            Scorer::Bm25 {
+                bm25,
+                document_count,
+                average_lengths,
+            } => Explanation::with_details(score, &format!("bm25 of \"{}\"", tf.term), vec![
+                Explanation::new(tf.count as f64, "tf"),
+                Explanation::with_details(
+                    Bm25::idf(*document_count, df),
+                    "idf, ln(1 + (documents - df + 0.5) / (df + 0.5))",
+                    vec![
+                        Explanation::new(*document_count as f64, "documents"),
+                        Explanation::new(df as f64, "df"),
+                    ],
+                ),
+                Explanation::new(length as f64, "field length"),
+                Explanation::new(
+                    average_lengths.get(&tf.field).copied().unwrap_or(0.0),
+                    "average field length",
+                ),
+                Explanation::new(bm25.k1, "k1"),
+                Explanation::new(bm25.b, "b"),
+            ]),
+        }
+    }
+
+    /// Explains the score of a term in a single field of a single document.
+    fn explain_one(
+        &self,
+        connection: &Connection,
+        url: &str,
+        field: Field,
+        term: &str,
+    ) -> Result<Option<Explanation>> {
+        let Some(tf) = term_frequencies::get(connection, url, field, term)? else {
+            return Ok(None);
+        };
+        Ok(Some(self.explain(
+            &tf,
+            term_stats::get(connection, field, term)?,
+            field_lengths::get(connection, url, field)?.unwrap_or(0),
+        )))
+    }
+
+    /// Scores every field of every document containing the term.
+    fn score_term(
+        &self,
+        connection: &Connection,
+        field: Option<Field>,
+        term: &str,
+    ) -> Result<Vec<Hit>> {
+        let document_frequencies = term_stats::get_by_term(connection, term)?;
+        let lengths = field_lengths::get_by_term(connection, term)?;
+
+        Ok(term_frequencies::get_by_term(connection, term)?
+            .into_iter()
+            .filter(|tf| field.is_none_or(|f| f == tf.field))
+            .map(|tf| {
+                let df = document_frequencies.get(&tf.field).copied().unwrap_or(0);
+                let length = lengths
+                    .get(&(tf.url.clone(), tf.field))
+                    .copied()
+                    .unwrap_or(0);
+                let score = self.score(&tf, df, length);
+                Hit {
+                    url: tf.url,
+                    field: tf.field,
+                    score,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Counts the places where each list of positions continues on from the one before it, i.e. the
+/// number of times the terms appear next to each other in order.
+fn count_phrase_matches(positions: &[&[u32]]) -> usize {
+    let Some((first, rest)) = positions.split_first() else {
+        return 0;
+    };
+
+    first
+        .iter()
+        .filter(|p| {
+            rest.iter()
+                .enumerate()
+                .all(|(i, ps)| ps.binary_search(&(*p + i as u32 + 1)).is_ok())
+        })
+        .count()
+}
+
+/// Whether the terms appear next to each other in order. Terms indexed before positions were
+/// recorded have no phrase data, a document with all of them is given the benefit of the doubt.
+fn has_phrase(positions: &[&[u32]]) -> bool {
+    positions.iter().any(|ps| ps.is_empty()) || count_phrase_matches(positions) > 0
+}
+
+/// The length of the shortest window of the document which contains at least one position from
+/// every list.
+fn min_span(positions: &[&[u32]]) -> Option<u32> {
+    if positions.is_empty() || positions.iter().any(|ps| ps.is_empty()) {
+        return None;
+    }
+
+    let mut events: Vec<(u32, usize)> = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, ps)| ps.iter().map(move |p| (*p, i)))
+        .collect();
+    events.sort();
+
+    let mut counts = vec![0; positions.len()];
+    let mut covered = 0;
+    let mut start = 0;
+    let mut best: Option<u32> = None;
+
+    for end in 0..events.len() {
+        let (end_position, list) = events[end];
+        if counts[list] == 0 {
+            covered += 1;
+        }
+        counts[list] += 1;
+
+        while covered == positions.len() {
+            let (start_position, start_list) = events[start];
+            let span = end_position - start_position + 1;
+            best = Some(best.map_or(span, |b| b.min(span)));
+
+            counts[start_list] -= 1;
+            if counts[start_list] == 0 {
+                covered -= 1;
+            }
+            start += 1;
+        }
+    }
+
+    best
+}
+
+/// Combines the scores each document got for each clause of the query. Every clause counts
+/// equally, and a coordination factor scales the sum by the share of clauses the document
+/// matched so partial matches rank below complete ones.
+fn combine(
+    clause_results: &[HashMap<String, f64>],
+    operator: Operator,
+    min_results: usize,
+) -> HashMap<String, f64> {
+    let mut matches: HashMap<&str, (f64, usize)> = HashMap::new();
+    for results in clause_results {
+        for (url, score) in results {
+            let m = matches.entry(url).or_default();
+            m.0 += score;
+            m.1 += 1;
+        }
+    }
+
+    let clause_count = clause_results.len();
+    let complete = matches.values().filter(|(_, n)| *n == clause_count).count();
+    let required = match operator {
+        Operator::And if complete >= min_results => clause_count,
+        _ => 1,
+    };
+
+    matches
+        .into_iter()
+        .filter(|(_, (_, n))| *n >= required)
+        .map(|(url, (score, n))| (url.to_string(), score * n as f64 / clause_count as f64))
+        .collect()
+}
+
+/// The documents, and the fields within them, where the phrase appears.
+fn get_phrase_scores(
+    connection: &Connection,
+    scorer: &Scorer,
+    field: Option<Field>,
+    phrase: &[String],
+) -> Result<Vec<Hit>> {
+    let mut postings: Vec<HashMap<(String, Field), term_frequencies::TermFrequency>> = Vec::new();
+    let mut document_frequencies = Vec::new();
+    for t in phrase {
+        let by_url = term_frequencies::get_by_term(connection, t)?
+            .into_iter()
+            .filter(|tf| field.is_none_or(|f| f == tf.field))
+            .map(|tf| ((tf.url.clone(), tf.field), tf))
+            .collect();
+        postings.push(by_url);
+        document_frequencies.push(term_stats::get_by_term(connection, t)?);
+    }
+    // Every document with the phrase has its first term
+    let lengths = field_lengths::get_by_term(connection, &phrase[0])?;
+
+    let mut results = Vec::new();
+
+    for (url, field) in postings[0].keys() {
+        let key = (url.clone(), *field);
+        let Some(tfs) = postings
+            .iter()
+            .map(|p| p.get(&key))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+
+        let positions: Vec<&[u32]> = tfs.iter().map(|tf| tf.positions.as_slice()).collect();
+        if !has_phrase(&positions) {
+            continue;
+        }
+
+        let length = lengths.get(&key).copied().unwrap_or(0);
+        let score = tfs
+            .iter()
+            .zip(&document_frequencies)
+            .map(|(tf, dfs)| scorer.score(tf, dfs.get(field).copied().unwrap_or(0), length))
+            .sum();
+        results.push(Hit {
+            url: url.clone(),
+            field: *field,
+            score,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Where each of the terms appears in the body of every document containing it, read a term at a
+/// time rather than a document at a time.
+fn get_body_positions(
+    connection: &Connection,
+    terms: &BTreeSet<&str>,
+) -> Result<Vec<HashMap<String, Vec<u32>>>> {
+    let mut positions = Vec::with_capacity(terms.len());
+    for t in terms {
+        positions.push(
+            term_frequencies::get_by_term(connection, t)?
+                .into_iter()
+                .filter(|tf| tf.field == Field::Body)
+                .map(|tf| (tf.url, tf.positions))
+                .collect(),
+        );
+    }
+    Ok(positions)
+}
+
+/// Documents where the query terms appear close together in the body are more likely to be
+/// about the thing being searched for than documents where they're scattered about. The boost
+/// ranges from one, for terms that are far apart, up to two when they're adjacent.
+fn get_proximity_boost(body_positions: &[HashMap<String, Vec<u32>>], url: &str) -> f64 {
+    let matched: Vec<&[u32]> = body_positions
+        .iter()
+        .filter_map(|p| p.get(url))
+        .map(|ps| ps.as_slice())
+        .collect();
+
+    if matched.len() < 2 {
+        return 1.0;
+    }
+
+    match min_span(&matched) {
+        Some(span) => 1.0 + matched.len() as f64 / span as f64,
+        None => 1.0,
+    }
+}
+
+/// A document matching several alternatives only counts its best one, so a typo or a synonym
+/// doesn't score higher than the word itself.
+fn best_alternative(alternatives: Vec<(HashMap<String, f64>, f64)>) -> HashMap<String, f64> {
+    let mut results: HashMap<String, f64> = HashMap::new();
+    for (scores, weight) in alternatives {
+        for (url, score) in scores {
+            let best = results.entry(url).or_default();
+            *best = best.max(score * weight);
+        }
+    }
+    results
+}
+
+/// Decides whether a document is let through, built from the filters and exclusions of a query.
+enum Matcher {
+    Filter(Filter),
+    /// Documents matched by a part of the query that's scored.
+    Matched(HashSet<String>),
+    Not(Box<Matcher>),
+    All(Vec<Matcher>),
+    Any(Vec<Matcher>),
+}
+
+impl Matcher {
+    fn matches(&self, url: &str, metadata: Option<&Metadata>) -> bool {
+        match self {
+            Matcher::Filter(f) => f.matches(url, metadata),
+            Matcher::Matched(urls) => urls.contains(url),
+            Matcher::Not(m) => !m.matches(url, metadata),
+            Matcher::All(ms) => ms.iter().all(|m| m.matches(url, metadata)),
+            Matcher::Any(ms) => ms.iter().any(|m| m.matches(url, metadata)),
+        }
+    }
+}
+
+/// Everything needed to evaluate a query, loaded once per search.
+struct Evaluator<'a> {
+    connection: &'a Connection,
+    scorer: Scorer,
+    boosts: FieldBoosts,
+    operator: Operator,
+    min_results: usize,
+    /// The terms prefix and fuzzy terms expanded to, which count as query terms for proximity
+    /// and snippets.
+    expanded: RefCell<BTreeSet<String>>,
+    /// What each prefix and fuzzy term expanded to, by where it is in the query, so explaining
+    /// results doesn't look them up again.
+    expansions: RefCell<HashMap<*const Query, Vec<(String, f64)>>>,
+    /// Whether results are going to be explained, which needs the documents each group of the
+    /// query matched.
+    explaining: bool,
+    /// The documents each group matched, by where it is in the query.
+    group_matches: RefCell<HashMap<*const Query, HashSet<String>>>,
+}
+
+impl Evaluator<'_> {
+    /// The documents matching the query along with the scores of their text.
+    fn scores(&self, query: &Query) -> Result<HashMap<String, f64>> {
+        match query {
+            Query::Term(field, t) => {
+                Ok(self.boost(self.scorer.score_term(self.connection, *field, t)?))
+            }
+            Query::Phrase(field, p) => {
+                Ok(self.boost(get_phrase_scores(self.connection, &self.scorer, *field, p)?))
+            }
+            Query::Prefix(field, _) | Query::Fuzzy(field, ..) => {
+                let mut alternatives = Vec::new();
+                for (term, weight) in self.expand(query)? {
+                    let scores =
+                        self.boost(self.scorer.score_term(self.connection, *field, &term)?);
+                    alternatives.push((scores, weight));
+                    self.expanded.borrow_mut().insert(term);
+                }
+                Ok(best_alternative(alternatives))
+            }
+            Query::Alternatives(qs) => {
+                let mut alternatives = Vec::with_capacity(qs.len());
+                for (q, weight) in qs {
+                    alternatives.push((self.scores(q)?, *weight));
+                }
+                Ok(best_alternative(alternatives))
+            }
+            Query::Filter(_) | Query::Not(_) => {
+                let results = self.group(std::slice::from_ref(query), Operator::And, 0)?;
+                Ok(self.remember_matches(query, results))
+            }
+            // Explicit operators are strict, they never fall back on partial matches
+            Query::And(qs) => {
+                let results = self.group(qs, Operator::And, 0)?;
+                Ok(self.remember_matches(query, results))
+            }
+            Query::Or(qs) => {
+                let results = self.group(qs, Operator::Or, 0)?;
+                Ok(self.remember_matches(query, results))
+            }
+            Query::Implicit(qs) => {
+                let results = self.group(qs, self.operator, self.min_results)?;
+                Ok(self.remember_matches(query, results))
+            }
+        }
+    }
+
+    /// Keeps which documents a group matched for explaining results later.
+    fn remember_matches(
+        &self,
+        query: &Query,
+        results: HashMap<String, f64>,
+    ) -> HashMap<String, f64> {
+        if self.explaining {
+            let matched = results.keys().cloned().collect();
+            self.group_matches
+                .borrow_mut()
+                .insert(std::ptr::from_ref(query), matched);
+        }
+        results
+    }
+
+    /// The terms in the index a prefix or fuzzy term stands for, each weighted by how far it is
+    /// from what was typed.
+    fn expand(&self, query: &Query) -> Result<Vec<(String, f64)>> {
+        let key = std::ptr::from_ref(query);
+        if let Some(expansions) = self.expansions.borrow().get(&key) {
+            return Ok(expansions.clone());
+        }
+        let expansions = self.expand_uncached(query)?;
+        self.expansions.borrow_mut().insert(key, expansions.clone());
+        Ok(expansions)
+    }
+
+    fn expand_uncached(&self, query: &Query) -> Result<Vec<(String, f64)>> {
+        match query {
+            Query::Prefix(field, prefix) => {
+                Ok(
+                    term_stats::get_by_prefix(self.connection, *field, prefix, MAX_EXPANSIONS)?
+                        .into_iter()
+                        .map(|t| {
+                            let weight = if t == *prefix { 1.0 } else { EXPANSION_WEIGHT };
+                            (t, weight)
+                        })
+                        .collect(),
+                )
+            }
+            Query::Fuzzy(field, term, distance) => {
+                // The spelling index leaves out long terms, those are still matched exactly
+                let mut candidates =
+                    spelling::get_terms(self.connection, &spelling::deletions(term, *distance))?;
+                candidates.insert(term.clone());
+                let mut expansions = Vec::new();
+                for candidate in candidates {
+                    let d = query::spelling::distance(term, &candidate);
+                    if d > *distance {
+                        continue;
+                    }
+                    let df = match field {
+                        Some(f) => term_stats::get(self.connection, *f, &candidate)?,
+                        None => term_stats::get_by_term(self.connection, &candidate)?
+                            .into_values()
+                            .max()
+                            .unwrap_or(0),
+                    };
+                    if df > 0 {
+                        expansions.push((d, Reverse(df), candidate));
+                    }
+                }
+                expansions.sort();
+                expansions.truncate(MAX_EXPANSIONS);
+                Ok(expansions
+                    .into_iter()
+                    .map(|(d, _, t)| (t, EXPANSION_WEIGHT.powi(d as i32)))
+                    .collect())
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Matches in each field of a document are combined by their boosts.
+    fn boost(&self, hits: Vec<Hit>) -> HashMap<String, f64> {
+        let mut results: HashMap<String, f64> = HashMap::new();
+        for h in hits {
+            *results.entry(h.url).or_default() += h.score * self.boosts.get(h.field);
+        }
+        results
+    }
+
+    /// Scores the parts of a group which contribute to the score and then drops the documents
+    /// its filters and exclusions don't let through.
+    fn group(
+        &self,
+        parts: &[Query],
+        operator: Operator,
+        min_results: usize,
+    ) -> Result<HashMap<String, f64>> {
+        let (predicates, scored): (Vec<&Query>, Vec<&Query>) =
+            parts.iter().partition(|q| q.is_predicate());
+
+        let results = if !scored.is_empty() {
+            let mut part_results = Vec::with_capacity(scored.len());
+            for q in scored {
+                part_results.push(self.scores(q)?);
+            }
+            combine(&part_results, operator, min_results)
+        } else if !predicates.is_empty() {
+            // With nothing to score every document the filters let through is a match
+            documents::get_all_urls(self.connection)?
+                .into_iter()
+                .map(|url| (url, 0.0))
+                .collect()
+        } else {
+            return Ok(HashMap::new());
+        };
+
+        if predicates.is_empty() {
+            return Ok(results);
+        }
+
+        let matchers = self.matchers(predicates)?;
+        let matcher = match operator {
+            Operator::Or => Matcher::Any(matchers),
+            Operator::And => Matcher::All(matchers),
+        };
+
+        let mut filtered = HashMap::new();
+        for (url, score) in results {
+            let metadata = documents::get_metadata(self.connection, &url)?;
+            if matcher.matches(&url, metadata.as_ref()) {
+                filtered.insert(url, score);
+            }
+        }
+        Ok(filtered)
+    }
+
+    fn matcher(&self, query: &Query) -> Result<Matcher> {
+        match query {
+            Query::Filter(f) => Ok(Matcher::Filter(f.clone())),
+            Query::Not(q) => Ok(Matcher::Not(Box::new(self.matcher(q)?))),
+            Query::Or(qs) if query.is_predicate() => Ok(Matcher::Any(self.matchers(qs)?)),
+            Query::And(qs) | Query::Implicit(qs) if query.is_predicate() => {
+                Ok(Matcher::All(self.matchers(qs)?))
+            }
+            _ => Ok(Matcher::Matched(self.scores(query)?.into_keys().collect())),
+        }
+    }
+
+    fn matchers<'q>(&self, queries: impl IntoIterator<Item = &'q Query>) -> Result<Vec<Matcher>> {
+        queries.into_iter().map(|q| self.matcher(q)).collect()
+    }
+
+    /// How the text score of a document the query matched was arrived at, following the same
+    /// path through the query as `scores`. Nothing for parts of the query the document doesn't
+    /// match.
+    fn explain(&self, query: &Query, url: &str) -> Result<Option<Explanation>> {
+        match query {
+            Query::Term(field, t) => {
+                let mut fields = Vec::new();
+                for f in Field::ALL
+                    .into_iter()
+                    .filter(|f| field.is_none_or(|g| g == *f))
+                {
+                    if let Some(e) = self.scorer.explain_one(self.connection, url, f, t)? {
+                        fields.push(self.explain_boost(f, vec![e]));
+                    }
+                }
+                Ok(sum(&format!("term \"{}\"", t), fields))
+            }
+            Query::Phrase(field, p) => {
+                let mut fields = Vec::new();
+                for f in Field::ALL
+                    .into_iter()
+                    .filter(|f| field.is_none_or(|g| g == *f))
+                {
+                    let mut positions = Vec::with_capacity(p.len());
+                    for t in p {
+                        match term_frequencies::get(self.connection, url, f, t)? {
+                            Some(tf) => positions.push(tf.positions),
+                            None => break,
+                        }
+                    }
+                    let positions: Vec<&[u32]> = positions.iter().map(|p| p.as_slice()).collect();
+                    if positions.len() < p.len() || !has_phrase(&positions) {
+                        continue;
+                    }
+                    let mut terms = Vec::with_capacity(p.len());
+                    for t in p {
+                        terms.extend(self.scorer.explain_one(self.connection, url, f, t)?);
+                    }
+                    fields.push(self.explain_boost(f, terms));
+                }
+                Ok(sum(&format!("phrase \"{}\"", p.join(" ")), fields))
+            }
+            Query::Prefix(field, _) | Query::Fuzzy(field, ..) => {
+                let mut alternatives = Vec::new();
+                for (term, weight) in self.expand(query)? {
+                    let t = Query::Term(*field, term);
+                    if let Some(e) = self.explain(&t, url)? {
+                        alternatives.push((e, weight));
+                    }
+                }
+                Ok(best_explanation(alternatives))
+            }
+            Query::Alternatives(qs) => {
+                let mut alternatives = Vec::new();
+                for (q, weight) in qs {
+                    if let Some(e) = self.explain_part(q, url)? {
+                        alternatives.push((e, *weight));
+                    }
+                }
+                Ok(best_explanation(alternatives))
+            }
+            Query::Filter(_) | Query::Not(_) => Ok(Some(Explanation::new(0.0, "matched filters"))),
+            Query::And(qs) | Query::Or(qs) | Query::Implicit(qs) => {
+                let scored: Vec<&Query> = qs.iter().filter(|q| !q.is_predicate()).collect();
+                if scored.is_empty() {
+                    return Ok(Some(Explanation::new(0.0, "matched filters")));
+                }
+                let mut parts = Vec::new();
+                for q in &scored {
+                    parts.extend(self.explain_part(q, url)?);
+                }
+                if scored.len() == 1 {
+                    return Ok(parts.pop());
+                }
+                if parts.len() == scored.len() {
+                    return Ok(sum("sum of clauses", parts));
+                }
+                let coord = parts.len() as f64 / scored.len() as f64;
+                Ok(sum("sum of clauses", parts).map(|s| {
+                    Explanation::with_details(
+                        s.value * coord,
+                        &format!("sum × {} of {} clauses matched", coord, scored.len()),
+                        vec![s],
+                    )
+                }))
+            }
+        }
+    }
+
+    /// Groups within the query can leave a document out even when it matches some of their
+    /// parts, so whether they matched it at all is checked against their scores.
+    fn explain_part(&self, query: &Query, url: &str) -> Result<Option<Explanation>> {
+        let group = matches!(
+            query,
+            Query::And(_) | Query::Or(_) | Query::Implicit(_) | Query::Filter(_) | Query::Not(_)
+        );
+        if group {
+            let remembered = self
+                .group_matches
+                .borrow()
+                .get(&std::ptr::from_ref(query))
+                .map(|matched| matched.contains(url));
+            let matched = match remembered {
+                Some(matched) => matched,
+                None => self.scores(query)?.contains_key(url),
+            };
+            if !matched {
+                return Ok(None);
             }
         }
+        self.explain(query, url)
     }
 
-    let mut sorted_results: Vec<Document> = scored_results
+    fn explain_boost(&self, field: Field, terms: Vec<Explanation>) -> Explanation {
+        let boost = self.boosts.get(field);
+        let score: f64 = terms.iter().map(|e| e.value).sum();
+        Explanation::with_details(
+            score * boost,
+            &format!("{} × boost {}", field.as_str(), boost),
+            terms,
+        )
+    }
+}
+
+/// Adds up the parts, nothing if there aren't any.
+fn sum(description: &str, parts: Vec<Explanation>) -> Option<Explanation> {
+    if parts.is_empty() {
+        return None;
+    }
+    Some(Explanation::with_details(
+        parts.iter().map(|e| e.value).sum(),
+        description,
+        parts,
+    ))
+}
+
+/// The counterpart of `best_alternative`, only the best weighted alternative counts.
+fn best_explanation(alternatives: Vec<(Explanation, f64)>) -> Option<Explanation> {
+    let (best, weight) = alternatives
+        .into_iter()
+        .max_by(|a, b| (a.0.value * a.1).total_cmp(&(b.0.value * b.1)))?;
+    Some(Explanation::with_details(
+        best.value * weight,
+        &format!("best alternative × weight {}", weight),
+        vec![best],
+    ))
+}
+
+pub fn execute(
+    connection: &Connection,
+    terms: &[String],
+    options: &SearchOptions,
+) -> Result<Results> {
+    let ranker = LinearRanker::load(connection)?;
+    execute_with_ranker(connection, terms, options, &ranker)
+}
+
+/// Finds the documents matching the query and scores their text, leaving the final score to
+/// the ranker.
+pub fn execute_with_ranker(
+    connection: &Connection,
+    terms: &[String],
+    options: &SearchOptions,
+    ranker: &dyn Ranker,
+) -> Result<Results> {
+    let analyzer = Analyzer::load(connection)?;
+    let text = terms.join(" ");
+    let mut query = Synonyms::load(connection)?.expand(query::parse(&analyzer, &text)?);
+    let filters = query::parse_filters(&analyzer, &options.filters)?;
+    if !filters.is_empty() {
+        query = Query::And([query].into_iter().chain(filters).collect());
+    }
+
+    let ranking = match options.ranking {
+        Some(r) => r,
+        None => RankingFunction::load(connection)?,
+    };
+    let min_results = match settings::get(connection, MIN_RESULTS_KEY)? {
+        Some(v) => v.parse()?,
+        None => DEFAULT_MIN_RESULTS,
+    };
+    let evaluator = Evaluator {
+        connection,
+        scorer: Scorer::load(connection, ranking)?,
+        boosts: FieldBoosts::load(connection)?,
+        operator: options.operator,
+        min_results,
+        expanded: RefCell::new(BTreeSet::new()),
+        expansions: RefCell::new(HashMap::new()),
+        explaining: options.explain,
+        group_matches: RefCell::new(HashMap::new()),
+    };
+    let mut scored_results = evaluator.scores(&query)?;
+
+    let expanded = evaluator.expanded.take();
+    let query_terms: BTreeSet<&str> = query.positive_terms().into_iter().collect();
+
+    let body_positions = if query_terms.len() > 1 {
+        get_body_positions(connection, &query_terms)?
+    } else {
+        vec![]
+    };
+    for (url, score) in scored_results.iter_mut() {
+        *score *= get_proximity_boost(&body_positions, url);
+    }
+
+    let trust = DomainTrust::load(connection)?;
+    let now = Utc::now().timestamp();
+    let documents: Vec<(&str, f64)> = scored_results
+        .iter()
+        .map(|(url, score)| (url.as_str(), *score))
+        .collect();
+    let candidates: Vec<Candidate> = Features::load_all(connection, &trust, &documents, now)?
         .into_iter()
-        .map(|(url, score)| Document::new(&url, score))
+        .zip(&scored_results)
+        .map(|(features, (url, _))| Candidate {
+            url: url.clone(),
+            features,
+        })
         .collect();
 
-    sorted_results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    let scores = ranker.rank(&candidates);
+    let results: Vec<Document> = candidates
+        .iter()
+        .zip(scores)
+        .map(|(c, score)| Document::new(&c.url, score))
+        .collect();
+    let mut sorted_results = collapse_near_duplicates(connection, results)?;
+
+    let facets = if options.facets {
+        let urls = sorted_results.iter().map(|d| d.url.as_str());
+        Some(Facets::count(connection, urls, now)?)
+    } else {
+        None
+    };
+
+    // Ties are broken by url so that pages don't overlap or skip results between requests
+    let by_score =
+        |a: &Document, b: &Document| b.score.total_cmp(&a.score).then_with(|| a.url.cmp(&b.url));
+
+    // Only the results up to the end of the page need to be put in order
+    let total = sorted_results.len();
+    let end = options
+        .limit
+        .map_or(total, |l| options.offset.saturating_add(l).min(total));
+    if let Some(per_site) = options.per_site
+        && !query.restricts_site()
+    {
+        let page_size = options.limit.unwrap_or(total);
+        sorted_results = limit_per_site(sorted_results, per_site, page_size, end);
+    } else {
+        if end == 0 {
+            sorted_results.clear();
+        } else if end < total {
+            sorted_results.select_nth_unstable_by(end - 1, by_score);
+            sorted_results.truncate(end);
+        }
+        sorted_results.sort_by(by_score);
+    }
+
+    let mut documents: Vec<Document> = sorted_results.into_iter().skip(options.offset).collect();
+    if let Some(path) = &options.snippets_from {
+        let mut highlighted = query_terms.clone();
+        highlighted.extend(expanded.iter().map(|t| t.as_str()));
+        for document in documents.iter_mut() {
+            document.snippets = get_snippets(path, &analyzer, &document.url, &highlighted)?;
+        }
+    }
+    if options.explain {
+        let candidates: HashMap<&str, &Candidate> =
+            candidates.iter().map(|c| (c.url.as_str(), c)).collect();
+        for document in documents.iter_mut() {
+            let mut text = evaluator
+                .explain(&query, &document.url)?
+                .unwrap_or_else(|| Explanation::new(0.0, "no match"));
+            let boost = get_proximity_boost(&body_positions, &document.url);
+            if boost != 1.0 {
+                text =
+                    Explanation::with_details(text.value * boost, "text × proximity boost", vec![
+                        text,
+                        Explanation::new(boost, "proximity boost"),
+                    ]);
+            }
+            let candidate = candidates[document.url.as_str()];
+            document.explanation = Some(ranker.explain(candidate, text));
+        }
+    }
+
+    // Looking up corrections is only worth it when the query found too little and only once,
+    // not again for every page
+    let suggestion = if options.offset == 0 && total < min_results {
+        query::spelling::suggest(connection, &analyzer, &text)?
+    } else {
+        None
+    };
+
+    Ok(Results {
+        total,
+        documents,
+        suggestion,
+        facets,
+    })
+}
+
+/// Shows each cluster of near duplicates as a single result, its canonical member if that
+/// matched and otherwise its best scoring one, counting the rest against it.
+fn collapse_near_duplicates(
+    connection: &Connection,
+    results: Vec<Document>,
+) -> Result<Vec<Document>> {
+    let urls: Vec<&str> = results.iter().map(|d| d.url.as_str()).collect();
+    let mut fingerprints = fingerprints::get_by_urls(connection, &urls)?;
+    let mut clusters: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, document) in results.iter().enumerate() {
+        if let Some(f) = fingerprints.remove(&document.url) {
+            clusters.entry(f.cluster).or_default().push(i);
+        }
+    }
+
+    // Left out near duplicates have no count at all
+    let mut similar_pages = vec![Some(0); results.len()];
+    for (cluster, members) in clusters {
+        let shown = match members.iter().find(|i| results[**i].url == cluster) {
+            Some(i) => *i,
+            None => *members
+                .iter()
+                .max_by(|a, b| {
+                    let (a, b) = (&results[**a], &results[**b]);
+                    a.score.total_cmp(&b.score).then_with(|| b.url.cmp(&a.url))
+                })
+                .unwrap(),
+        };
+        for i in &members {
+            similar_pages[*i] = None;
+        }
+        similar_pages[shown] = Some(members.len() - 1);
+    }
+
+    Ok(results
+        .into_iter()
+        .zip(similar_pages)
+        .filter_map(|(mut document, similar)| {
+            document.similar_pages = similar?;
+            Some(document)
+        })
+        .collect())
+}
+
+/// Orders documents best first in a max-heap, ties broken by url the same way as the results.
+struct Ranked(Document);
 
-    Ok(sorted_results)
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .score
+            .total_cmp(&other.0.score)
+            .then_with(|| other.0.url.cmp(&self.0.url))
+    }
 }
 
-pub fn search(connection: &Connection, terms: &Vec<String>) -> Result<()> {
-    let results = execute(connection, terms)?;
-    for document in results {
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+/// The first `end` results, filled in a page at a time so that no page has more than `per_site`
+/// results from one site. A site's other results are carried over to the next page, ahead of
+/// anything they outrank, and once there is nothing else left they fill up the page anyway.
+/// Only as many results as it takes to get to `end` are taken out of the heap in order.
+fn limit_per_site(
+    results: Vec<Document>,
+    per_site: usize,
+    page_size: usize,
+    end: usize,
+) -> Vec<Document> {
+    let mut remaining: BinaryHeap<Ranked> = results.into_iter().map(Ranked).collect();
+    let mut carried: VecDeque<Document> = VecDeque::new();
+    let mut kept: Vec<Document> = Vec::with_capacity(end);
+    while kept.len() < end && !(remaining.is_empty() && carried.is_empty()) {
+        let page_end = kept.len().saturating_add(page_size.max(1)).min(end);
+        let mut sites: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut left_out: VecDeque<Document> = VecDeque::new();
+        while kept.len() < page_end {
+            let Some(document) = carried.pop_front().or_else(|| remaining.pop().map(|r| r.0))
+            else {
+                break;
+            };
+            let (best, count) = sites.entry(site(&document.url)).or_insert((kept.len(), 0));
+            *count += 1;
+            if *count <= per_site.max(1) {
+                kept.push(document);
+            } else {
+                kept[*best].more_from_site += 1;
+                left_out.push_back(document);
+            }
+        }
+        if remaining.is_empty() && carried.is_empty() {
+            while kept.len() < page_end
+                && let Some(document) = left_out.pop_front()
+            {
+                kept[sites[&site(&document.url)].0].more_from_site -= 1;
+                kept.push(document);
+            }
+        }
+        left_out.extend(carried.drain(..));
+        carried = left_out;
+    }
+    kept
+}
+
+/// Documents only known from links pointing at them have no text and so no snippets.
+fn get_snippets(
+    path: &Path,
+    analyzer: &Analyzer,
+    url: &str,
+    terms: &BTreeSet<&str>,
+) -> Result<Vec<Snippet>> {
+    let text = match fs::read_to_string(crawl::wet_path(path, url)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(snippets::snippets(analyzer, &text, terms, MAX_SNIPPETS))
+}
+
+pub fn search(connection: &Connection, terms: &[String], options: &SearchOptions) -> Result<()> {
+    let results = execute(connection, terms, options)?;
+    if let Some(suggestion) = &results.suggestion {
+        println!("Did you mean: {}", suggestion);
+    }
+    println!("{} results", results.total);
+    if let Some(facets) = &results.facets {
+        for (name, values) in [
+            ("Sites", &facets.site),
+            ("Languages", &facets.language),
+            ("Content types", &facets.content_type),
+            ("Crawled", &facets.crawled),
+        ] {
+            println!("{}", name);
+            for v in values {
+                println!("\t{}\t{}\t{}", v.value, v.count, v.filter);
+            }
+        }
+    }
+    for document in results.documents {
         println!("\t{}\t{}", document.url, document.score);
+        for snippet in document.snippets {
+            println!("\t\t{}", snippet.marked("*", "*"));
+        }
+        if let Some(explanation) = &document.explanation {
+            for line in explanation.to_string().lines() {
+                println!("\t\t{}", line);
+            }
+        }
+        if document.more_from_site > 0 {
+            println!(
+                "\t\t{} more from site:{}",
+                document.more_from_site,
+                site(&document.url)
+            );
+        }
+        if document.similar_pages > 0 {
+            println!("\t\t{} similar pages", document.similar_pages);
+        }
     }
 
     Ok(())
@@ -64,7 +1218,14 @@ pub fn search(connection: &Connection, terms: &Vec<String>) -> Result<()> {
 mod tests {
     use super::*;
     use crate::db;
-    use crate::db::tf_idf::TfIdfScore;
+    use crate::db::documents::index;
+
+    fn tf_idf() -> SearchOptions {
+        SearchOptions {
+            ranking: Some(RankingFunction::TfIdf),
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_execute() {
@@ -75,29 +1236,809 @@ mod tests {
             "bravo".to_string(),
             "charlie".to_string(),
         ];
-        let results = execute(&connection, &terms).unwrap();
+        let results = execute(&connection, &terms, &tf_idf()).unwrap().documents;
 
         let expected: Vec<Document> = vec![];
         assert_eq!(expected, results);
 
-        db::tf_idf::insert(
+        index(
             &connection,
-            &TfIdfScore::new("http://alpha.example.com", "alpha", 0.01),
-        )
-        .unwrap();
-        db::tf_idf::insert(
+            "http://alpha.example.com",
+            Field::Body,
+            "alpha delta delta delta",
+        );
+        index(
             &connection,
-            &TfIdfScore::new("http://alpha2.example.com", "alpha", 0.02),
-        )
+            "http://alpha2.example.com",
+            Field::Body,
+            "alpha alpha delta delta",
+        );
+        index(&connection, "http://echo.example.com", Field::Body, "echo");
+
+        // Searchable straight away, the document frequency of alpha is two out of three. Nothing
+        // matches every term so the documents matching one of them are scaled down by a third.
+        let idf = (3.0f64 / 2.0).log10();
+        let results = execute(&connection, &terms, &tf_idf()).unwrap().documents;
+        assert_eq!(
+            vec![
+                Document::new("http://alpha2.example.com", 0.5 * idf / 3.0),
+                Document::new("http://alpha.example.com", 0.25 * idf / 3.0),
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn test_combine() {
+        let results = |scores: &[(&str, f64)]| -> HashMap<String, f64> {
+            scores.iter().map(|(u, s)| (u.to_string(), *s)).collect()
+        };
+        let clauses = [
+            results(&[("a", 1.0), ("b", 2.0)]),
+            results(&[("a", 1.0), ("c", 4.0)]),
+        ];
+
+        assert_eq!(results(&[("a", 2.0)]), combine(&clauses, Operator::And, 1));
+        assert_eq!(
+            results(&[("a", 2.0), ("b", 1.0), ("c", 2.0)]),
+            combine(&clauses, Operator::And, 2)
+        );
+        assert_eq!(
+            results(&[("a", 2.0), ("b", 1.0), ("c", 2.0)]),
+            combine(&clauses, Operator::Or, 1)
+        );
+        assert_eq!(results(&[]), combine(&[], Operator::And, 1));
+    }
+
+    #[test]
+    fn test_execute_conjunctive() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://both.example.com",
+            Field::Body,
+            "rust book",
+        );
+        index(
+            &connection,
+            "http://rust.example.com",
+            Field::Body,
+            "rust rust rust",
+        );
+        index(&connection, "http://book.example.com", Field::Body, "book");
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+        db::settings::set(&connection, "search.min_results", "1").unwrap();
+
+        let query = vec!["rust".to_string(), "book".to_string(), "rust".to_string()];
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!(vec!["http://both.example.com"], urls(&results));
+
+        let or = SearchOptions {
+            operator: Operator::Or,
+            ..tf_idf()
+        };
+        let results = execute(&connection, &query, &or).unwrap().documents;
+        assert_eq!(3, results.len());
+        assert_eq!("http://both.example.com", results[0].url);
+
+        // Too few complete matches falls back to partial ones
+        db::settings::set(&connection, "search.min_results", "2").unwrap();
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!(3, results.len());
+    }
+
+    #[test]
+    fn test_execute_suggestion() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        for url in ["http://a.example.com", "http://b.example.com"] {
+            index(&connection, url, Field::Body, "search engine");
+        }
+        index(&connection, "http://c.example.com", Field::Body, "serch");
+
+        let query = vec!["serch".to_string()];
+        let results = execute(&connection, &query, &tf_idf()).unwrap();
+        assert_eq!(Some("search".to_string()), results.suggestion);
+
+        // Not on later pages
+        let second = SearchOptions {
+            offset: 1,
+            ..tf_idf()
+        };
+        let results = execute(&connection, &query, &second).unwrap();
+        assert_eq!(None, results.suggestion);
+
+        // Nor when the query found enough already
+        db::settings::set(&connection, "search.min_results", "1").unwrap();
+        let results = execute(&connection, &query, &tf_idf()).unwrap();
+        assert_eq!(None, results.suggestion);
+    }
+
+    fn urls(documents: &[Document]) -> Vec<&str> {
+        documents.iter().map(|d| d.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_execute_with_votes() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://a.example.com",
+            Field::Body,
+            "alpha alpha",
+        );
+        index(
+            &connection,
+            "http://b.example.com",
+            Field::Body,
+            "alpha bravo",
+        );
+        index(&connection, "http://c.example.com", Field::Body, "charlie");
+        db::votes::set(&connection, "http://b.example.com", "alice", 1).unwrap();
+
+        let query = vec!["alpha".to_string()];
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!("http://a.example.com", results[0].url);
+
+        db::settings::set(&connection, &LinearRanker::key("votes"), "1.0").unwrap();
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!("http://b.example.com", results[0].url);
+    }
+
+    #[test]
+    fn test_execute_boolean() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://book.example.com",
+            Field::Body,
+            "rust book",
+        );
+        index(
+            &connection,
+            "http://game.example.com",
+            Field::Body,
+            "rust game",
+        );
+        index(
+            &connection,
+            "http://fungus.example.com",
+            Field::Body,
+            "fungus",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let search = |q: &str| {
+            let mut results = execute(&connection, &[q.to_string()], &tf_idf())
+                .unwrap()
+                .documents;
+            results.sort_by(|a, b| a.url.cmp(&b.url));
+            results.into_iter().map(|d| d.url).collect::<Vec<String>>()
+        };
+
+        assert_eq!(vec!["http://book.example.com"], search("rust -game"));
+        assert_eq!(vec!["http://book.example.com"], search("rust NOT game"));
+        assert_eq!(vec!["http://book.example.com"], search("rust AND book"));
+        assert_eq!(
+            vec!["http://book.example.com", "http://fungus.example.com"],
+            search("book OR fungus")
+        );
+        assert_eq!(
+            vec!["http://fungus.example.com", "http://game.example.com"],
+            search("(rust OR fungus) -book")
+        );
+        assert!(execute(&connection, &["rust OR".to_string()], &tf_idf()).is_err());
+    }
+
+    #[test]
+    fn test_execute_filters() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        let documents = [
+            (
+                "http://en.example.com/",
+                "text/html",
+                Some("en"),
+                1704067200,
+            ),
+            (
+                "http://fr.example.com/",
+                "text/html",
+                Some("fr"),
+                1706745600,
+            ),
+            ("http://text.example.org/", "text/plain", None, 1706745600),
+        ];
+        for (url, content_type, language, crawled_at) in documents {
+            index(&connection, url, Field::Body, "rust");
+            let metadata = documents::Metadata::new(url, content_type, language, crawled_at);
+            documents::set_metadata(&connection, &metadata).unwrap();
+        }
+
+        let search = |q: &str| {
+            let mut results = execute(&connection, &[q.to_string()], &tf_idf())
+                .unwrap()
+                .documents;
+            results.sort_by(|a, b| a.url.cmp(&b.url));
+            results.into_iter().map(|d| d.url).collect::<Vec<String>>()
+        };
+
+        assert_eq!(vec!["http://en.example.com/"], search("rust lang:en"));
+        assert_eq!(
+            vec!["http://en.example.com/", "http://fr.example.com/"],
+            search("rust site:example.com")
+        );
+        assert_eq!(vec!["http://text.example.org/"], search("rust type:plain"));
+        assert_eq!(
+            vec!["http://en.example.com/"],
+            search("rust before:2024-02-01")
+        );
+        assert_eq!(
+            vec!["http://fr.example.com/", "http://text.example.org/"],
+            search("after:2024-02-01")
+        );
+        assert_eq!(
+            vec!["http://en.example.com/", "http://text.example.org/"],
+            search("rust -lang:fr")
+        );
+    }
+
+    #[test]
+    fn test_execute_pagination() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        // Enough matches for one term that a cap on postings would lose some, with plenty of ties
+        for i in 0..150 {
+            let text = format!("alpha {}", "filler ".repeat(i % 40));
+            index(
+                &connection,
+                &format!("http://{}.example.com", i),
+                Field::Body,
+                &text,
+            );
+        }
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let query = vec!["alpha".to_string()];
+        let everything = execute(&connection, &query, &tf_idf()).unwrap();
+        assert_eq!(150, everything.total);
+        assert_eq!(150, everything.documents.len());
+
+        let page = |offset: usize, limit: usize| {
+            let options = SearchOptions {
+                offset,
+                limit: Some(limit),
+                ..tf_idf()
+            };
+            execute(&connection, &query, &options).unwrap()
+        };
+
+        let first = page(0, 10);
+        assert_eq!(150, first.total);
+        assert_eq!(everything.documents[..10], first.documents[..]);
+        assert_eq!(everything.documents[120..140], page(120, 20).documents[..]);
+        assert_eq!(everything.documents[140..], page(140, 20).documents[..]);
+        assert_eq!(Vec::<Document>::new(), page(200, 10).documents);
+        assert_eq!(Vec::<Document>::new(), page(0, 0).documents);
+    }
+
+    #[test]
+    fn test_count_phrase_matches() {
+        assert_eq!(2, count_phrase_matches(&[&[1, 5, 9], &[2, 10], &[3, 11]]));
+        assert_eq!(0, count_phrase_matches(&[&[1, 5], &[3, 7]]));
+        assert_eq!(0, count_phrase_matches(&[]));
+        assert!(has_phrase(&[&[1, 5], &[], &[3, 7]]));
+        assert!(!has_phrase(&[&[1, 5], &[3, 7]]));
+    }
+
+    #[test]
+    fn test_min_span() {
+        assert_eq!(Some(2), min_span(&[&[1, 20], &[2, 40]]));
+        assert_eq!(Some(3), min_span(&[&[0, 10], &[12, 30], &[11]]));
+        assert_eq!(None, min_span(&[&[1], &[]]));
+    }
+
+    #[test]
+    fn test_execute_phrase() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://phrase.example.com",
+            Field::Body,
+            "not very good",
+        );
+        index(
+            &connection,
+            "http://scattered.example.com",
+            Field::Body,
+            "not a b c d e f g h very i j k l m n o p q r good",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let query = vec![
+            "\"not".to_string(),
+            "very".to_string(),
+            "good\"".to_string(),
+        ];
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!(1, results.len());
+        assert_eq!("http://phrase.example.com", results[0].url);
+
+        let query = vec!["not".to_string(), "very".to_string(), "good".to_string()];
+        let results = execute(&connection, &query, &tf_idf()).unwrap().documents;
+        assert_eq!(2, results.len());
+        assert_eq!("http://phrase.example.com", results[0].url);
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn test_execute_fields() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(&connection, "http://body.example.com", Field::Body, "alpha");
+        index(
+            &connection,
+            "http://title.example.com",
+            Field::Title,
+            "alpha",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let idf = 3.0f64.log10();
+        let results = execute(&connection, &["alpha".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(
+            vec![
+                Document::new("http://title.example.com", idf * 4.0),
+                Document::new("http://body.example.com", idf),
+            ],
+            results
+        );
+
+        let results = execute(&connection, &["body:alpha".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(vec![Document::new("http://body.example.com", idf)], results);
+
+        db::settings::set(&connection, "boost.title", "0.5").unwrap();
+        let results = execute(&connection, &["alpha".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(
+            vec![
+                Document::new("http://body.example.com", idf),
+                Document::new("http://title.example.com", idf * 0.5),
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn test_execute_expansions() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://exact.example.com",
+            Field::Body,
+            "program",
+        );
+        index(
+            &connection,
+            "http://longer.example.com",
+            Field::Body,
+            "programming",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let idf = 3.0f64.log10();
+        let results = execute(&connection, &["program*".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(
+            vec![
+                Document::new("http://exact.example.com", idf),
+                Document::new("http://longer.example.com", idf * 0.5),
+            ],
+            results
+        );
+
+        let results = execute(&connection, &["progrem~1".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(
+            vec![Document::new("http://exact.example.com", idf * 0.5)],
+            results
+        );
+
+        let results = execute(&connection, &["prgoram~".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(
+            vec![Document::new("http://exact.example.com", idf * 0.5)],
+            results
+        );
+
+        // Terms too long for the spelling index still match themselves
+        let long = "supercalifragilisticexpialidocious";
+        index(&connection, "http://long.example.com", Field::Body, long);
+        let idf = 4.0f64.log10();
+        let results = execute(&connection, &[format!("{long}~")], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(vec![Document::new("http://long.example.com", idf)], results);
+
+        // Prefixes only expand to terms in the field searched, those in other fields don't
+        // crowd them out
+        let crowd: Vec<String> = (0..MAX_EXPANSIONS)
+            .map(|i| format!("programa{i}"))
+            .collect();
+        index(
+            &connection,
+            "http://crowd.example.com",
+            Field::Body,
+            &crowd.join(" "),
+        );
+        index(
+            &connection,
+            "http://title.example.com",
+            Field::Title,
+            "programmer",
+        );
+        let results = execute(&connection, &["title:program*".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
+        assert_eq!(vec!["http://title.example.com"], urls(&results));
+    }
+
+    #[test]
+    fn test_execute_per_site() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        for url in [
+            "http://a.example.com/1",
+            "http://a.example.com/2",
+            "http://www.a.example.com/3",
+        ] {
+            index(&connection, url, Field::Body, "alpha");
+        }
+        index(
+            &connection,
+            "http://b.example.com",
+            Field::Body,
+            "alpha bravo",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let page = |offset: usize| SearchOptions {
+            per_site: Some(2),
+            offset,
+            limit: Some(3),
+            ..tf_idf()
+        };
+        let more = |results: &Results| -> Vec<(String, usize)> {
+            results
+                .documents
+                .iter()
+                .map(|d| (d.url.clone(), d.more_from_site))
+                .collect()
+        };
+        let query = vec!["alpha".to_string()];
+        let results = execute(&connection, &query, &page(0)).unwrap();
+        assert_eq!(4, results.total);
+        assert_eq!(
+            vec![
+                ("http://a.example.com/1".to_string(), 1),
+                ("http://a.example.com/2".to_string(), 0),
+                ("http://b.example.com".to_string(), 0),
+            ],
+            more(&results)
+        );
+
+        // The site's other result comes on the next page
+        let results = execute(&connection, &query, &page(3)).unwrap();
+        assert_eq!(
+            vec![("http://www.a.example.com/3".to_string(), 0)],
+            more(&results)
+        );
+
+        // Without other sites to make room for a page fills up with the same one
+        let results = execute(&connection, &query, &SearchOptions {
+            limit: Some(4),
+            ..page(0)
+        })
         .unwrap();
+        assert_eq!(4, results.documents.len());
+        assert!(results.documents.iter().all(|d| d.more_from_site == 0));
+
+        // Drilling down into a site shows all of its results
+        let query = vec!["alpha".to_string(), "site:a.example.com".to_string()];
+        let results = execute(&connection, &query, &page(0)).unwrap();
+        assert_eq!(3, results.total);
+        assert!(results.documents.iter().all(|d| d.more_from_site == 0));
+    }
+
+    #[test]
+    fn test_execute_near_duplicates() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        for (url, text) in [
+            ("http://a.example.com/article", "alpha bravo bravo bravo"),
+            (
+                "http://print.example.com/article",
+                "alpha alpha bravo bravo",
+            ),
+            (
+                "http://mirror.example.com/article",
+                "alpha alpha alpha bravo",
+            ),
+            ("http://b.example.com/", "alpha charlie"),
+            ("http://c.example.com/", "charlie"),
+        ] {
+            index(&connection, url, Field::Body, text);
+        }
+        fingerprints::set(&connection, "http://a.example.com/article", 0).unwrap();
+        fingerprints::set(&connection, "http://print.example.com/article", 1).unwrap();
+        fingerprints::set(&connection, "http://mirror.example.com/article", 3).unwrap();
+        fingerprints::set(&connection, "http://b.example.com/", u64::MAX).unwrap();
+
+        let search = |q: &str| {
+            let results = execute(&connection, &[q.to_string()], &tf_idf()).unwrap();
+            let documents: Vec<(String, usize)> = results
+                .documents
+                .into_iter()
+                .map(|d| (d.url, d.similar_pages))
+                .collect();
+            (results.total, documents)
+        };
+
+        // The canonical member is shown even though a copy scored higher
+        assert_eq!(
+            (2, vec![
+                ("http://b.example.com/".to_string(), 0),
+                ("http://a.example.com/article".to_string(), 2),
+            ]),
+            search("alpha")
+        );
+        // Otherwise the best of the copies that matched
+        assert_eq!(
+            (2, vec![
+                ("http://mirror.example.com/article".to_string(), 1),
+                ("http://b.example.com/".to_string(), 0),
+            ]),
+            search("alpha -site:a.example.com")
+        );
+    }
+
+    #[test]
+    fn test_execute_facets() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        let now = Utc::now().timestamp();
+        for (url, language) in [
+            ("http://en.example.com", "en"),
+            ("http://fr.example.com", "fr"),
+        ] {
+            index(&connection, url, Field::Body, "alpha bravo");
+            let metadata = Metadata::new(url, "text/html", Some(language), now);
+            documents::set_metadata(&connection, &metadata).unwrap();
+        }
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+
+        let options = SearchOptions {
+            facets: true,
+            ..tf_idf()
+        };
+        let results = execute(&connection, &["alpha".to_string()], &options).unwrap();
+        let facets = results.facets.unwrap();
+        assert_eq!(2, facets.language.len());
+        assert_eq!("lang:en", facets.language[0].filter);
+
+        // Filters hold even when any word of the query will do
+        let options = SearchOptions {
+            operator: Operator::Or,
+            filters: vec![facets.language[0].filter.clone()],
+            facets: true,
+            ..tf_idf()
+        };
+        let query = vec!["alpha".to_string(), "bravo".to_string()];
+        let results = execute(&connection, &query, &options).unwrap();
+        assert_eq!(1, results.total);
+        assert_eq!("http://en.example.com", results.documents[0].url);
+        assert_eq!(1, results.facets.unwrap().language.len());
 
-        let results = execute(&connection, &terms).unwrap();
+        let options = SearchOptions {
+            filters: vec!["alpha".to_string()],
+            ..tf_idf()
+        };
+        let error = execute(&connection, &query, &options).unwrap_err();
+        assert!(error.downcast_ref::<query::ParseError>().is_some());
+    }
+
+    #[test]
+    fn test_execute_synonyms() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(&connection, "http://js.example.com", Field::Body, "js");
+        index(
+            &connection,
+            "http://javascript.example.com",
+            Field::Body,
+            "javascript",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+        crate::actions::synonyms::add(&connection, "js, javascript", 0.5).unwrap();
+
+        let idf = 3.0f64.log10();
+        let results = execute(&connection, &["JavaScript".to_string()], &tf_idf())
+            .unwrap()
+            .documents;
         assert_eq!(
             vec![
-                Document::new("http://alpha2.example.com", 0.02,),
-                Document::new("http://alpha.example.com", 0.01,),
+                Document::new("http://javascript.example.com", idf),
+                Document::new("http://js.example.com", idf * 0.5),
             ],
             results
         );
     }
+
+    #[test]
+    fn test_execute_explain() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://book.example.com",
+            Field::Body,
+            "the rust book covers rust",
+        );
+        index(&connection, "http://book.example.com", Field::Title, "rust");
+        index(
+            &connection,
+            "http://game.example.com",
+            Field::Body,
+            "rust game",
+        );
+        index(
+            &connection,
+            "http://other.example.com",
+            Field::Body,
+            "other",
+        );
+        crate::actions::synonyms::add(&connection, "game, play", 0.5).unwrap();
+        db::settings::set(&connection, "search.min_results", "1").unwrap();
+        db::settings::set(&connection, &LinearRanker::key("authority"), "0.5").unwrap();
+
+        let explained = |q: &str, ranking: RankingFunction, operator: Operator| {
+            let options = SearchOptions {
+                ranking: Some(ranking),
+                operator,
+                explain: true,
+                ..Default::default()
+            };
+            execute(&connection, &[q.to_string()], &options)
+                .unwrap()
+                .documents
+        };
+
+        for q in [
+            "rust",
+            "rust book",
+            "\"rust book\"",
+            "ru*",
+            "rusty~",
+            "rust -game",
+            "title:rust OR game",
+            "(rust book) OR other",
+        ] {
+            for ranking in RankingFunction::ALL {
+                for operator in Operator::ALL {
+                    let documents = explained(q, ranking, operator);
+                    assert!(!documents.is_empty(), "{}", q);
+                    for d in documents {
+                        let explanation = d.explanation.unwrap();
+                        assert!((d.score - explanation.value).abs() < 1e-9, "{}", q);
+                    }
+                }
+            }
+        }
+
+        // One term in one field of one document, weighted by the ranker
+        let idf = 3.0f64.log10();
+        let documents = explained("game", RankingFunction::TfIdf, Operator::And);
+        let tf_idf =
+            Explanation::with_details(0.5 * idf, "tf-idf of \"game\", frequency × idf", vec![
+                Explanation::new(0.5, "frequency"),
+                Explanation::with_details(idf, "idf, log10(documents / df)", vec![
+                    Explanation::new(3.0, "documents"),
+                    Explanation::new(1.0, "df"),
+                ]),
+            ]);
+        let term =
+            Explanation::with_details(0.5 * idf, "term \"game\"", vec![Explanation::with_details(
+                0.5 * idf,
+                "body × boost 1",
+                vec![tf_idf],
+            )]);
+        assert_eq!(
+            Some(Explanation::with_details(
+                0.5 * idf,
+                "weighted sum of features",
+                vec![
+                    Explanation::with_details(0.5 * idf, "text × weight 1", vec![
+                        Explanation::with_details(0.5 * idf, "best alternative × weight 1", vec![
+                            term
+                        ],)
+                    ],),
+                    Explanation::with_details(0.0, "authority × weight 0.5", vec![
+                        Explanation::new(0.0, "authority")
+                    ],),
+                ],
+            )),
+            documents[0].explanation
+        );
+    }
 }
@@ -0,0 +1,101 @@
+use anyhow::{Result, anyhow};
+use rusqlite::Connection;
+
+use crate::db::{crawls, settings};
+use crate::ranking::RankingFunction;
+
+pub fn set(connection: &Connection, key: &str, value: &str) -> Result<()> {
+    // Changing how text is analyzed would leave the existing index disagreeing with new queries
+    if key.starts_with("analysis.") {
+        return Err(anyhow!(
+            "Invalid key: {} - analysis settings can only be chosen at init.",
+            key
+        ));
+    }
+
+    // Crawled urls were normalized the old way, the same page would be queued again under a
+    // different url
+    if key.starts_with("urls.") && crawls::has_any(connection)? {
+        return Err(anyhow!(
+            "Invalid key: {} - url settings can only be changed before crawling.",
+            key
+        ));
+    }
+
+    if key.starts_with("boost.")
+        || key.starts_with("ranking.bm25.")
+        || key.starts_with("ranker.weight.")
+        || key.starts_with("trust.")
+    {
+        let v = value
+            .parse::<f64>()
+            .map_err(|e| anyhow!("Invalid value for {}: {} - {}", key, value, e))?;
+        // One NaN or infinite weight would make every score NaN
+        if !v.is_finite() {
+            return Err(anyhow!(
+                "Invalid value for {}: {} - must be a finite number",
+                key,
+                value
+            ));
+        }
+    }
+
+    if key == "search.min_results" {
+        value
+            .parse::<usize>()
+            .map_err(|e| anyhow!("Invalid value for {}: {} - {}", key, value, e))?;
+    }
+
+    if key == "suggest.min_query_count" {
+        value
+            .parse::<u64>()
+            .map_err(|e| anyhow!("Invalid value for {}: {} - {}", key, value, e))?;
+    }
+
+    if key == "suggest.record_queries"
+        || key == "urls.sort_query"
+        || key == "urls.strip_trailing_slash"
+    {
+        value
+            .parse::<bool>()
+            .map_err(|e| anyhow!("Invalid value for {}: {} - {}", key, value, e))?;
+    }
+
+    if key == "ranking.function" {
+        RankingFunction::try_from(value).map_err(anyhow::Error::msg)?;
+    }
+
+    settings::set(connection, key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_set() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        set(&connection, "boost.title", "4.0").unwrap();
+        for value in ["NaN", "inf", "-inf", "four"] {
+            assert!(set(&connection, "boost.title", value).is_err());
+            assert!(set(&connection, "trust.example.com", value).is_err());
+        }
+        assert_eq!(
+            Some("4.0".to_string()),
+            settings::get(&connection, "boost.title").unwrap()
+        );
+
+        assert!(set(&connection, "urls.sort_query", "no").is_err());
+        set(&connection, "urls.sort_query", "false").unwrap();
+        crawls::insert(
+            &connection,
+            &crawls::Crawl::new("http://example.com/", &Default::default()).unwrap(),
+        )
+        .unwrap();
+        assert!(set(&connection, "urls.sort_query", "true").is_err());
+        assert!(set(&connection, "urls.strip_parameters", "ref").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::Url;
+use reqwest::blocking::Client;
+use rusqlite::Connection;
+
+use crate::actions::crawl::fetch;
+use crate::analysis::sitemap::{self, Entry, Sitemap};
+use crate::analysis::urls::{UrlNormalizer, same_site};
+use crate::db::{crawls, sitemaps};
+
+/// Sitemaps read per site, counting the indexes leading to them.
+static MAX_SITEMAPS: usize = 50;
+
+/// The root url of the site a url is on, what sites are kept as in the `sitemaps` table.
+pub fn site(url: &str) -> Result<String> {
+    Ok(Url::parse(url)?.join("/")?.into())
+}
+
+/// Reads the sitemaps of every site not read in the last day, queueing their pages to be crawled.
+pub fn read_all(
+    connection: &mut Connection,
+    client: &Client,
+    normalizer: &UrlNormalizer,
+) -> Result<()> {
+    for site in sitemaps::get_all_needing_update(connection)? {
+        read(connection, client, normalizer, &site)?;
+        sitemaps::set_read(connection, &site, Utc::now().timestamp())?;
+    }
+    Ok(())
+}
+
+/// Finds a site's sitemaps through its robots.txt, or at `/sitemap.xml` if it doesn't name any,
+/// and follows sitemap indexes down to the pages. Sitemaps which can't be read are skipped.
+pub fn read(
+    connection: &mut Connection,
+    client: &Client,
+    normalizer: &UrlNormalizer,
+    site: &str,
+) -> Result<()> {
+    let mut queue: VecDeque<String> = discover(client, site)?.into();
+    let mut seen = BTreeSet::new();
+
+    while let Some(url) = queue.pop_front() {
+        if seen.len() >= MAX_SITEMAPS {
+            break;
+        }
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+
+        println!("Reading sitemap {}", url);
+        let sitemap = match fetch(client, &url).and_then(|body| Sitemap::parse(&body)) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Failed to read sitemap: {}", url);
+                println!("\tError:{}", e);
+                continue;
+            }
+        };
+
+        match sitemap {
+            Sitemap::Index(sitemaps) => queue.extend(sitemaps),
+            Sitemap::Pages(entries) => {
+                let tx = connection.transaction()?;
+                let mut queued = 0;
+                for e in &entries {
+                    if enqueue(&tx, normalizer, site, e)? {
+                        queued += 1;
+                    }
+                }
+                tx.commit()?;
+                println!("Queued {} pages", queued);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The sitemaps named in the site's robots.txt, or the well known one.
+fn discover(client: &Client, site: &str) -> Result<Vec<String>> {
+    let robots_url = Url::parse(site)?.join("/robots.txt")?;
+    let named: Vec<String> = match fetch(client, robots_url.as_str()) {
+        Ok(robots) => sitemap::from_robots(&robots)
+            .iter()
+            .filter_map(|s| robots_url.join(s).ok())
+            .map(|s| s.into())
+            .collect(),
+        Err(_) => vec![],
+    };
+    if named.is_empty() {
+        return Ok(vec![Url::parse(site)?.join("/sitemap.xml")?.into()]);
+    }
+    Ok(named)
+}
+
+/// Queues a page from a site's sitemap to be crawled, recording when it last changed and how
+/// often it does for the crawl schedule. A sitemap only speaks for its own site, pages on others
+/// are left out. A change in the future is taken as happening now, otherwise the page would be
+/// due again on every run until then. Says whether the page was queued.
+pub fn enqueue(
+    connection: &Connection,
+    normalizer: &UrlNormalizer,
+    site: &str,
+    entry: &Entry,
+) -> Result<bool> {
+    let Ok(url) = normalizer.normalize(&entry.url) else {
+        return Ok(false);
+    };
+    if !same_site(&url, site) {
+        return Ok(false);
+    }
+
+    if crawls::get(connection, &url)?.is_none() {
+        crawls::insert(connection, &crawls::Crawl::new(&url, normalizer)?)?;
+    }
+    let now = Utc::now().timestamp();
+    crawls::set_schedule(
+        connection,
+        &url,
+        entry.last_modified.map(|m| m.min(now)),
+        entry.change_frequency,
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::db::crawls::ChangeFrequency;
+
+    #[test]
+    fn test_enqueue() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let normalizer = UrlNormalizer::default();
+        let site = site("https://Example.com/blog/post?id=1").unwrap();
+        assert_eq!("https://example.com/", site);
+
+        crawls::insert(
+            &connection,
+            &crawls::Crawl::new("https://example.com/about", &normalizer).unwrap(),
+        )
+        .unwrap();
+        crawls::set_ready(&connection, "https://example.com/about", 1000).unwrap();
+
+        for (url, last_modified, change_frequency, queued) in [
+            ("https://example.com/about/", Some(2000), None, true),
+            (
+                "https://example.com/news",
+                None,
+                Some(ChangeFrequency::Hourly),
+                true,
+            ),
+            ("https://www.example.com/later", Some(i64::MAX), None, true),
+            ("https://elsewhere.com/", None, None, false),
+            ("not a url", None, None, false),
+        ] {
+            let entry = Entry {
+                url: url.to_string(),
+                last_modified,
+                change_frequency,
+            };
+            assert_eq!(
+                queued,
+                enqueue(&connection, &normalizer, &site, &entry).unwrap()
+            );
+        }
+
+        // Already crawled, but changed since
+        let about = crawls::get(&connection, "https://example.com/about")
+            .unwrap()
+            .unwrap();
+        assert_eq!(1000, about.last_updated);
+        assert_eq!(Some(2000), about.last_modified);
+        assert!(about.is_due(1500));
+
+        let news = crawls::get(&connection, "https://example.com/news")
+            .unwrap()
+            .unwrap();
+        assert_eq!(-1, news.last_updated);
+        assert_eq!(Some(ChangeFrequency::Hourly), news.change_frequency);
+        // Not due again as soon as it's crawled
+        let later = crawls::get(&connection, "https://www.example.com/later")
+            .unwrap()
+            .unwrap();
+        assert!(later.last_modified.unwrap() <= Utc::now().timestamp());
+        crawls::set_ready(&connection, &later.url, Utc::now().timestamp()).unwrap();
+        let later = crawls::get(&connection, &later.url).unwrap().unwrap();
+        assert!(!later.is_due(Utc::now().timestamp()));
+
+        assert!(
+            crawls::get(&connection, "https://elsewhere.com/")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
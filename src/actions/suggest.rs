@@ -0,0 +1,137 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::analysis::Analyzer;
+use crate::db::{queries, settings, term_stats};
+
+static RECORD_QUERIES_KEY: &str = "suggest.record_queries";
+static MIN_QUERY_COUNT_KEY: &str = "suggest.min_query_count";
+
+/// How many times a query has to be searched for before it's suggested to anyone else, so that
+/// what one person typed isn't shown to the next.
+static DEFAULT_MIN_QUERY_COUNT: u64 = 3;
+
+/// Longer queries are too particular to be worth suggesting and aren't recorded.
+static MAX_QUERY_LENGTH: usize = 100;
+
+/// Records a query someone found results for so it can be suggested later, when the index has
+/// `suggest.record_queries` turned on. Suggestions are served to anyone, so what people searched
+/// for is only kept when asked for. Whitespace is collapsed so the same query typed slightly
+/// differently counts once.
+pub fn record(connection: &Connection, query: &str) -> Result<()> {
+    if settings::get(connection, RECORD_QUERIES_KEY)?.is_none_or(|v| v != "true") {
+        return Ok(());
+    }
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    if query.is_empty() || query.chars().count() > MAX_QUERY_LENGTH {
+        return Ok(());
+    }
+    queries::record(connection, &query)
+}
+
+/// Completions for a partly typed query. Earlier queries starting with it come first, most
+/// popular first and only those searched for at least `suggest.min_query_count` times, then the
+/// query with its last word completed to terms in the index, the most common first.
+pub fn suggest(connection: &Connection, prefix: &str, limit: usize) -> Result<Vec<String>> {
+    let trimmed = prefix.trim_start();
+    if trimmed.trim_end().is_empty() || limit == 0 {
+        return Ok(vec![]);
+    }
+
+    let collapsed = trimmed.split_whitespace().collect::<Vec<_>>().join(" ");
+    let min_count = match settings::get(connection, MIN_QUERY_COUNT_KEY)? {
+        Some(v) => v.parse()?,
+        None => DEFAULT_MIN_QUERY_COUNT,
+    };
+    let mut suggestions = queries::get_by_prefix(connection, &collapsed, min_count, limit)?;
+
+    // Completing the word still being typed, a trailing space means the last one is finished
+    let head = trimmed.trim_end_matches(char::is_alphanumeric);
+    let partial = &trimmed[head.len()..];
+    if !partial.is_empty() {
+        let analyzer = Analyzer::load(connection)?;
+        let head = head.split_whitespace().collect::<Vec<_>>().join(" ");
+        let head = match head.chars().last() {
+            Some(c) if !matches!(c, '(' | '"' | '-' | ':') => format!("{} ", head),
+            _ => head,
+        };
+        for term in
+            term_stats::get_by_prefix(connection, None, &analyzer.normalize(partial), limit)?
+        {
+            let completion = format!("{}{}", head, term);
+            if !suggestions
+                .iter()
+                .any(|s| s.to_lowercase() == completion.to_lowercase())
+            {
+                suggestions.push(completion);
+            }
+        }
+    }
+
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::fields::Field;
+    use crate::db;
+    use crate::db::documents::index;
+
+    #[test]
+    fn test_suggest() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        for (url, text) in [
+            ("http://a.example.com", "rust programming"),
+            ("http://b.example.com", "rust programs"),
+            ("http://c.example.com", "rusty programs"),
+        ] {
+            index(&connection, url, Field::Body, text);
+        }
+
+        assert_eq!(
+            vec!["rust", "rusty"],
+            suggest(&connection, "Ru", 5).unwrap()
+        );
+        assert_eq!(
+            vec!["rust programs", "rust programming"],
+            suggest(&connection, "rust  prog", 5).unwrap()
+        );
+        assert_eq!(
+            vec!["title:rust"],
+            suggest(&connection, "title:rust", 1).unwrap()
+        );
+
+        // Nothing is kept until recording is turned on
+        record(&connection, "rust cli").unwrap();
+        settings::set(&connection, MIN_QUERY_COUNT_KEY, "1").unwrap();
+        assert!(suggest(&connection, "rust c", 5).unwrap().is_empty());
+        settings::set(&connection, MIN_QUERY_COUNT_KEY, "3").unwrap();
+
+        settings::set(&connection, RECORD_QUERIES_KEY, "true").unwrap();
+        for _ in 0..3 {
+            record(&connection, "rust   book").unwrap();
+            record(&connection, "Rust web").unwrap();
+        }
+        record(&connection, "rust web").unwrap();
+        record(&connection, "rust private").unwrap();
+        record(&connection, &format!("rust {}", "a".repeat(100))).unwrap();
+        assert_eq!(
+            vec!["Rust web", "rust book", "rust", "rusty"],
+            suggest(&connection, "rust", 5).unwrap()
+        );
+
+        settings::set(&connection, MIN_QUERY_COUNT_KEY, "1").unwrap();
+        assert_eq!(
+            vec!["Rust web", "rust book", "rust private"],
+            suggest(&connection, "rust", 3).unwrap()
+        );
+
+        settings::set(&connection, RECORD_QUERIES_KEY, "false").unwrap();
+        record(&connection, "rust cli").unwrap();
+        assert!(suggest(&connection, "rust c", 5).unwrap().is_empty());
+        assert!(suggest(&connection, "  ", 5).unwrap().is_empty());
+    }
+}
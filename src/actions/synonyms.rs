@@ -0,0 +1,120 @@
+use anyhow::{Result, anyhow};
+use rusqlite::Connection;
+
+use crate::analysis::Analyzer;
+use crate::db::synonyms::{self, Synonym};
+
+/// Synonyms count for a little less than the words actually searched for.
+pub static DEFAULT_WEIGHT: f64 = 0.8;
+
+/// Turns a rule into the one-way synonyms it stands for. `js, javascript` makes every entry
+/// equivalent to every other and `k8s => kubernetes` only expands the left hand side into the
+/// right. Entries can be several words, `nyc, new york city`.
+pub fn parse_rule(analyzer: &Analyzer, rule: &str, weight: f64) -> Result<Vec<Synonym>> {
+    if !(weight > 0.0 && weight <= 1.0) {
+        return Err(anyhow!(
+            "Invalid synonym weight: {} - must be more than 0 and at most 1",
+            weight
+        ));
+    }
+
+    let entries = |side: &str| -> Result<Vec<String>> {
+        let entries: Vec<String> = side
+            .split(',')
+            .map(|e| analyzer.tokenize(e).join(" "))
+            .collect();
+        if entries.iter().any(|e| e.is_empty()) {
+            return Err(anyhow!("Invalid synonym rule: {} - empty entry", rule));
+        }
+        Ok(entries)
+    };
+
+    let pairs: Vec<(String, String)> = match rule.split_once("=>") {
+        Some((sources, targets)) => {
+            let targets = entries(targets)?;
+            entries(sources)?
+                .into_iter()
+                .flat_map(|s| targets.iter().map(move |t| (s.clone(), t.clone())))
+                .collect()
+        }
+        None => {
+            let entries = entries(rule)?;
+            if entries.len() < 2 {
+                return Err(anyhow!(
+                    "Invalid synonym rule: {} - needs at least two entries",
+                    rule
+                ));
+            }
+            entries
+                .iter()
+                .flat_map(|s| entries.iter().map(move |t| (s.clone(), t.clone())))
+                .collect()
+        }
+    };
+
+    Ok(pairs
+        .into_iter()
+        .filter(|(s, t)| s != t)
+        .map(|(s, t)| Synonym::new(&s, &t, weight))
+        .collect())
+}
+
+pub fn add(connection: &Connection, rule: &str, weight: f64) -> Result<()> {
+    let analyzer = Analyzer::load(connection)?;
+    for synonym in parse_rule(&analyzer, rule, weight)? {
+        synonyms::set(connection, &synonym)?;
+    }
+    Ok(())
+}
+
+/// Removes the synonyms a rule would have added.
+pub fn remove(connection: &Connection, rule: &str) -> Result<()> {
+    let analyzer = Analyzer::load(connection)?;
+    for synonym in parse_rule(&analyzer, rule, DEFAULT_WEIGHT)? {
+        synonyms::delete(connection, &synonym.source, &synonym.target)?;
+    }
+    Ok(())
+}
+
+pub fn list(connection: &Connection) -> Result<()> {
+    for synonym in synonyms::get_all(connection)? {
+        println!(
+            "{} => {}\t{}",
+            synonym.source, synonym.target, synonym.weight
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            vec![
+                Synonym::new("js", "javascript", 0.8),
+                Synonym::new("javascript", "js", 0.8),
+            ],
+            parse_rule(&analyzer, "JS, JavaScript", 0.8).unwrap()
+        );
+        assert_eq!(
+            vec![
+                Synonym::new("k8s", "kubernetes", 0.5),
+                Synonym::new("k8s", "kube", 0.5),
+            ],
+            parse_rule(&analyzer, "k8s => Kubernetes, kube", 0.5).unwrap()
+        );
+        assert_eq!(
+            vec![Synonym::new("nyc", "new york city", 1.0)],
+            parse_rule(&analyzer, "nyc => New York City", 1.0).unwrap()
+        );
+
+        assert!(parse_rule(&analyzer, "js", 0.8).is_err());
+        assert!(parse_rule(&analyzer, "js, ", 0.8).is_err());
+        assert!(parse_rule(&analyzer, "=> js", 0.8).is_err());
+        assert!(parse_rule(&analyzer, "js, javascript", 1.5).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+/// Characters from scripts that are written without spaces between words.
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'     // Hangul Jamo
+        | '\u{2E80}'..='\u{2FDF}'   // CJK and Kangxi radicals
+        | '\u{3005}'..='\u{3007}'   // Iteration mark, closing mark, ideographic zero
+        | '\u{3040}'..='\u{30FF}'   // Hiragana and Katakana
+        | '\u{3130}'..='\u{318F}'   // Hangul compatibility Jamo
+        | '\u{31F0}'..='\u{31FF}'   // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK unified ideographs extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK unified ideographs
+        | '\u{A960}'..='\u{A97F}'   // Hangul Jamo extended A
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK compatibility ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth Katakana
+        | '\u{FFA0}'..='\u{FFDC}'   // Halfwidth Hangul
+        | '\u{20000}'..='\u{3134F}' // CJK unified ideographs extensions B through G
+    )
+}
+
+/// Punctuation used in CJK text, these break up runs of characters the same way whitespace
+/// would.
+pub fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c,
+        '\u{3001}'..='\u{3004}'
+        | '\u{3008}'..='\u{3011}'
+        | '\u{3014}'..='\u{301F}'
+        | '\u{30FB}'
+        | '\u{FF01}'..='\u{FF0F}'
+        | '\u{FF1A}'..='\u{FF20}'
+        | '\u{FF3B}'..='\u{FF40}'
+        | '\u{FF5B}'..='\u{FF65}'
+    )
+}
+
+/// Splits a single whitespace delimited word into terms.
+///
+/// Chinese, Japanese and Korean text is written without spaces between words so splitting on
+/// whitespace leaves whole sentences as a single term. Rather than carrying a dictionary around
+/// for each language we index runs of these characters as overlapping bigrams, "東京タワー"
+/// becomes ["東京", "京タ", "タワ", "ワー"], and apply the same treatment to queries so that any
+/// two adjacent characters can be matched. A run of one character is kept as a unigram and
+/// anything that isn't CJK is passed through as is.
+pub fn segment(word: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut other = String::new();
+    let mut run: Vec<char> = Vec::new();
+
+    for c in word.chars() {
+        if is_cjk_punctuation(c) {
+            flush_other(&mut other, &mut terms);
+            flush_run(&mut run, &mut terms);
+        } else if is_cjk(c) {
+            flush_other(&mut other, &mut terms);
+            run.push(c);
+        } else {
+            flush_run(&mut run, &mut terms);
+            other.push(c);
+        }
+    }
+
+    flush_other(&mut other, &mut terms);
+    flush_run(&mut run, &mut terms);
+
+    terms
+}
+
+fn flush_other(other: &mut String, terms: &mut Vec<String>) {
+    if !other.is_empty() {
+        terms.push(std::mem::take(other));
+    }
+}
+
+fn flush_run(run: &mut Vec<char>, terms: &mut Vec<String>) {
+    match run.len() {
+        0 => return,
+        1 => terms.push(run[0].to_string()),
+        _ => terms.extend(run.windows(2).map(|w| w.iter().collect::<String>())),
+    }
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_bigrams() {
+        assert_eq!(vec!["東京", "京タ", "タワ", "ワー"], segment("東京タワー"));
+        assert_eq!(vec!["한국", "국어"], segment("한국어"));
+        assert_eq!(vec!["中"], segment("中"));
+    }
+
+    #[test]
+    fn test_segment_mixed_scripts() {
+        assert_eq!(vec!["iphone", "発売"], segment("iphone発売"));
+        assert_eq!(vec!["rust", "言語", "v2"], segment("rust言語v2"));
+    }
+
+    #[test]
+    fn test_segment_punctuation() {
+        assert_eq!(
+            vec!["今日", "日は", "晴れ", "明日", "日は", "雨"],
+            segment("今日は、晴れ。明日は「雨」")
+        );
+    }
+
+    #[test]
+    fn test_segment_latin() {
+        assert_eq!(vec!["alpha"], segment("alpha"));
+        assert_eq!(Vec::<String>::new(), segment(""));
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{Result, anyhow};
+use chrono::DateTime;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::analysis::html::Page;
+use crate::analysis::xml::Element;
+
+/// The types `<link rel="alternate">` gives feeds, for finding them from a page.
+pub static FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+    "application/json",
+];
+
+/// An entry of a feed, the post it links to along with what the feed says about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub url: String,
+    pub title: String,
+    /// As text, feeds often give it as html.
+    pub summary: String,
+    /// When the entry was last updated, or published if the feed doesn't say.
+    pub updated: Option<i64>,
+}
+
+/// An RSS (2.0 or 1.0), Atom or JSON feed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub title: String,
+    pub items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeed {
+    title: Option<String>,
+    #[serde(default)]
+    items: Vec<JsonItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content_text: Option<String>,
+    content_html: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+}
+
+impl Feed {
+    /// Relative links are resolved against the url the feed was fetched from, entries without a
+    /// link are left out.
+    pub fn parse(input: &str, url: &str) -> Result<Self> {
+        let base = Url::parse(url)?;
+        if input.trim_start().starts_with('{') {
+            return Self::parse_json(input, &base);
+        }
+
+        let root = Element::parse(input)?;
+        match root.name.as_str() {
+            "rss" => {
+                let channel = root
+                    .child("channel")
+                    .ok_or(anyhow!("no channel in rss feed"))?;
+                Ok(Self::parse_rss(channel, channel, &base))
+            }
+            // RSS 1.0 has its items next to the channel rather than in it
+            "rdf:RDF" => {
+                let channel = root
+                    .child("channel")
+                    .ok_or(anyhow!("no channel in rss feed"))?;
+                Ok(Self::parse_rss(channel, &root, &base))
+            }
+            "feed" => Ok(Self::parse_atom(&root, &base)),
+            name => Err(anyhow!("not a feed: <{}>", name)),
+        }
+    }
+
+    fn parse_rss(channel: &Element, items: &Element, base: &Url) -> Self {
+        let items = items
+            .children_named("item")
+            .filter_map(|i| {
+                let link = i.child_text("link").or_else(|| {
+                    // A guid is the entry's link unless it says it isn't
+                    let guid = i.child("guid")?;
+                    (guid.attribute("isPermaLink") != Some("false")).then(|| guid.text())
+                })?;
+                Some(Item {
+                    url: resolve(base, &link)?,
+                    title: text(&i.child_text("title").unwrap_or_default()),
+                    summary: text(&i.child_text("description").unwrap_or_default()),
+                    updated: i
+                        .child_text("pubDate")
+                        .and_then(|d| DateTime::parse_from_rfc2822(&d).ok())
+                        .or_else(|| DateTime::parse_from_rfc3339(&i.child_text("dc:date")?).ok())
+                        .map(|d| d.timestamp()),
+                })
+            })
+            .collect();
+        Self {
+            title: text(&channel.child_text("title").unwrap_or_default()),
+            items,
+        }
+    }
+
+    fn parse_atom(feed: &Element, base: &Url) -> Self {
+        let date = |e: &Element, name: &str| {
+            DateTime::parse_from_rfc3339(&e.child_text(name)?)
+                .ok()
+                .map(|d| d.timestamp())
+        };
+        let items = feed
+            .children_named("entry")
+            .filter_map(|e| {
+                let link = e
+                    .children_named("link")
+                    .find(|l| l.attribute("rel").is_none_or(|r| r == "alternate"))?
+                    .attribute("href")?;
+                Some(Item {
+                    url: resolve(base, link)?,
+                    title: text(&e.child_text("title").unwrap_or_default()),
+                    summary: text(
+                        &e.child_text("summary")
+                            .or_else(|| e.child_text("content"))
+                            .unwrap_or_default(),
+                    ),
+                    updated: date(e, "updated").or_else(|| date(e, "published")),
+                })
+            })
+            .collect();
+        Self {
+            title: text(&feed.child_text("title").unwrap_or_default()),
+            items,
+        }
+    }
+
+    fn parse_json(input: &str, base: &Url) -> Result<Self> {
+        let feed: JsonFeed = serde_json::from_str(input)?;
+        let date = |d: &Option<String>| {
+            DateTime::parse_from_rfc3339(d.as_deref()?)
+                .ok()
+                .map(|d| d.timestamp())
+        };
+        let items = feed
+            .items
+            .into_iter()
+            .filter_map(|i| {
+                Some(Item {
+                    url: resolve(base, i.url.as_ref().or(i.external_url.as_ref())?)?,
+                    title: text(&i.title.unwrap_or_default()),
+                    summary: text(
+                        &i.summary
+                            .or(i.content_text)
+                            .or(i.content_html)
+                            .unwrap_or_default(),
+                    ),
+                    updated: date(&i.date_modified).or_else(|| date(&i.date_published)),
+                })
+            })
+            .collect();
+        Ok(Self {
+            title: feed.title.unwrap_or_default(),
+            items,
+        })
+    }
+}
+
+fn resolve(base: &Url, link: &str) -> Option<String> {
+    let url = base.join(link.trim()).ok()?;
+    (url.scheme() == "http" || url.scheme() == "https").then(|| url.into())
+}
+
+/// Titles and summaries can be html, only their text is kept.
+fn text(html: &str) -> String {
+    let page = Page::from_html(html, "");
+    page.body.split('\n').collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss() {
+        let input = r#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+              <channel>
+                <title>Example Blog</title>
+                <link>https://example.com/</link>
+                <atom:link href="https://example.com/feed.xml" rel="self" />
+                <item>
+                  <title>First &amp; best</title>
+                  <link>https://example.com/posts/first</link>
+                  <description>&lt;p&gt;Hello &lt;b&gt;world&lt;/b&gt;&lt;/p&gt;</description>
+                  <pubDate>Fri, 01 Mar 2024 12:00:00 GMT</pubDate>
+                </item>
+                <item>
+                  <title>Second</title>
+                  <guid>/posts/second</guid>
+                  <description><![CDATA[Plain <i>text</i>]]></description>
+                </item>
+                <item>
+                  <title>No link</title>
+                  <guid isPermaLink="false">tag:example.com,2024:3</guid>
+                </item>
+              </channel>
+            </rss>"#;
+        assert_eq!(
+            Feed {
+                title: "Example Blog".to_string(),
+                items: vec![
+                    Item {
+                        url: "https://example.com/posts/first".to_string(),
+                        title: "First & best".to_string(),
+                        summary: "Hello world".to_string(),
+                        updated: Some(1709294400),
+                    },
+                    Item {
+                        url: "https://example.com/posts/second".to_string(),
+                        title: "Second".to_string(),
+                        summary: "Plain text".to_string(),
+                        updated: None,
+                    },
+                ],
+            },
+            Feed::parse(input, "https://example.com/feed.xml").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let input = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <title type="text">Example Blog</title>
+              <link href="https://example.com/atom.xml" rel="self" />
+              <entry>
+                <title>First</title>
+                <link rel="edit" href="https://example.com/edit/1" />
+                <link rel="alternate" type="text/html" href="/posts/first" />
+                <published>2024-03-01T12:00:00Z</published>
+                <updated>2024-03-02T12:00:00Z</updated>
+                <content type="html">&lt;p&gt;Hello&lt;/p&gt;</content>
+              </entry>
+              <entry>
+                <title>Second</title>
+                <link href="https://example.com/posts/second" />
+                <summary>Short</summary>
+                <published>2024-03-01T12:00:00+01:00</published>
+              </entry>
+            </feed>"#;
+        assert_eq!(
+            Feed {
+                title: "Example Blog".to_string(),
+                items: vec![
+                    Item {
+                        url: "https://example.com/posts/first".to_string(),
+                        title: "First".to_string(),
+                        summary: "Hello".to_string(),
+                        updated: Some(1709380800),
+                    },
+                    Item {
+                        url: "https://example.com/posts/second".to_string(),
+                        title: "Second".to_string(),
+                        summary: "Short".to_string(),
+                        updated: Some(1709290800),
+                    },
+                ],
+            },
+            Feed::parse(input, "https://example.com/atom.xml").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let input = r#"{
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": "Example Blog",
+            "items": [
+                {
+                    "id": "1",
+                    "url": "https://example.com/posts/first",
+                    "title": "First",
+                    "content_html": "<p>Hello</p>",
+                    "date_published": "2024-03-01T12:00:00Z"
+                },
+                {
+                    "id": "2",
+                    "external_url": "https://elsewhere.com/story",
+                    "summary": "Worth reading"
+                },
+                { "id": "3", "content_text": "A note without a link" }
+            ]
+        }"#;
+        assert_eq!(
+            Feed {
+                title: "Example Blog".to_string(),
+                items: vec![
+                    Item {
+                        url: "https://example.com/posts/first".to_string(),
+                        title: "First".to_string(),
+                        summary: "Hello".to_string(),
+                        updated: Some(1709294400),
+                    },
+                    Item {
+                        url: "https://elsewhere.com/story".to_string(),
+                        title: "".to_string(),
+                        summary: "Worth reading".to_string(),
+                        updated: None,
+                    },
+                ],
+            },
+            Feed::parse(input, "https://example.com/feed.json").unwrap()
+        );
+
+        assert!(Feed::parse("<html></html>", "https://example.com/").is_err());
+        assert!(Feed::parse("{ not json", "https://example.com/").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use rusqlite::Connection;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::Serialize;
+
+use crate::db::settings;
+
+/// The parts of a document which are indexed independently of each other so that a match in
+/// one can be worth more than a match in another.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Field {
+    Title,
+    Headings,
+    Body,
+    Url,
+    Description,
+    /// The text of links on other pages which point at this document.
+    Anchor,
+}
+
+impl Field {
+    pub const ALL: [Field; 6] = [
+        Field::Title,
+        Field::Headings,
+        Field::Body,
+        Field::Url,
+        Field::Description,
+        Field::Anchor,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Headings => "headings",
+            Field::Body => "body",
+            Field::Url => "url",
+            Field::Description => "description",
+            Field::Anchor => "anchor",
+        }
+    }
+
+    pub fn default_boost(&self) -> f64 {
+        match self {
+            Field::Title => 4.0,
+            Field::Headings => 2.0,
+            Field::Body => 1.0,
+            Field::Url => 2.0,
+            Field::Description => 1.5,
+            Field::Anchor => 3.0,
+        }
+    }
+}
+
+impl TryFrom<&str> for Field {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, std::string::String> {
+        Field::ALL
+            .into_iter()
+            .find(|f| f.as_str() == value)
+            .ok_or(format!("Invalid field: {}", value))
+    }
+}
+
+impl FromSql for Field {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| match Field::try_from(s) {
+            Ok(f) => Ok(f),
+            Err(_) => Err(FromSqlError::InvalidType),
+        })
+    }
+}
+
+impl ToSql for Field {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Turns a url into text the analyzer can split into terms, dropping the scheme and breaking
+/// the rest apart on anything that isn't a letter or a number.
+pub fn url_text(url: &str) -> String {
+    let rest = match url.split_once("://") {
+        Some((_scheme, rest)) => rest,
+        None => url,
+    };
+
+    rest.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect()
+}
+
+/// How much a match in each field is worth relative to a match in the body. These are stored
+/// with the index under `boost.<field>` so they can be tuned without a rebuild.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldBoosts {
+    boosts: BTreeMap<Field, f64>,
+}
+
+impl Default for FieldBoosts {
+    fn default() -> Self {
+        Self {
+            boosts: Field::ALL
+                .into_iter()
+                .map(|f| (f, f.default_boost()))
+                .collect(),
+        }
+    }
+}
+
+impl FieldBoosts {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut boosts = Self::default();
+        for f in Field::ALL {
+            if let Some(v) = settings::get(connection, &Self::key(f))? {
+                boosts.boosts.insert(f, v.parse()?);
+            }
+        }
+        Ok(boosts)
+    }
+
+    pub fn key(field: Field) -> String {
+        format!("boost.{}", field.as_str())
+    }
+
+    pub fn get(&self, field: Field) -> f64 {
+        self.boosts
+            .get(&field)
+            .copied()
+            .unwrap_or(field.default_boost())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_text() {
+        assert_eq!(
+            "www example com rust lang blog ",
+            url_text("https://www.example.com/rust-lang/blog/")
+        );
+    }
+
+    #[test]
+    fn test_load_boosts() {
+        let connection = Connection::open_in_memory().unwrap();
+        settings::create_table(&connection).unwrap();
+        settings::set(&connection, "boost.title", "10").unwrap();
+
+        let boosts = FieldBoosts::load(&connection).unwrap();
+        assert_eq!(10.0, boosts.get(Field::Title));
+        assert_eq!(1.0, boosts.get(Field::Body));
+    }
+}
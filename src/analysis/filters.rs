@@ -0,0 +1,98 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Compatibility normalization so that the many ways of writing the same character, full width
+/// latin letters, ligatures, half width katakana, precomposed vs combining accents, all end up as
+/// the same sequence of code points.
+pub fn nfkc(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+/// Full case folding. `to_lowercase` gets us most of the way there but leaves a handful of
+/// characters that fold to something other than their lowercase form.
+pub fn case_fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for c in text.to_lowercase().chars() {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ϐ' => folded.push('β'),
+            'ϑ' => folded.push('θ'),
+            'ϕ' => folded.push('φ'),
+            'ϖ' => folded.push('π'),
+            'ϰ' => folded.push('κ'),
+            'ϱ' => folded.push('ρ'),
+            'ϵ' => folded.push('ε'),
+            'ſ' => folded.push('s'),
+            'ẛ' => folded.push('ṡ'),
+            _ => folded.push(c),
+        }
+    }
+
+    folded
+}
+
+/// Combining diacritics as used by latin, greek and cyrillic text. We deliberately don't strip
+/// every combining mark, the Japanese voicing marks and the vowel signs of Indic scripts change
+/// the meaning of a word rather than decorating it.
+fn is_diacritic(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// Strips accents so "café" and "cafe" are the same term. Letters which don't decompose into a
+/// base letter plus an accent are mapped by hand.
+pub fn fold_accents(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for c in text.nfd().filter(|c| !is_diacritic(*c)) {
+        match c {
+            'ø' => folded.push('o'),
+            'đ' | 'ð' => folded.push('d'),
+            'ł' => folded.push('l'),
+            'ħ' => folded.push('h'),
+            'ı' => folded.push('i'),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'þ' => folded.push_str("th"),
+            _ => folded.push(c),
+        }
+    }
+
+    folded.nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfkc() {
+        assert_eq!("ABC", nfkc("ＡＢＣ"));
+        assert_eq!("fi", nfkc("ﬁ"));
+        assert_eq!("カタカナ", nfkc("ｶﾀｶﾅ"));
+        assert_eq!("café", nfkc("cafe\u{0301}"));
+    }
+
+    #[test]
+    fn test_case_fold() {
+        assert_eq!("strasse", case_fold("STRAẞE"));
+        assert_eq!("strasse", case_fold("straße"));
+        assert_eq!("σοφοσ", case_fold("ΣΟΦΟΣ"));
+    }
+
+    #[test]
+    fn test_fold_accents() {
+        assert_eq!("cafe", fold_accents("café"));
+        assert_eq!("cafe", fold_accents("cafe\u{0301}"));
+        assert_eq!("naive", fold_accents("naïve"));
+        assert_eq!("lodz", fold_accents("łódź"));
+        assert_eq!("が", fold_accents("が"));
+        assert_eq!("한국어", fold_accents("한국어"));
+    }
+}
@@ -0,0 +1,337 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::analysis::feed::FEED_TYPES;
+
+/// Elements whose text isn't meant to be read.
+static SKIPPED_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "head"];
+
+/// The name our crawler answers to in robots directives addressed to a single crawler.
+pub static ROBOT_NAME: &str = "nvgs";
+
+/// Directives which take a value after a colon, which could otherwise be mistaken for the name
+/// of the crawler a directive is addressed to.
+static VALUED_DIRECTIVES: &[&str] = &[
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+/// What a page asks crawlers to do with it, from `<meta name="robots">` and the `X-Robots-Tag`
+/// header.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Robots {
+    /// Keep the page out of the index.
+    pub noindex: bool,
+    /// Don't follow the links on the page.
+    pub nofollow: bool,
+}
+
+impl Robots {
+    /// Adds the directives of a comma separated list like `noindex, nofollow`.
+    pub fn add_directives(&mut self, directives: &str) {
+        for d in directives.split(',').map(|d| d.trim().to_lowercase()) {
+            match d.as_str() {
+                "noindex" => self.noindex = true,
+                "nofollow" => self.nofollow = true,
+                "none" => {
+                    self.noindex = true;
+                    self.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Adds an `X-Robots-Tag` value, which can be addressed to a single crawler with a prefix
+    /// like `googlebot: noindex`. Only those for every crawler or for us count.
+    pub fn add_header(&mut self, value: &str) {
+        let directives = match value.split_once(':') {
+            Some((agent, rest))
+                if !agent.contains(',')
+                    && !VALUED_DIRECTIVES.contains(&agent.trim().to_lowercase().as_str()) =>
+            {
+                if !agent.trim().eq_ignore_ascii_case(ROBOT_NAME) {
+                    return;
+                }
+                rest
+            }
+            _ => value,
+        };
+        self.add_directives(directives);
+    }
+}
+
+/// A link found on a page, resolved against the url of the page it was found on.
+#[derive(Debug, PartialEq)]
+pub struct Anchor {
+    pub href: String,
+    pub text: String,
+}
+
+/// The text of a document broken out into the fields we index separately.
+#[derive(Debug, Default, PartialEq)]
+pub struct Page {
+    pub title: String,
+    pub headings: String,
+    pub description: String,
+    pub body: String,
+    pub anchors: Vec<Anchor>,
+    /// The primary subtag of the language the page declares, "en" for `<html lang="en-GB">`.
+    pub language: Option<String>,
+    /// The url the page says is the original of it, `<link rel="canonical">`.
+    pub canonical: Option<String>,
+    /// The feeds the page links to with `<link rel="alternate">`.
+    pub feeds: Vec<String>,
+    pub robots: Robots,
+}
+
+impl Page {
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            body: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_html(html: &str, url: &str) -> Self {
+        let document = Html::parse_document(html);
+
+        let title_selector = Selector::parse("title").unwrap();
+        let headings_selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+        let meta_selector = Selector::parse("meta").unwrap();
+        let body_selector = Selector::parse("body").unwrap();
+        let anchor_selector = Selector::parse("a[href]").unwrap();
+        let image_selector = Selector::parse("img[alt]").unwrap();
+        let canonical_selector = Selector::parse("link[rel~=canonical][href]").unwrap();
+        let feed_selector = Selector::parse("link[rel~=alternate][href][type]").unwrap();
+
+        let title = document
+            .select(&title_selector)
+            .next()
+            .map(|e| join_words(e.text()))
+            .unwrap_or_default();
+
+        let headings: Vec<String> = document
+            .select(&headings_selector)
+            .map(|e| join_words(e.text()))
+            .filter(|h| !h.is_empty())
+            .collect();
+
+        let description = document
+            .select(&meta_selector)
+            .find(|e| {
+                e.value()
+                    .attr("name")
+                    .is_some_and(|n| n.eq_ignore_ascii_case("description"))
+            })
+            .and_then(|e| e.value().attr("content"))
+            .map(|c| join_words([c].into_iter()))
+            .unwrap_or_default();
+
+        let mut robots = Robots::default();
+        for e in document.select(&meta_selector) {
+            let name = e.value().attr("name").unwrap_or_default();
+            if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(ROBOT_NAME) {
+                robots.add_directives(e.value().attr("content").unwrap_or_default());
+            }
+        }
+
+        let base = Url::parse(url).ok();
+        let canonical = document
+            .select(&canonical_selector)
+            .next()
+            .and_then(|e| resolve(base.as_ref()?, e.value().attr("href")?));
+
+        let feeds = document
+            .select(&feed_selector)
+            .filter(|e| {
+                let feed_type = e.value().attr("type").unwrap_or_default();
+                FEED_TYPES.contains(&feed_type.trim().to_lowercase().as_str())
+            })
+            .filter_map(|e| resolve(base.as_ref()?, e.value().attr("href")?))
+            .collect();
+
+        let anchors = document
+            .select(&anchor_selector)
+            .filter_map(|e| {
+                // Links the page doesn't vouch for
+                let rel = e.value().attr("rel").unwrap_or_default();
+                if rel
+                    .split_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("nofollow"))
+                {
+                    return None;
+                }
+                let href = resolve(base.as_ref()?, e.value().attr("href")?)?;
+
+                // Image links are described by their alt text
+                let mut text = join_words(e.text());
+                if text.is_empty() {
+                    text = join_words(
+                        e.select(&image_selector)
+                            .filter_map(|i| i.value().attr("alt")),
+                    );
+                }
+
+                Some(Anchor { href, text })
+            })
+            .collect();
+
+        let language = document
+            .root_element()
+            .value()
+            .attr("lang")
+            .and_then(|l| l.split(['-', '_']).next())
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.is_empty());
+
+        let mut lines = Vec::new();
+        let root = document
+            .select(&body_selector)
+            .next()
+            .unwrap_or(document.root_element());
+        collect_lines(root, &mut lines);
+
+        Self {
+            title,
+            headings: headings.join("\n"),
+            description,
+            body: lines.join("\n"),
+            anchors,
+            language,
+            canonical,
+            feeds,
+            robots,
+        }
+    }
+}
+
+/// Resolves a possibly relative link to an absolute url without its fragment, ignoring anything
+/// that isn't fetched over http.
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    let mut url = base.join(href).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    url.set_fragment(None);
+    Some(url.into())
+}
+
+/// Collapses runs of whitespace, including newlines, down to single spaces.
+fn join_words<'a>(text: impl Iterator<Item = &'a str>) -> String {
+    let words: Vec<&str> = text.flat_map(|t| t.split_whitespace()).collect();
+    words.join(" ")
+}
+
+/// Each text node becomes a line of its own.
+fn collect_lines(element: ElementRef, lines: &mut Vec<String>) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                let line = join_words([&text[..]].into_iter());
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+            }
+            Node::Element(e) if !SKIPPED_ELEMENTS.contains(&e.name()) => {
+                if let Some(child_element) = ElementRef::wrap(child) {
+                    collect_lines(child_element, lines);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_html() {
+        let html = r#"
+            <html lang="en-GB">
+                <head>
+                    <title>  Not Very
+                        Good Search</title>
+                    <meta name="Description" content="A search engine">
+                    <meta name="robots" content="NoFollow">
+                    <link rel="canonical" href="/">
+                    <link rel="alternate" type="application/rss+xml" href="/feed.xml">
+                    <link rel="alternate" hreflang="fr" href="/fr/">
+                    <style>body { color: red; }</style>
+                </head>
+                <body>
+                    <h1>Welcome</h1>
+                    <p>Hello   <b>world</b></p>
+                    <script>let x = 1;</script>
+                    <h2>About</h2>
+                    <a href="/about#team">About   us</a>
+                    <a href="https://other.example.com/"><img alt="Other site"></a>
+                    <a href="mailto:nvgs@example.com">Email</a>
+                    <a href="/ad" rel="sponsored nofollow">Ad</a>
+                </body>
+            </html>
+        "#;
+        assert_eq!(
+            Page {
+                title: "Not Very Good Search".to_string(),
+                headings: "Welcome\nAbout".to_string(),
+                description: "A search engine".to_string(),
+                body: "Welcome\nHello\nworld\nAbout\nAbout us\nEmail\nAd".to_string(),
+                anchors: vec![
+                    Anchor {
+                        href: "http://www.example.com/about".to_string(),
+                        text: "About us".to_string(),
+                    },
+                    Anchor {
+                        href: "https://other.example.com/".to_string(),
+                        text: "Other site".to_string(),
+                    },
+                ],
+                language: Some("en".to_string()),
+                canonical: Some("http://www.example.com/".to_string()),
+                feeds: vec!["http://www.example.com/feed.xml".to_string()],
+                robots: Robots {
+                    noindex: false,
+                    nofollow: true,
+                },
+            },
+            Page::from_html(html, "http://www.example.com/index.html")
+        );
+    }
+
+    #[test]
+    fn test_robots_header() {
+        let robots = |values: &[&str]| {
+            let mut robots = Robots::default();
+            for v in values {
+                robots.add_header(v);
+            }
+            robots
+        };
+        assert_eq!(
+            Robots {
+                noindex: true,
+                nofollow: true
+            },
+            robots(&["none"])
+        );
+        assert_eq!(
+            Robots {
+                noindex: true,
+                nofollow: false
+            },
+            robots(&["googlebot: nofollow", "NVGS: noindex"])
+        );
+        assert_eq!(
+            Robots {
+                noindex: false,
+                nofollow: true
+            },
+            robots(&["unavailable_after: 2025-01-01, nofollow"])
+        );
+    }
+}
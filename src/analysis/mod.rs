@@ -0,0 +1,117 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::db::settings;
+
+pub mod cjk;
+pub mod feed;
+pub mod fields;
+pub mod filters;
+pub mod html;
+pub mod simhash;
+pub mod sitemap;
+pub mod snippets;
+pub mod urls;
+pub mod xml;
+
+static PUNCTUATION: &[char] = &[
+    '(', ')', ',', '\"', '.', ';', ':', '\'', '-', '~', '+', '=', '$', '%', '^', '*', '?',
+];
+
+static FOLD_ACCENTS_KEY: &str = "analysis.fold_accents";
+
+/// Turns runs of text into the list of terms we index, in the order they appear. Documents and
+/// queries must both go through the same analyzer so that they agree on what a term looks like,
+/// which is why its configuration is stored with the index rather than passed on the command
+/// line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analyzer {
+    pub fold_accents: bool,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self { fold_accents: true }
+    }
+}
+
+impl Analyzer {
+    pub fn new(fold_accents: bool) -> Self {
+        Self { fold_accents }
+    }
+
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut analyzer = Self::default();
+        if let Some(v) = settings::get(connection, FOLD_ACCENTS_KEY)? {
+            analyzer.fold_accents = v == "true";
+        }
+        Ok(analyzer)
+    }
+
+    pub fn save(&self, connection: &Connection) -> Result<()> {
+        settings::set(connection, FOLD_ACCENTS_KEY, &self.fold_accents.to_string())
+    }
+
+    /// Applies the character level filters: NFKC, case folding and optionally accent folding.
+    pub fn normalize(&self, text: &str) -> String {
+        let normalized = filters::case_fold(&filters::nfkc(text));
+        if self.fold_accents {
+            filters::fold_accents(&normalized)
+        } else {
+            normalized
+        }
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+
+        for word in self.normalize(text).split_whitespace() {
+            // Strip punctuation
+            let w = word.replace(PUNCTUATION, "");
+            terms.extend(cjk::segment(&w));
+        }
+
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            vec!["alpha", "bravo", "charlie"],
+            analyzer.tokenize("Alpha (Bravo),\tcharlie.")
+        );
+        assert_eq!(
+            vec!["検索", "索エ", "エン", "ンジ", "ジン", "nvgs"],
+            analyzer.tokenize("検索エンジン NVGS")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_normalizes() {
+        let analyzer = Analyzer::default();
+        let expected = vec!["cafe"];
+        assert_eq!(expected, analyzer.tokenize("café"));
+        assert_eq!(expected, analyzer.tokenize("cafe"));
+        assert_eq!(expected, analyzer.tokenize("CAFE\u{0301}"));
+        assert_eq!(vec!["nvgs"], analyzer.tokenize("ＮＶＧＳ"));
+
+        let analyzer = Analyzer::new(false);
+        assert_eq!(vec!["café"], analyzer.tokenize("CAFE\u{0301}"));
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let connection = Connection::open_in_memory().unwrap();
+        settings::create_table(&connection).unwrap();
+        assert_eq!(Analyzer::default(), Analyzer::load(&connection).unwrap());
+
+        Analyzer::new(false).save(&connection).unwrap();
+        assert_eq!(Analyzer::new(false), Analyzer::load(&connection).unwrap());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::db::term_frequencies::TermFrequency;
+
+/// Words per shingle, runs of words rather than single words so that the order counts.
+static SHINGLE_WORDS: usize = 3;
+
+/// Texts shorter than this don't get a fingerprint. A handful of shingles is too few for their
+/// bits to even out, short pages with nothing in common would end up next to each other.
+pub static MIN_WORDS: usize = 20;
+
+/// FNV-1a, hashes have to stay the same from one build to the next since fingerprints are stored
+/// with the index.
+fn hash(words: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (i, w) in words.iter().enumerate() {
+        if i > 0 {
+            hash = (hash ^ b' ' as u64).wrapping_mul(0x100000001b3);
+        }
+        for b in w.bytes() {
+            hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// A 64 bit SimHash of the shingles of a text. Texts which are mostly the same get fingerprints
+/// differing in only a few bits. Nothing for a text of fewer than `MIN_WORDS` words.
+pub fn fingerprint(words: &[&str]) -> Option<u64> {
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut counts = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let h = hash(shingle);
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if h & (1 << bit) != 0 {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+
+    Some(
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .fold(0, |f, (bit, _)| f | (1 << bit)),
+    )
+}
+
+/// The number of bits two fingerprints differ in.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// The words of one field of a document in order, put back together from their positions.
+pub fn words<'a>(terms: impl IntoIterator<Item = &'a TermFrequency>) -> Vec<&'a str> {
+    let mut positioned: Vec<(u32, &str)> = terms
+        .into_iter()
+        .flat_map(|tf| tf.positions.iter().map(|p| (*p, tf.term.as_str())))
+        .collect();
+    positioned.sort();
+    positioned.into_iter().map(|(_, w)| w).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let text = "mirrors syndicated copies and print versions of articles all get indexed as \
+                    separate documents which we would rather show once with a note saying how \
+                    many similar pages there are";
+        let words: Vec<&str> = text.split(' ').collect();
+        let original = fingerprint(&words).unwrap();
+
+        let mut edited = words.clone();
+        edited[10] = "crawled";
+        let edited = fingerprint(&edited).unwrap();
+
+        let other: Vec<&str> = "an entirely different page about growing tomatoes in the \
+                                garden over the summer with plenty of water and sunshine \
+                                every day"
+            .split(' ')
+            .collect();
+        let other = fingerprint(&other).unwrap();
+
+        assert_eq!(original, fingerprint(&words).unwrap());
+        assert!(distance(original, edited) <= 10);
+        assert!(distance(original, other) > 10);
+        assert_eq!(None, fingerprint(&[]));
+    }
+
+    #[test]
+    fn test_fingerprint_short() {
+        // Short pages with nothing in common would otherwise land within a few bits of each other
+        for text in ["page not found", "contact us", "log in"] {
+            let words: Vec<&str> = text.split(' ').collect();
+            assert_eq!(None, fingerprint(&words));
+        }
+    }
+}
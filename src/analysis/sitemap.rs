@@ -0,0 +1,159 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate};
+
+use crate::analysis::xml::Element;
+use crate::db::crawls::ChangeFrequency;
+
+/// A page listed in a sitemap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub url: String,
+    pub last_modified: Option<i64>,
+    pub change_frequency: Option<ChangeFrequency>,
+}
+
+/// Either the pages of a site or, for sites with more pages than fit in one, an index of other
+/// sitemaps.
+#[derive(Debug, PartialEq)]
+pub enum Sitemap {
+    Index(Vec<String>),
+    Pages(Vec<Entry>),
+}
+
+impl Sitemap {
+    pub fn parse(input: &str) -> Result<Self> {
+        let root = Element::parse(input)?;
+        match root.name.as_str() {
+            "sitemapindex" => Ok(Sitemap::Index(
+                root.children_named("sitemap")
+                    .filter_map(|s| s.child_text("loc"))
+                    .collect(),
+            )),
+            "urlset" => Ok(Sitemap::Pages(
+                root.children_named("url")
+                    .filter_map(|u| {
+                        Some(Entry {
+                            url: u.child_text("loc")?,
+                            last_modified: u
+                                .child_text("lastmod")
+                                .and_then(|m| parse_last_modified(&m)),
+                            change_frequency: u.child_text("changefreq").and_then(|f| {
+                                ChangeFrequency::try_from(f.to_lowercase().as_str()).ok()
+                            }),
+                        })
+                    })
+                    .collect(),
+            )),
+            name => Err(anyhow!("not a sitemap: <{}>", name)),
+        }
+    }
+}
+
+/// `lastmod` is a W3C datetime, a date on its own or with a time and offset.
+fn parse_last_modified(input: &str) -> Option<i64> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(input) {
+        return Some(t.timestamp());
+    }
+    // Seconds are optional
+    if let Ok(t) = DateTime::parse_from_str(input, "%Y-%m-%dT%H:%M%:z") {
+        return Some(t.timestamp());
+    }
+    if let Ok(t) = DateTime::parse_from_str(&input.replace('Z', "+00:00"), "%Y-%m-%dT%H:%M%:z") {
+        return Some(t.timestamp());
+    }
+    let date = NaiveDate::parse_from_str(input, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+}
+
+/// The sitemaps a robots.txt points at with `Sitemap:` lines.
+pub fn from_robots(robots: &str) -> Vec<String> {
+    robots
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let (field, value) = line.split_once(':')?;
+            if !field.trim().eq_ignore_ascii_case("sitemap") {
+                return None;
+            }
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url>
+                <loc>https://example.com/</loc>
+                <lastmod>2024-03-01</lastmod>
+                <changefreq>Daily</changefreq>
+              </url>
+              <url>
+                <loc>https://example.com/about</loc>
+                <lastmod>2024-03-01T12:30:00+01:00</lastmod>
+                <changefreq>sometimes</changefreq>
+              </url>
+              <url>
+                <loc>https://example.com/news</loc>
+                <lastmod>2024-03-01T12:30Z</lastmod>
+              </url>
+              <url><lastmod>2024-03-01</lastmod></url>
+            </urlset>"#;
+        assert_eq!(
+            Sitemap::Pages(vec![
+                Entry {
+                    url: "https://example.com/".to_string(),
+                    last_modified: Some(1709251200),
+                    change_frequency: Some(ChangeFrequency::Daily),
+                },
+                Entry {
+                    url: "https://example.com/about".to_string(),
+                    last_modified: Some(1709292600),
+                    change_frequency: None,
+                },
+                Entry {
+                    url: "https://example.com/news".to_string(),
+                    last_modified: Some(1709296200),
+                    change_frequency: None,
+                },
+            ]),
+            Sitemap::parse(input).unwrap()
+        );
+
+        let input = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/pages.xml</loc></sitemap>
+              <sitemap><loc>https://example.com/posts.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+        assert_eq!(
+            Sitemap::Index(vec![
+                "https://example.com/pages.xml".to_string(),
+                "https://example.com/posts.xml.gz".to_string(),
+            ]),
+            Sitemap::parse(input).unwrap()
+        );
+
+        assert!(Sitemap::parse("<html><body></body></html>").is_err());
+    }
+
+    #[test]
+    fn test_from_robots() {
+        let robots = "User-agent: *\n\
+                      Disallow: /private\n\
+                      Sitemap: https://example.com/sitemap.xml\n\
+                      sitemap:https://example.com/news.xml # news only\n\
+                      Sitemap:\n";
+        assert_eq!(
+            vec![
+                "https://example.com/sitemap.xml".to_string(),
+                "https://example.com/news.xml".to_string(),
+            ],
+            from_robots(robots)
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use super::Analyzer;
+use super::cjk::is_cjk;
+
+/// How many words a snippet is long.
+static SNIPPET_WORDS: usize = 30;
+
+/// How many words of context come before the first match in a snippet.
+static LEADING_WORDS: usize = 8;
+
+/// A matched word within a snippet, as character offsets into its text.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A passage of a document's text shown with its result.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<Highlight>,
+}
+
+impl Snippet {
+    /// The text with every highlight wrapped in `open` and `close`.
+    pub fn marked(&self, open: &str, close: &str) -> String {
+        let mut marked = String::with_capacity(self.text.len());
+        let mut highlights = self.highlights.iter().peekable();
+        for (i, c) in self.text.chars().enumerate() {
+            if highlights.peek().is_some_and(|h| h.start == i) {
+                marked.push_str(open);
+            }
+            marked.push(c);
+            if highlights.next_if(|h| h.end == i + 1).is_some() {
+                marked.push_str(close);
+            }
+        }
+        marked
+    }
+}
+
+/// A piece of the text that snippets are cut from and highlighted by. Text written with spaces
+/// is split into its words while runs of CJK characters are split into single characters, the
+/// same units the analyzer's bigrams are made of.
+struct Word<'a> {
+    text: &'a str,
+    /// Written directly after the previous word, without a space between them.
+    attached: bool,
+    /// The query term starting at this word.
+    matched: Option<String>,
+    /// Part of a matched term, a bigram covers this word and the one before it too.
+    highlighted: bool,
+}
+
+/// Picks up to `count` passages of the text containing the most of the query's terms, in the
+/// order they appear. Text without any of the terms gets its opening words instead so every
+/// result has something to show.
+pub fn snippets(
+    analyzer: &Analyzer,
+    text: &str,
+    terms: &BTreeSet<&str>,
+    count: usize,
+) -> Vec<Snippet> {
+    let words = words(analyzer, text, terms);
+    if words.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    // Every match starts a candidate passage, the ones with more distinct terms win and then
+    // the ones with more matches overall
+    let mut candidates: Vec<(usize, usize, usize, usize)> = words
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| w.matched.is_some())
+        .map(|(i, _)| {
+            let start = i.saturating_sub(LEADING_WORDS);
+            let end = (start + SNIPPET_WORDS).min(words.len());
+            let found: Vec<&String> = words[start..end]
+                .iter()
+                .filter_map(|w| w.matched.as_ref())
+                .collect();
+            let distinct = found.iter().collect::<BTreeSet<_>>().len();
+            (start, end, distinct, found.len())
+        })
+        .collect();
+    candidates.sort_by(|a, b| (b.2, b.3, a.0).cmp(&(a.2, a.3, b.0)));
+
+    let mut picked: Vec<(usize, usize)> = Vec::new();
+    for (start, end, _, _) in candidates {
+        if picked.len() == count {
+            break;
+        }
+        if picked.iter().all(|&(s, e)| end <= s || start >= e) {
+            picked.push((start, end));
+        }
+    }
+    if picked.is_empty() {
+        picked.push((0, SNIPPET_WORDS.min(words.len())));
+    }
+    picked.sort();
+
+    picked
+        .into_iter()
+        .map(|(start, end)| snippet(&words[start..end]))
+        .collect()
+}
+
+/// Splits the text into words and finds the query terms among them.
+fn words<'a>(analyzer: &Analyzer, text: &'a str, terms: &BTreeSet<&str>) -> Vec<Word<'a>> {
+    let mut words = Vec::new();
+    for whole in text.split_whitespace() {
+        let mut attached = false;
+        let mut rest = whole;
+        while let Some(first) = rest.chars().next() {
+            let cjk = is_cjk(first);
+            let length = rest
+                .char_indices()
+                .find(|&(_, c)| is_cjk(c) != cjk)
+                .map_or(rest.len(), |(i, _)| i);
+            let (piece, remaining) = rest.split_at(length);
+            rest = remaining;
+
+            if !cjk {
+                let matched = analyzer
+                    .tokenize(piece)
+                    .into_iter()
+                    .find(|t| terms.contains(t.as_str()));
+                words.push(Word {
+                    text: piece,
+                    attached,
+                    highlighted: matched.is_some(),
+                    matched,
+                });
+                attached = true;
+                continue;
+            }
+
+            // Match the bigram starting at each character, or the character on its own when
+            // the run is only one long
+            let characters: Vec<(usize, char)> = piece.char_indices().collect();
+            let mut previous = false;
+            for (i, &(offset, c)) in characters.iter().enumerate() {
+                let gram = match characters.get(i + 1) {
+                    Some(&(next, n)) => &piece[offset..next + n.len_utf8()],
+                    None if characters.len() == 1 => piece,
+                    None => "",
+                };
+                let matched = analyzer
+                    .tokenize(gram)
+                    .into_iter()
+                    .find(|t| terms.contains(t.as_str()));
+                words.push(Word {
+                    text: &piece[offset..offset + c.len_utf8()],
+                    attached,
+                    highlighted: matched.is_some() || previous,
+                    matched: matched.clone(),
+                });
+                previous = matched.is_some() && characters.len() > 1;
+                attached = true;
+            }
+        }
+    }
+    words
+}
+
+/// Joins the words, with single spaces where the text had whitespace, highlighting the
+/// matched ones without their surrounding punctuation.
+fn snippet(words: &[Word]) -> Snippet {
+    let mut text = String::new();
+    let mut highlights: Vec<Highlight> = Vec::new();
+    let mut offset = 0;
+    for word in words {
+        if !text.is_empty() && !word.attached {
+            text.push(' ');
+            offset += 1;
+        }
+        let length = word.text.chars().count();
+        if word.highlighted {
+            let leading = word
+                .text
+                .chars()
+                .take_while(|c| !c.is_alphanumeric())
+                .count();
+            let trailing = word
+                .text
+                .chars()
+                .rev()
+                .take_while(|c| !c.is_alphanumeric())
+                .count();
+            if leading + trailing < length {
+                let (start, end) = (offset + leading, offset + length - trailing);
+                match highlights.last_mut() {
+                    Some(last) if last.end == start => last.end = end,
+                    _ => highlights.push(Highlight { start, end }),
+                }
+            }
+        }
+        text.push_str(word.text);
+        offset += length;
+    }
+    Snippet { text, highlights }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms<'a>(terms: &[&'a str]) -> BTreeSet<&'a str> {
+        terms.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_snippets_highlight() {
+        let analyzer = Analyzer::default();
+        let snippets = snippets(
+            &analyzer,
+            "The (Café)\n serves  coffee.",
+            &terms(&["cafe", "coffee"]),
+            3,
+        );
+
+        assert_eq!(1, snippets.len());
+        assert_eq!("The (Café) serves coffee.", snippets[0].text);
+        assert_eq!(
+            vec![Highlight { start: 5, end: 9 }, Highlight {
+                start: 18,
+                end: 24
+            }],
+            snippets[0].highlights
+        );
+        assert_eq!(
+            "The (<mark>Café</mark>) serves <mark>coffee</mark>.",
+            snippets[0].marked("<mark>", "</mark>")
+        );
+    }
+
+    #[test]
+    fn test_snippets_best_passages() {
+        let analyzer = Analyzer::default();
+        let filler = "filler ".repeat(100);
+        let text = format!("alpha {filler} alpha bravo {filler} bravo {filler} charlie");
+
+        let snippets = snippets(&analyzer, &text, &terms(&["alpha", "bravo"]), 2);
+        assert_eq!(2, snippets.len());
+        assert!(snippets[0].text.starts_with("alpha filler"));
+        assert!(snippets[1].text.contains("alpha bravo"));
+        assert_eq!(2, snippets[1].highlights.len());
+    }
+
+    #[test]
+    fn test_snippets_without_matches() {
+        let analyzer = Analyzer::default();
+        let text = "word ".repeat(100);
+
+        let snippets = snippets(&analyzer, &text, &terms(&["alpha"]), 3);
+        assert_eq!(1, snippets.len());
+        assert_eq!(SNIPPET_WORDS, snippets[0].text.split(' ').count());
+        assert!(snippets[0].highlights.is_empty());
+
+        assert!(super::snippets(&analyzer, "", &terms(&["alpha"]), 3).is_empty());
+    }
+
+    #[test]
+    fn test_snippets_cjk() {
+        let analyzer = Analyzer::default();
+        let filler = "あ".repeat(100);
+        let text = format!("{filler}東京タワーへ行く{filler}");
+
+        let snippets = snippets(&analyzer, &text, &terms(&["タワ", "ワー"]), 3);
+        assert_eq!(1, snippets.len());
+        assert_eq!(SNIPPET_WORDS, snippets[0].text.chars().count());
+        assert!(snippets[0].text.contains("東京タワーへ行く"));
+        assert_eq!(
+            vec![Highlight {
+                start: LEADING_WORDS,
+                end: LEADING_WORDS + 3
+            }],
+            snippets[0].highlights
+        );
+    }
+}
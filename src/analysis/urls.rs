@@ -0,0 +1,197 @@
+use anyhow::Result;
+use reqwest::Url;
+use rusqlite::Connection;
+
+use crate::db::settings;
+
+static STRIP_PARAMETERS_KEY: &str = "urls.strip_parameters";
+static SORT_QUERY_KEY: &str = "urls.sort_query";
+static STRIP_TRAILING_SLASH_KEY: &str = "urls.strip_trailing_slash";
+
+/// Tracking parameters which don't change what a page shows.
+static DEFAULT_STRIP_PARAMETERS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_ga",
+];
+
+/// Turns the different ways of writing a url into one, so that a page is only crawled and
+/// indexed once. Host case, default ports and fragments are always normalized, the rest is
+/// configured with the index's `urls.*` settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlNormalizer {
+    /// Query parameters to drop, `utm_*` drops every parameter starting with `utm_`. Set with a
+    /// comma separated list.
+    pub strip_parameters: Vec<String>,
+    /// Puts the query parameters in order.
+    pub sort_query: bool,
+    /// Drops the slash at the end of any path but the root, `/a/` becomes `/a`.
+    pub strip_trailing_slash: bool,
+}
+
+impl Default for UrlNormalizer {
+    fn default() -> Self {
+        Self {
+            strip_parameters: DEFAULT_STRIP_PARAMETERS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            sort_query: true,
+            strip_trailing_slash: true,
+        }
+    }
+}
+
+impl UrlNormalizer {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut normalizer = Self::default();
+        if let Some(v) = settings::get(connection, STRIP_PARAMETERS_KEY)? {
+            normalizer.strip_parameters = v
+                .split(',')
+                .map(|p| p.trim().to_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect();
+        }
+        if let Some(v) = settings::get(connection, SORT_QUERY_KEY)? {
+            normalizer.sort_query = v == "true";
+        }
+        if let Some(v) = settings::get(connection, STRIP_TRAILING_SLASH_KEY)? {
+            normalizer.strip_trailing_slash = v == "true";
+        }
+        Ok(normalizer)
+    }
+
+    fn strips(&self, parameter: &str) -> bool {
+        let parameter = parameter.to_lowercase();
+        self.strip_parameters
+            .iter()
+            .any(|p| match p.strip_suffix('*') {
+                Some(prefix) => parameter.starts_with(prefix),
+                None => parameter == *p,
+            })
+    }
+
+    /// Normalizing a url more than once doesn't change it any further.
+    pub fn normalize(&self, url: &str) -> Result<String> {
+        // Parsing lowercases the host and drops default ports
+        let mut url = Url::parse(url.trim())?;
+        url.set_fragment(None);
+
+        // Parameters are kept as they were written rather than decoded and encoded again, the
+        // decoded pairs come in the same order
+        let query = url.query().unwrap_or_default().to_string();
+        let mut parameters: Vec<&str> = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .zip(url.query_pairs())
+            .filter(|(_, (name, _))| !self.strips(name))
+            .map(|(p, _)| p)
+            .collect();
+        if self.sort_query {
+            parameters.sort();
+        }
+        if parameters.is_empty() {
+            url.set_query(None);
+        } else {
+            url.set_query(Some(&parameters.join("&")));
+        }
+
+        if self.strip_trailing_slash && url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(if path.is_empty() { "/" } else { &path });
+        }
+
+        Ok(url.into())
+    }
+
+    /// For looking up urls stored normalized, anything which doesn't parse is left as it is.
+    pub fn lookup(&self, url: &str) -> String {
+        self.normalize(url).unwrap_or_else(|_| url.to_string())
+    }
+}
+
+/// Whether two urls are on the same site, so that one can speak for the other. Without a list
+/// of public suffixes to find the registrable domain by, that's the same host give or take
+/// `www.`.
+pub fn same_site(a: &str, b: &str) -> bool {
+    let host = |url: &str| {
+        Url::parse(url)
+            .ok()?
+            .host_str()
+            .map(|h| h.trim_start_matches("www.").to_string())
+    };
+    host(a).is_some_and(|h| Some(h) == host(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = UrlNormalizer::default();
+        let normalize = |url: &str| normalizer.normalize(url).unwrap();
+
+        assert_eq!(
+            "http://example.com/a",
+            normalize("http://Example.COM:80/a#x")
+        );
+        assert_eq!(
+            "https://example.com/a",
+            normalize("https://example.com/a?utm_source=feed&UTM_Medium=rss&fbclid=1")
+        );
+        assert_eq!("https://example.com/a", normalize("https://example.com/a/"));
+        assert_eq!("https://example.com/", normalize("https://example.com"));
+        assert_eq!(
+            "https://example.com/a?b=2&c=3%204",
+            normalize("https://example.com:443/a/?c=3%204&utm_campaign=x&b=2")
+        );
+        let normalized = normalize("https://example.com/a/?c=1&a=2#top");
+        assert_eq!(normalized, normalize(&normalized));
+        assert!(normalizer.normalize("not a url").is_err());
+
+        let normalizer = UrlNormalizer {
+            strip_parameters: vec!["ref".to_string()],
+            sort_query: false,
+            strip_trailing_slash: false,
+        };
+        assert_eq!(
+            "https://example.com/a/?utm_source=x&b=1",
+            normalizer
+                .normalize("https://example.com/a/?utm_source=x&ref=y&b=1")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let connection = Connection::open_in_memory().unwrap();
+        settings::create_table(&connection).unwrap();
+        assert_eq!(
+            UrlNormalizer::default(),
+            UrlNormalizer::load(&connection).unwrap()
+        );
+
+        settings::set(&connection, STRIP_PARAMETERS_KEY, "ref, utm_*").unwrap();
+        settings::set(&connection, SORT_QUERY_KEY, "false").unwrap();
+        assert_eq!(
+            UrlNormalizer {
+                strip_parameters: vec!["ref".to_string(), "utm_*".to_string()],
+                sort_query: false,
+                strip_trailing_slash: true,
+            },
+            UrlNormalizer::load(&connection).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_same_site() {
+        assert!(same_site(
+            "https://example.com/a",
+            "http://www.example.com/b"
+        ));
+        assert!(!same_site(
+            "https://example.com/",
+            "https://blog.example.com/"
+        ));
+        assert!(!same_site("not a url", "not a url"));
+    }
+}
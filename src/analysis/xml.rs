@@ -0,0 +1,339 @@
+use anyhow::{Result, anyhow};
+
+/// How deeply elements can be nested. Sitemaps and feeds only go a few levels deep, anything
+/// much deeper would overflow the stack walking or dropping the tree.
+static MAX_DEPTH: usize = 64;
+
+/// Just enough of XML to read sitemaps: elements, attributes, text, CDATA and the predefined
+/// and numeric entities. Declarations, comments and doctypes are skipped and badly nested tags
+/// are closed where they can be rather than rejected, since what sites serve isn't always valid.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Element {
+    /// As written, including any namespace prefix.
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    /// The document's root element.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser { input, position: 0 };
+        parser.root()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The child elements, leaving out text.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// All the text inside the element, trimmed.
+    pub fn text(&self) -> String {
+        fn collect(element: &Element, text: &mut String) {
+            for c in &element.children {
+                match c {
+                    Node::Element(e) => collect(e, text),
+                    Node::Text(t) => text.push_str(t),
+                }
+            }
+        }
+        let mut text = String::new();
+        collect(self, &mut text);
+        text.trim().to_string()
+    }
+
+    /// The trimmed text of the first child element with the name, if it has any.
+    pub fn child_text(&self, name: &str) -> Option<String> {
+        self.child(name).map(|c| c.text()).filter(|t| !t.is_empty())
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    /// Moves past the next `end`, or to the end of the input if there isn't one.
+    fn skip_past(&mut self, end: &str) -> &'a str {
+        let rest = &self.input[self.position..];
+        match rest.find(end) {
+            Some(i) => {
+                self.position += i + end.len();
+                &rest[..i]
+            }
+            None => {
+                self.position = self.input.len();
+                rest
+            }
+        }
+    }
+
+    fn root(&mut self) -> Result<Element> {
+        // Open elements, innermost last
+        let mut stack: Vec<Element> = vec![];
+
+        while self.position < self.input.len() {
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>");
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->");
+            } else if rest.starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                let text = self.skip_past("]]>").to_string();
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Text(text));
+                }
+            } else if rest.starts_with("<!") {
+                self.skip_doctype();
+            } else if rest.starts_with("</") {
+                self.position += 2;
+                let name = self.skip_past(">").trim().to_string();
+                // Closes anything left open inside it, a close tag without an open one is ignored
+                if let Some(i) = stack.iter().rposition(|e| e.name == name) {
+                    while stack.len() > i {
+                        let element = stack.pop().unwrap();
+                        match stack.last_mut() {
+                            Some(parent) => parent.children.push(Node::Element(element)),
+                            None => return Ok(element),
+                        }
+                    }
+                }
+            } else if rest.starts_with('<') {
+                self.position += 1;
+                let (element, closed) = self.start_tag();
+                if closed {
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Ok(element),
+                    }
+                } else if stack.len() == MAX_DEPTH {
+                    return Err(anyhow!("xml nested more than {} elements deep", MAX_DEPTH));
+                } else {
+                    stack.push(element);
+                }
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = decode(&rest[..end]);
+                self.position += end;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Text(text));
+                }
+            }
+        }
+
+        // Whatever is still open at the end of the input is closed there
+        let mut root = None;
+        while let Some(element) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(Node::Element(element)),
+                None => root = Some(element),
+            }
+        }
+        root.ok_or(anyhow!("no xml element found"))
+    }
+
+    fn skip_doctype(&mut self) {
+        // An internal subset can hold `>` of its own
+        let mut depth = 0;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth <= 0 => {
+                    self.position += i + 1;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.position = self.input.len();
+    }
+
+    /// Reads a start tag up to and including its `>`, saying whether it closed itself.
+    fn start_tag(&mut self) -> (Element, bool) {
+        let mut element = Element::default();
+        let rest = self.rest();
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        element.name = rest[..name_end].to_string();
+        self.position += name_end;
+
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            if trimmed.is_empty() {
+                return (element, true);
+            } else if trimmed.starts_with("/>") {
+                self.position += 2;
+                return (element, true);
+            } else if trimmed.starts_with('>') {
+                self.position += 1;
+                return (element, false);
+            }
+
+            let name_end = trimmed
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                .unwrap_or(trimmed.len())
+                .max(1);
+            let name = trimmed[..name_end].to_string();
+            self.position += name_end;
+
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            if !trimmed.starts_with('=') {
+                element.attributes.push((name, String::new()));
+                continue;
+            }
+            let after_equals = trimmed[1..].trim_start();
+            self.position += rest.len() - after_equals.len();
+            let value = match after_equals.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    self.position += 1;
+                    self.skip_past(&q.to_string()).to_string()
+                }
+                _ => {
+                    let end = after_equals
+                        .find(|c: char| c.is_whitespace() || c == '>')
+                        .unwrap_or(after_equals.len());
+                    self.position += end;
+                    after_equals[..end].to_string()
+                }
+            };
+            element.attributes.push((name, decode(&value)));
+        }
+    }
+}
+
+/// Replaces entity references with what they stand for, leaving any it doesn't know.
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let replacement = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                e => e
+                    .strip_prefix("#x")
+                    .or(e.strip_prefix("#X"))
+                    .and_then(|h| u32::from_str_radix(h, 16).ok())
+                    .or(e.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32),
+            }?;
+            Some((c, end + 1))
+        });
+        match replacement {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!DOCTYPE urlset [ <!ENTITY x "y"> ]>
+            <!-- generated -->
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url>
+                <loc>https://example.com/?a=1&amp;b=2</loc>
+                <image:image href='pic.png' />
+              </url>
+              <url><loc><![CDATA[https://example.com/<raw>]]></loc></url>
+              <note>caf&#233; &#x2014; &unknown;</note>
+            </urlset>"#;
+        let root = Element::parse(input).unwrap();
+
+        assert_eq!("urlset", root.name);
+        assert_eq!(
+            Some("http://www.sitemaps.org/schemas/sitemap/0.9"),
+            root.attribute("xmlns")
+        );
+        let urls: Vec<&Element> = root.children_named("url").collect();
+        assert_eq!(2, urls.len());
+        assert_eq!(
+            Some("https://example.com/?a=1&b=2".to_string()),
+            urls[0].child_text("loc")
+        );
+        assert_eq!(
+            Some("pic.png"),
+            urls[0].child("image:image").unwrap().attribute("href")
+        );
+        assert_eq!(
+            Some("https://example.com/<raw>".to_string()),
+            urls[1].child_text("loc")
+        );
+        assert_eq!(
+            Some("café — &unknown;".to_string()),
+            root.child_text("note")
+        );
+        assert_eq!(None, root.child_text("missing"));
+    }
+
+    #[test]
+    fn test_parse_unbalanced() {
+        let root = Element::parse("<a><b>one<c>two</b></d>three").unwrap();
+        assert_eq!("a", root.name);
+        assert_eq!("onetwothree", root.text());
+        assert_eq!("two", root.child("b").unwrap().child("c").unwrap().text());
+
+        assert!(Element::parse("just text").is_err());
+    }
+
+    #[test]
+    fn test_parse_too_deep() {
+        let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(Element::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Element::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Element::parse(&"<a>".repeat(1_000_000)).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use axum::{
     Json, Router,
-    extract::{Request, State},
+    extract::{Query, Request, State},
     http::{HeaderMap, StatusCode},
     middleware,
     middleware::Next,
@@ -16,9 +16,16 @@ use tokio::net::TcpListener;
 use tokio_rusqlite::Connection;
 use tower_http::cors::CorsLayer;
 
-use crate::actions::search;
-use crate::actions::search::Document;
-use crate::db::crawls;
+use crate::actions::facets::Facets;
+use crate::actions::search::{Document, Operator, SearchOptions};
+use crate::actions::{crawl, feeds, migrate, related, search, sitemaps, suggest, synonyms};
+use crate::analysis::Analyzer;
+use crate::analysis::urls::UrlNormalizer;
+use crate::db::feeds::Feed;
+use crate::db::synonyms::Synonym;
+use crate::db::{self, crawls, votes};
+use crate::query::ParseError;
+use crate::ranking::RankingFunction;
 
 mod auth;
 
@@ -26,21 +33,35 @@ mod auth;
 struct AppState {
     connection: Connection,
     auth_backend: auth::Backend,
+    path: PathBuf,
 }
 
 static SEARCH_PAGE: &str = include_str!("../../data/search_page.html");
 static INTRO_PAGE: &str = include_str!("../../data/intro_page.html");
 
+static DEFAULT_LIMIT: usize = 10;
+static MAX_LIMIT: usize = 100;
+
+static DEFAULT_SUGGESTIONS: usize = 8;
+static MAX_SUGGESTIONS: usize = 20;
+
+static DEFAULT_RELATED: usize = 10;
+static MAX_RELATED: usize = 50;
+
 pub async fn start(path: &PathBuf, address: &str) -> Result<()> {
     println!("Starting nvgs server: {}", address);
     let db_path = path.join("nvgs.db");
     println!("Connecting: {}", db_path.display());
     let connection = Connection::open(db_path).await?;
+    connection
+        .call(|conn| migrate::migrate(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into())))
+        .await?;
     let auth_backend = auth::Backend::new(connection.clone());
     println!("Established connection");
     let state = AppState {
         connection,
         auth_backend,
+        path: path.clone(),
     };
 
     // Note using post for crawls/get because sending
@@ -49,12 +70,21 @@ pub async fn start(path: &PathBuf, address: &str) -> Result<()> {
         .route("/crawls", routing::post(add_crawl))
         .route("/crawls/get", routing::post(get_crawl))
         .route("/crawls/delete", routing::post(delete_crawl))
+        .route("/votes", routing::post(vote))
+        .route("/synonyms", routing::get(get_synonyms))
+        .route("/synonyms", routing::post(add_synonyms))
+        .route("/synonyms/delete", routing::post(delete_synonyms))
+        .route("/feeds", routing::get(get_feeds))
+        .route("/feeds", routing::post(subscribe))
+        .route("/feeds/delete", routing::post(unsubscribe))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ))
         .route("/search", routing::post(search))
         .route("/search", routing::get(search_page))
+        .route("/suggest", routing::get(suggest))
+        .route("/related", routing::get(get_related))
         .route("/", routing::get(intro_page))
         .layer(CorsLayer::very_permissive())
         .with_state(state)
@@ -112,8 +142,11 @@ async fn get_crawl(
     let crawl_result = app_state
         .connection
         .call(move |conn| {
-            Ok(crawls::get(&conn, &payload.url)
-                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?)
+            let get = || -> Result<Option<crawls::Crawl>> {
+                let url = UrlNormalizer::load(conn)?.lookup(&payload.url);
+                crawls::get(conn, &url)
+            };
+            get().map_err(|e| tokio_rusqlite::Error::Other(e.into()))
         })
         .await;
 
@@ -134,6 +167,9 @@ async fn get_crawl(
 #[derive(Deserialize)]
 struct AddCrawlRequest {
     urls: Vec<String>,
+    /// Also crawl the pages listed in their sites' sitemaps
+    #[serde(default)]
+    sitemaps: bool,
 }
 
 async fn add_crawl(
@@ -142,12 +178,20 @@ async fn add_crawl(
 ) -> Result<(StatusCode, String), AppError> {
     state
         .connection
-        .call(|conn| {
+        .call(move |conn| {
+            let normalizer =
+                UrlNormalizer::load(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
             for u in payload.urls {
-                let crawl =
-                    crawls::Crawl::new(&u).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+                let crawl = crawls::Crawl::new(&u, &normalizer)
+                    .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
                 crawls::insert(&conn, &crawl)
                     .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+                if payload.sitemaps {
+                    let site = sitemaps::site(&crawl.url)
+                        .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+                    db::sitemaps::insert(conn, &site)
+                        .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+                }
                 println!("Added url: {}", u);
             }
             Ok(())
@@ -168,30 +212,275 @@ async fn delete_crawl(
     state
         .connection
         .call(move |conn| {
-            Ok(crawls::delete(&conn, &payload.url)
-                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?)
+            let tx = conn.transaction()?;
+            let remove = || -> Result<()> {
+                let analyzer = Analyzer::load(&tx)?;
+                let url = UrlNormalizer::load(&tx)?.lookup(&payload.url);
+                crawls::delete(&tx, &url)?;
+                crawl::remove(&tx, &analyzer, &url)
+            };
+            remove().map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+            Ok(tx.commit()?)
+        })
+        .await?;
+    Ok((StatusCode::CREATED, "".to_string()))
+}
+
+#[derive(Deserialize)]
+struct VoteRequest {
+    url: String,
+    /// 1 for up, -1 for down and 0 to take a vote back.
+    vote: i64,
+}
+
+async fn vote(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<VoteRequest>,
+) -> Result<(StatusCode, String), AppError> {
+    if !(-1..=1).contains(&payload.vote) {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "vote must be -1, 0 or 1".to_string(),
+        ));
+    }
+
+    // The auth middleware has already checked this header belongs to a real user
+    let username = headers
+        .get("Nvgs-Username")
+        .and_then(|u| u.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    state
+        .connection
+        .call(move |conn| {
+            let set = || -> Result<()> {
+                let url = UrlNormalizer::load(conn)?.lookup(&payload.url);
+                votes::set(conn, &url, &username, payload.vote)
+            };
+            set().map_err(|e| tokio_rusqlite::Error::Other(e.into()))
         })
         .await?;
     Ok((StatusCode::CREATED, "".to_string()))
 }
 
+#[derive(Deserialize)]
+struct SynonymRequest {
+    /// `js, javascript` for equivalent terms or `k8s => kubernetes` for one-way synonyms.
+    rule: String,
+    /// Defaults to 0.8.
+    weight: Option<f64>,
+}
+
+async fn get_synonyms(State(state): State<AppState>) -> Result<Json<Vec<Synonym>>, AppError> {
+    let synonyms = state
+        .connection
+        .call(|conn| {
+            db::synonyms::get_all(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into()))
+        })
+        .await?;
+    Ok(Json(synonyms))
+}
+
+async fn add_synonyms(
+    State(state): State<AppState>,
+    Json(payload): Json<SynonymRequest>,
+) -> Result<(StatusCode, String), AppError> {
+    let added = state
+        .connection
+        .call(move |conn| {
+            let analyzer =
+                Analyzer::load(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+            let weight = payload.weight.unwrap_or(synonyms::DEFAULT_WEIGHT);
+            // A malformed rule is the caller's to fix
+            let synonyms = match synonyms::parse_rule(&analyzer, &payload.rule, weight) {
+                Ok(synonyms) => synonyms,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            for s in synonyms {
+                db::synonyms::set(conn, &s).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+            }
+            Ok(Ok(()))
+        })
+        .await?;
+    match added {
+        Ok(()) => Ok((StatusCode::CREATED, "".to_string())),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e)),
+    }
+}
+
+async fn delete_synonyms(
+    State(state): State<AppState>,
+    Json(payload): Json<SynonymRequest>,
+) -> Result<(StatusCode, String), AppError> {
+    state
+        .connection
+        .call(move |conn| {
+            synonyms::remove(conn, &payload.rule)
+                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))
+        })
+        .await?;
+    Ok((StatusCode::OK, "".to_string()))
+}
+
+async fn get_feeds(State(state): State<AppState>) -> Result<Json<Vec<Feed>>, AppError> {
+    let feeds = state
+        .connection
+        .call(|conn| db::feeds::get_all(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into())))
+        .await?;
+    Ok(Json(feeds))
+}
+
+#[derive(Deserialize)]
+struct FeedRequest {
+    url: String,
+}
+
+async fn subscribe(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedRequest>,
+) -> Result<(StatusCode, String), AppError> {
+    let subscribed = state
+        .connection
+        .call(move |conn| {
+            let normalizer =
+                UrlNormalizer::load(conn).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+            // A url which doesn't parse is the caller's to fix
+            let url = match normalizer.normalize(&payload.url) {
+                Ok(url) => url,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            db::feeds::subscribe(conn, &url).map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+            Ok(Ok(()))
+        })
+        .await?;
+    match subscribed {
+        Ok(()) => Ok((StatusCode::CREATED, "".to_string())),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e)),
+    }
+}
+
+async fn unsubscribe(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedRequest>,
+) -> Result<(StatusCode, String), AppError> {
+    state
+        .connection
+        .call(move |conn| {
+            feeds::unsubscribe(conn, &payload.url)
+                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))
+        })
+        .await?;
+    Ok((StatusCode::OK, "".to_string()))
+}
+
 async fn search(
     State(state): State<AppState>,
     Json(payload): Json<SearchQuery>,
-) -> Result<(StatusCode, response::Json<SearchResult>), AppError> {
+) -> Result<Response, AppError> {
     let results = state
         .connection
         .call(move |conn| {
-            let results = search::execute(conn, &payload.terms)
-                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
-            Ok(results)
+            let options = SearchOptions {
+                ranking: payload.ranking,
+                operator: payload.operator,
+                offset: payload.offset,
+                limit: Some(payload.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)),
+                snippets_from: Some(state.path),
+                per_site: Some(payload.per_site.unwrap_or(search::DEFAULT_PER_SITE))
+                    .filter(|n| *n > 0),
+                filters: payload.filters,
+                facets: payload.facets,
+                explain: payload.explain,
+            };
+            let terms = match payload.query {
+                Some(q) => vec![q],
+                None => payload.terms,
+            };
+            // A query the user got wrong is theirs to fix rather than an error of ours
+            match search::execute(conn, &terms, &options) {
+                Ok(results) => {
+                    // Only the first page, so paging through results doesn't count again
+                    if options.offset == 0 && results.total > 0 {
+                        suggest::record(conn, &terms.join(" "))
+                            .map_err(|e| tokio_rusqlite::Error::Other(e.into()))?;
+                    }
+                    Ok(Ok(results))
+                }
+                Err(e) => match e.downcast::<ParseError>() {
+                    Ok(parse_error) => Ok(Err(parse_error)),
+                    Err(e) => Err(tokio_rusqlite::Error::Other(e.into())),
+                },
+            }
+        })
+        .await?;
+
+    match results {
+        Ok(results) => Ok((
+            StatusCode::CREATED,
+            response::Json(SearchResult {
+                total: results.total,
+                results: results.documents,
+                suggestion: results.suggestion,
+                facets: results.facets,
+            }),
+        )
+            .into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+#[derive(Deserialize)]
+struct SuggestQuery {
+    /// What has been typed so far.
+    q: String,
+    /// Defaults to eight and can't be more than twenty.
+    limit: Option<usize>,
+}
+
+async fn suggest(
+    State(state): State<AppState>,
+    Query(params): Query<SuggestQuery>,
+) -> Result<Json<Vec<String>>, AppError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .min(MAX_SUGGESTIONS);
+    let suggestions = state
+        .connection
+        .call(move |conn| {
+            suggest::suggest(conn, &params.q, limit)
+                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))
         })
         .await?;
+    Ok(Json(suggestions))
+}
+
+#[derive(Deserialize)]
+struct RelatedQuery {
+    /// A document in the index.
+    url: String,
+    /// Defaults to ten and can't be more than fifty.
+    limit: Option<usize>,
+}
 
-    Ok((
-        StatusCode::CREATED,
-        response::Json(SearchResult { results }),
-    ))
+async fn get_related(
+    State(state): State<AppState>,
+    Query(params): Query<RelatedQuery>,
+) -> Result<Response, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_RELATED).min(MAX_RELATED);
+    let documents = state
+        .connection
+        .call(move |conn| {
+            related::find(conn, &params.url, limit)
+                .map_err(|e| tokio_rusqlite::Error::Other(e.into()))
+        })
+        .await?;
+    match documents {
+        Some(documents) => Ok(Json(documents).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
 }
 
 async fn search_page() -> response::Html<&'static str> {
@@ -204,12 +493,40 @@ async fn intro_page() -> response::Html<&'static str> {
 
 #[derive(Deserialize)]
 struct SearchQuery {
+    /// The query as the user wrote it, takes the place of `terms`.
+    query: Option<String>,
+    #[serde(default)]
     terms: Vec<String>,
+    ranking: Option<RankingFunction>,
+    #[serde(default)]
+    operator: Operator,
+    #[serde(default)]
+    offset: usize,
+    /// Defaults to ten and can't be more than a hundred.
+    limit: Option<usize>,
+    /// The most results from any one site on a page, two by default and 0 for no limit.
+    per_site: Option<usize>,
+    /// Filters on top of the query, like the ones facets give.
+    #[serde(default)]
+    filters: Vec<String>,
+    /// Whether to count the results by site, language, content type and crawl date.
+    #[serde(default)]
+    facets: bool,
+    /// Whether to explain how each result's score was arrived at.
+    #[serde(default)]
+    explain: bool,
 }
 
 #[derive(Serialize)]
 struct SearchResult {
+    /// Every document matching the query, `results` is only the requested page of them.
+    total: usize,
     results: Vec<Document>,
+    /// A respelling of the query which should find more, "did you mean".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<Facets>,
 }
 
 // Make our own error that wraps `anyhow::Error`.
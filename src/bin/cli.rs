@@ -5,6 +5,9 @@ use clap::{Parser, Subcommand};
 use rusqlite::Connection;
 
 use nvgs::actions;
+use nvgs::actions::search::{Operator, SearchOptions};
+use nvgs::analysis::Analyzer;
+use nvgs::ranking::RankingFunction;
 
 #[derive(Parser, Debug)]
 #[command(name = "nvgs")]
@@ -24,6 +27,9 @@ pub enum Action {
     Add {
         #[arg(long)]
         url: String,
+        /// Also crawl the pages listed in the site's sitemaps
+        #[arg(long)]
+        sitemaps: bool,
     },
     AddUser {
         #[arg(long)]
@@ -32,39 +38,160 @@ pub enum Action {
         password: String,
     },
     Crawl {
-        #[arg(long)]
+        /// No longer does anything, the statistics are kept up to date as pages are crawled
+        #[arg(long, hide = true)]
         index_after: bool,
     },
+    /// Compares ranking functions against a file of relevance judgments
+    Evaluate {
+        #[arg(long)]
+        judgments: PathBuf,
+        #[arg(long, default_value_t = 10)]
+        k: usize,
+    },
+    /// Recomputes the collection statistics used for ranking
     Index,
+    /// Adds synonyms, `js, javascript` makes the terms equivalent and `k8s => kubernetes` only
+    /// expands searches for the left hand side
+    AddSynonyms {
+        #[arg(long)]
+        rule: String,
+        /// How much matches of a synonym count for compared to the term searched for
+        #[arg(long, default_value_t = actions::synonyms::DEFAULT_WEIGHT)]
+        weight: f64,
+    },
+    /// Removes the synonyms a rule added
+    RemoveSynonyms {
+        #[arg(long)]
+        rule: String,
+    },
+    /// Lists every synonym
+    Synonyms,
+    /// Polls a feed for new entries to crawl each crawl, the url can be of a page linking to it
+    Subscribe {
+        #[arg(long)]
+        url: String,
+    },
+    Unsubscribe {
+        #[arg(long)]
+        url: String,
+    },
+    /// Lists subscribed feeds and those found on crawled pages
+    Feeds,
+    /// Finds documents similar to one in the index, leaving out copies of it
+    Related {
+        #[arg(long)]
+        url: String,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    Set {
+        #[arg(long)]
+        key: String,
+        #[arg(long)]
+        value: String,
+    },
     Search {
         #[arg(long, value_parser, num_args = 1.., value_delimiter = ' ')]
         query: Vec<String>,
+        #[arg(long)]
+        ranking: Option<String>,
+        /// Whether results must match every word, `and`, or any of them, `or`
+        #[arg(long, default_value = "and")]
+        operator: String,
+        /// How many results to skip
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// The most results from any one site on a page, 0 for no limit
+        #[arg(long, default_value_t = actions::search::DEFAULT_PER_SITE)]
+        per_site: usize,
+        /// A filter every result has to match, `lang:en`, can be given more than once
+        #[arg(long)]
+        filter: Vec<String>,
+        /// Counts the results by site, language, content type and crawl date
+        #[arg(long)]
+        facets: bool,
+        /// Shows how the score of each result was arrived at
+        #[arg(long)]
+        explain: bool,
+    },
+    Init {
+        /// Index "café" and "cafe" as different terms
+        #[arg(long)]
+        keep_accents: bool,
     },
-    Init,
 }
 
 pub fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    if let Action::Init = &cli.action {
-        actions::init::init(&cli.path)?;
+    if let Action::Init { keep_accents } = &cli.action {
+        actions::init::init(&cli.path, &Analyzer::new(!keep_accents))?;
         return Ok(());
     }
 
     let db_path = cli.path.join("nvgs.db");
     let mut connection = Connection::open(db_path)?;
+    actions::migrate::migrate(&mut connection)?;
 
     match &cli.action {
-        Action::Add { url } => actions::add_url::add_url(&connection, url),
+        Action::Add { url, sitemaps } => actions::add_url::add_url(&connection, url, *sitemaps),
         Action::AddUser { username, password } => {
             actions::add_user::add_user(&connection, username, password)
         }
         Action::Crawl { index_after } => {
-            actions::crawl::crawl(&mut connection, &cli.path, *index_after)
+            if *index_after {
+                println!(
+                    "--index-after is deprecated, the index is kept up to date while crawling"
+                );
+            }
+            actions::crawl::crawl(&mut connection, &cli.path)
+        }
+        Action::Evaluate { judgments, k } => {
+            actions::evaluate::evaluate(&connection, judgments, *k)
         }
         Action::Index => actions::index::index(&connection),
-        Action::Search { query } => actions::search::search(&connection, query),
-        Action::Init => Err(anyhow!(
+        Action::AddSynonyms { rule, weight } => actions::synonyms::add(&connection, rule, *weight),
+        Action::RemoveSynonyms { rule } => actions::synonyms::remove(&connection, rule),
+        Action::Synonyms => actions::synonyms::list(&connection),
+        Action::Subscribe { url } => actions::feeds::subscribe(&connection, url),
+        Action::Unsubscribe { url } => actions::feeds::unsubscribe(&connection, url),
+        Action::Feeds => actions::feeds::list(&connection),
+        Action::Related { url, limit } => actions::related::related(&connection, url, *limit),
+        Action::Set { key, value } => actions::set::set(&connection, key, value),
+        Action::Search {
+            query,
+            ranking,
+            operator,
+            offset,
+            limit,
+            per_site,
+            filter,
+            facets,
+            explain,
+        } => {
+            let ranking = ranking
+                .as_deref()
+                .map(RankingFunction::try_from)
+                .transpose()
+                .map_err(|e| anyhow!(e))?;
+            let operator = Operator::try_from(operator.as_str()).map_err(|e| anyhow!(e))?;
+            let options = SearchOptions {
+                ranking,
+                operator,
+                offset: *offset,
+                limit: Some(*limit),
+                snippets_from: Some(cli.path.clone()),
+                per_site: Some(*per_site).filter(|n| *n > 0),
+                filters: filter.clone(),
+                facets: *facets,
+                explain: *explain,
+            };
+            actions::search::search(&connection, query, &options)
+        }
+        Action::Init { .. } => Err(anyhow!(
             "Should never get here, earlier check for init failed"
         )),
     }?;
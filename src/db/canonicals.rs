@@ -0,0 +1,90 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+/// Pages which said another url is the original of them with `<link rel="canonical">`. They
+/// aren't indexed under their own url, what they have is folded into the canonical url's
+/// document.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE canonicals (
+            url TEXT NOT NULL,
+            canonical TEXT NOT NULL,
+            PRIMARY KEY (url)
+        )
+        ",
+        params![],
+    )?;
+    connection.execute(
+        "CREATE INDEX canonicals_canonical ON canonicals (canonical)",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn set(connection: &Connection, url: &str, canonical: &str) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            canonicals (url, canonical)
+        VALUES
+            (?1, ?2)
+        ON CONFLICT
+            (url)
+        DO UPDATE
+        SET
+            canonical = ?2
+        ",
+        params![url, canonical],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, url: &str) -> Result<Option<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            canonical
+        FROM
+            canonicals
+        WHERE
+            url = ?1
+        ",
+    )?;
+    Ok(statement
+        .query_row(params![url], |row| row.get(0))
+        .optional()?)
+}
+
+/// The pages which are copies of the canonical url.
+pub fn get_by_canonical(connection: &Connection, canonical: &str) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url
+        FROM
+            canonicals
+        WHERE
+            canonical = ?1
+        ORDER BY
+            url
+        ",
+    )?;
+    let results: Vec<String> = statement
+        .query_map(params![canonical], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn delete(connection: &Connection, url: &str) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            canonicals
+        WHERE
+            url = ?1
+        ",
+        params![url],
+    )?;
+    Ok(())
+}
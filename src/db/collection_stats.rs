@@ -0,0 +1,60 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+/// Counts which describe the whole index. There's only ever a single row.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE collection_stats (
+            id INTEGER NOT NULL CHECK (id = 0),
+            document_count INTEGER NOT NULL,
+            PRIMARY KEY (id)
+        )
+        ",
+        params![],
+    )?;
+    connection.execute(
+        "INSERT INTO collection_stats (id, document_count) VALUES (0, 0)",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn add_documents(connection: &Connection, delta: i64) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            collection_stats
+        SET
+            document_count = document_count + ?1
+        WHERE
+            id = 0
+        ",
+        params![delta],
+    )?;
+    Ok(())
+}
+
+pub fn get_document_count(connection: &Connection) -> Result<u64> {
+    let count: u64 = connection.query_row(
+        "SELECT document_count FROM collection_stats WHERE id = 0",
+        params![],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Recounts the documents from the stored field lengths, a document is any url with at least one
+/// indexed field.
+pub fn rebuild(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            collection_stats
+        SET
+            document_count = (SELECT COUNT(DISTINCT url) FROM field_lengths)
+        WHERE
+            id = 0
+        ",
+        params![],
+    )?;
+    Ok(())
+}
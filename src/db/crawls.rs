@@ -1,10 +1,14 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use chrono::{TimeDelta, Utc};
-use reqwest::Url;
 use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
-use rusqlite::{Connection, OptionalExtension, params};
+use rusqlite::{Connection, OptionalExtension, Row, params};
 use serde::Serialize;
 
+use crate::analysis::urls::UrlNormalizer;
+use crate::db;
+
 #[derive(Serialize)]
 pub enum Status {
     Ready,
@@ -23,9 +27,9 @@ impl TryFrom<&str> for Status {
     }
 }
 
-impl Into<String> for &Status {
-    fn into(self) -> String {
-        match self {
+impl From<&Status> for String {
+    fn from(status: &Status) -> Self {
+        match status {
             Status::Ready => "ready".to_string(),
             Status::Crawling => "crawling".to_string(),
         }
@@ -48,22 +52,133 @@ impl ToSql for Status {
     }
 }
 
+/// How often a page says it changes, from its entry in a sitemap.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ChangeFrequency {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFrequency {
+    /// How long after a crawl the page is crawled again, `Never` pages are only crawled once.
+    pub fn interval(&self) -> Option<TimeDelta> {
+        match self {
+            ChangeFrequency::Always => Some(TimeDelta::zero()),
+            ChangeFrequency::Hourly => Some(TimeDelta::hours(1)),
+            ChangeFrequency::Daily => Some(TimeDelta::days(1)),
+            ChangeFrequency::Weekly => Some(TimeDelta::weeks(1)),
+            ChangeFrequency::Monthly => Some(TimeDelta::days(30)),
+            ChangeFrequency::Yearly => Some(TimeDelta::days(365)),
+            ChangeFrequency::Never => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for ChangeFrequency {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, std::string::String> {
+        match value {
+            "always" => Ok(ChangeFrequency::Always),
+            "hourly" => Ok(ChangeFrequency::Hourly),
+            "daily" => Ok(ChangeFrequency::Daily),
+            "weekly" => Ok(ChangeFrequency::Weekly),
+            "monthly" => Ok(ChangeFrequency::Monthly),
+            "yearly" => Ok(ChangeFrequency::Yearly),
+            "never" => Ok(ChangeFrequency::Never),
+            _ => Err(format!("Invalid change frequency: {}", value)),
+        }
+    }
+}
+
+impl From<&ChangeFrequency> for String {
+    fn from(frequency: &ChangeFrequency) -> Self {
+        match frequency {
+            ChangeFrequency::Always => "always".to_string(),
+            ChangeFrequency::Hourly => "hourly".to_string(),
+            ChangeFrequency::Daily => "daily".to_string(),
+            ChangeFrequency::Weekly => "weekly".to_string(),
+            ChangeFrequency::Monthly => "monthly".to_string(),
+            ChangeFrequency::Yearly => "yearly".to_string(),
+            ChangeFrequency::Never => "never".to_string(),
+        }
+    }
+}
+
+impl FromSql for ChangeFrequency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()
+            .and_then(|s| match ChangeFrequency::try_from(s) {
+                Ok(f) => Ok(f),
+                Err(_) => Err(FromSqlError::InvalidType),
+            })
+    }
+}
+
+impl ToSql for ChangeFrequency {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let o: String = self.into();
+        Ok(ToSqlOutput::from(o))
+    }
+}
+
+/// Pages are crawled again a day after they were last crawled, unless a sitemap said when they
+/// were last modified or how often they change.
+static DEFAULT_INTERVAL: TimeDelta = TimeDelta::days(1);
+
 #[derive(Serialize)]
 pub struct Crawl {
     pub url: String,
     pub status: Status,
     pub last_updated: i64,
+    /// When a sitemap said the page last changed.
+    pub last_modified: Option<i64>,
+    pub change_frequency: Option<ChangeFrequency>,
 }
 
 impl Crawl {
-    pub fn new(url_str: &str) -> Result<Self> {
-        let url = Url::parse(url_str)?;
+    pub fn new(url_str: &str, normalizer: &UrlNormalizer) -> Result<Self> {
         Ok(Self {
-            url: url.into(),
+            url: normalizer.normalize(url_str)?,
             status: Status::Ready,
             last_updated: -1,
+            last_modified: None,
+            change_frequency: None,
+        })
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            url: row.get(0)?,
+            status: row.get(1)?,
+            last_updated: row.get(2)?,
+            last_modified: row.get(3)?,
+            change_frequency: row.get(4)?,
         })
     }
+
+    /// Whether the page should be crawled again, because it changed since it was last crawled
+    /// or it's been long enough since then.
+    pub fn is_due(&self, now: i64) -> bool {
+        if let Some(m) = self.last_modified
+            && m > self.last_updated
+        {
+            return true;
+        }
+        match self.change_frequency {
+            Some(f) => match f.interval() {
+                Some(i) => self.last_updated <= now - i.num_seconds(),
+                None => self.last_updated <= 0,
+            },
+            None => self.last_updated < now - DEFAULT_INTERVAL.num_seconds(),
+        }
+    }
 }
 
 pub fn create_table(connection: &Connection) -> Result<()> {
@@ -72,6 +187,8 @@ pub fn create_table(connection: &Connection) -> Result<()> {
             url STRING NOT NULL,
             status STRING NOT NULL,
             last_updated INTEGER NOT NULL,
+            last_modified INTEGER,
+            change_frequency STRING,
             PRIMARY KEY (url)
         )",
         params![],
@@ -83,18 +200,26 @@ pub fn insert(connection: &Connection, crawl: &Crawl) -> Result<()> {
     connection.execute(
         "INSERT INTO
             crawls (
-                url, status, last_updated
+                url, status, last_updated, last_modified, change_frequency
             )
         VALUES
-            (?1, ?2, ?3)
+            (?1, ?2, ?3, ?4, ?5)
         ON CONFLICT
             (url)
         DO UPDATE
         SET
             status = ?2,
-            last_updated = ?3
+            last_updated = ?3,
+            last_modified = ?4,
+            change_frequency = ?5
         ",
-        params![crawl.url, crawl.status, crawl.last_updated],
+        params![
+            crawl.url,
+            crawl.status,
+            crawl.last_updated,
+            crawl.last_modified,
+            crawl.change_frequency
+        ],
     )?;
     Ok(())
 }
@@ -102,7 +227,7 @@ pub fn insert(connection: &Connection, crawl: &Crawl) -> Result<()> {
 pub fn get(connection: &Connection, url: &str) -> Result<Option<Crawl>> {
     let mut statement = connection.prepare(
         "SELECT
-            url, status, last_updated
+            url, status, last_updated, last_modified, change_frequency
         FROM
             crawls
         WHERE
@@ -113,17 +238,37 @@ pub fn get(connection: &Connection, url: &str) -> Result<Option<Crawl>> {
     )?;
 
     let result: Option<Crawl> = statement
-        .query_row(params![url], |row| {
-            Ok(Crawl {
-                url: row.get(0)?,
-                status: row.get(1)?,
-                last_updated: row.get(2)?,
-            })
-        })
+        .query_row(params![url], Crawl::from_row)
         .optional()?;
     Ok(result)
 }
 
+/// Whether anything has been queued for crawling yet.
+pub fn has_any(connection: &Connection) -> Result<bool> {
+    let exists: bool =
+        connection.query_row("SELECT EXISTS (SELECT 1 FROM crawls)", params![], |row| {
+            row.get(0)
+        })?;
+    Ok(exists)
+}
+
+/// The crawls of those urls that have one.
+pub fn get_by_urls(connection: &Connection, urls: &[&str]) -> Result<HashMap<String, Crawl>> {
+    let results = db::query_by_urls(
+        connection,
+        "SELECT
+            url, status, last_updated, last_modified, change_frequency
+        FROM
+            crawls
+        WHERE
+            url IN ({})
+        ",
+        urls,
+        Crawl::from_row,
+    )?;
+    Ok(results.into_iter().map(|c| (c.url.clone(), c)).collect())
+}
+
 pub fn delete(connection: &Connection, url: &str) -> Result<()> {
     let mut statement = connection.prepare(
         "DELETE
@@ -147,7 +292,7 @@ pub fn get_all_with_status_since(
 ) -> Result<Vec<Crawl>> {
     let mut statement = connection.prepare(
         "SELECT
-            url, status, last_updated
+            url, status, last_updated, last_modified, change_frequency
         FROM
             crawls
         WHERE
@@ -159,22 +304,22 @@ pub fn get_all_with_status_since(
     let last_updated = (Utc::now() - *since).timestamp();
 
     let result: Vec<Crawl> = statement
-        .query_map(params![status, last_updated], |row| {
-            Ok(Crawl {
-                url: row.get(0)?,
-                status: row.get(1)?,
-                last_updated: row.get(2)?,
-            })
-        })?
-        .into_iter()
+        .query_map(params![status, last_updated], Crawl::from_row)?
         .flatten()
         .collect();
 
     Ok(result)
 }
 
+/// The pages due to be crawled again, see `Crawl::is_due`.
 pub fn get_all_needing_update(connection: &Connection) -> Result<Vec<Crawl>> {
-    get_all_with_status_since(connection, &Status::Ready, &TimeDelta::hours(24))
+    let now = Utc::now().timestamp();
+    Ok(
+        get_all_with_status_since(connection, &Status::Ready, &TimeDelta::zero())?
+            .into_iter()
+            .filter(|c| c.is_due(now))
+            .collect(),
+    )
 }
 
 pub fn set_crawling(connection: &Connection, url: &str) -> Result<()> {
@@ -205,3 +350,59 @@ pub fn set_ready(connection: &Connection, url: &str, updated_at: i64) -> Result<
     )?;
     Ok(())
 }
+
+/// Records what a sitemap said about a page.
+pub fn set_schedule(
+    connection: &Connection,
+    url: &str,
+    last_modified: Option<i64>,
+    change_frequency: Option<ChangeFrequency>,
+) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            crawls
+        SET
+            last_modified = ?2,
+            change_frequency = ?3
+        WHERE
+            url = ?1
+        ",
+        params![url, last_modified, change_frequency],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_due() {
+        let now = 1000 * 86400;
+        let crawl = |last_updated, last_modified, change_frequency| Crawl {
+            url: "https://example.com/".to_string(),
+            status: Status::Ready,
+            last_updated,
+            last_modified,
+            change_frequency,
+        };
+
+        assert!(crawl(-1, None, None).is_due(now));
+        assert!(crawl(now - 86400 - 1, None, None).is_due(now));
+        assert!(!crawl(now - 3600, None, None).is_due(now));
+
+        // Modified since the last crawl
+        assert!(crawl(now - 3600, Some(now - 60), None).is_due(now));
+        assert!(!crawl(now - 3600, Some(now - 7200), None).is_due(now));
+
+        let hourly = Some(ChangeFrequency::Hourly);
+        assert!(crawl(now - 3600, None, hourly).is_due(now));
+        assert!(!crawl(now - 60, None, hourly).is_due(now));
+        let weekly = Some(ChangeFrequency::Weekly);
+        assert!(!crawl(now - 3 * 86400, None, weekly).is_due(now));
+        let never = Some(ChangeFrequency::Never);
+        assert!(crawl(-1, None, never).is_due(now));
+        assert!(!crawl(now - 300 * 86400, None, never).is_due(now));
+        assert!(crawl(now - 300 * 86400, Some(now - 60), never).is_due(now));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::analysis::fields::Field;
+use crate::db::term_frequencies::{self, TermFrequency};
+use crate::db::{self, collection_stats, field_lengths, field_stats, spelling, term_stats};
+
+/// What we know about a crawled document besides its text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub url: String,
+    pub content_type: String,
+    /// The primary language subtag, "en" for "en-GB", if the document declared one.
+    pub language: Option<String>,
+    pub crawled_at: i64,
+}
+
+impl Metadata {
+    pub fn new(url: &str, content_type: &str, language: Option<&str>, crawled_at: i64) -> Self {
+        Self {
+            url: url.to_string(),
+            content_type: content_type.to_string(),
+            language: language.map(str::to_string),
+            crawled_at,
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            url: row.get(0)?,
+            content_type: row.get(1)?,
+            language: row.get(2)?,
+            crawled_at: row.get(3)?,
+        })
+    }
+}
+
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE documents (
+            url STRING NOT NULL,
+            content_type TEXT NOT NULL,
+            language TEXT,
+            crawled_at INTEGER NOT NULL,
+            PRIMARY KEY (url)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn set_metadata(connection: &Connection, metadata: &Metadata) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            documents (url, content_type, language, crawled_at)
+        VALUES
+            (?1, ?2, ?3, ?4)
+        ON CONFLICT
+            (url)
+        DO UPDATE
+        SET
+            content_type = ?2,
+            language = ?3,
+            crawled_at = ?4
+        ",
+        params![
+            metadata.url,
+            metadata.content_type,
+            metadata.language,
+            metadata.crawled_at
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_metadata(connection: &Connection, url: &str) -> Result<Option<Metadata>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, content_type, language, crawled_at
+        FROM
+            documents
+        WHERE
+            url = ?1
+        LIMIT
+            1
+        ",
+    )?;
+
+    let result: Option<Metadata> = statement
+        .query_row(params![url], Metadata::from_row)
+        .optional()?;
+    Ok(result)
+}
+
+/// The metadata of those urls that have any.
+pub fn get_metadata_by_urls(
+    connection: &Connection,
+    urls: &[&str],
+) -> Result<HashMap<String, Metadata>> {
+    let results = db::query_by_urls(
+        connection,
+        "
+        SELECT
+            url, content_type, language, crawled_at
+        FROM
+            documents
+        WHERE
+            url IN ({})
+        ",
+        urls,
+        Metadata::from_row,
+    )?;
+    Ok(results.into_iter().map(|m| (m.url.clone(), m)).collect())
+}
+
+/// Every crawled document.
+pub fn get_all_urls(connection: &Connection) -> Result<Vec<String>> {
+    let mut statement = connection.prepare("SELECT url FROM documents")?;
+
+    let results: Vec<String> = statement
+        .query_map(params![], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn delete_metadata(connection: &Connection, url: &str) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            documents
+        WHERE
+            url = ?1
+        ",
+        params![url],
+    )?;
+    Ok(())
+}
+
+/// Replaces the terms indexed for one field of a document, keeping the collection statistics in
+/// step so the change is searchable as soon as it's committed. Every write to the postings should
+/// go through here, or through `delete`, otherwise the statistics will drift.
+pub fn replace_field(
+    connection: &Connection,
+    url: &str,
+    field: Field,
+    terms: &[TermFrequency],
+) -> Result<()> {
+    let had_document = field_lengths::has_url(connection, url)?;
+    let old_length = field_lengths::get(connection, url, field)?.unwrap_or(0);
+
+    let old_terms = term_frequencies::get_by_url_and_field(connection, url, field)?;
+    for t in &old_terms {
+        term_stats::add(connection, field, &t.term, -1)?;
+    }
+    term_frequencies::delete_by_url_and_field(connection, url, field)?;
+
+    let mut length = 0;
+    for t in terms.iter().filter(|t| t.field == field) {
+        term_frequencies::insert(connection, t)?;
+        term_stats::add(connection, field, &t.term, 1)?;
+        length += t.count;
+    }
+
+    // Only terms coming into or going out of the vocabulary change the spelling index
+    let new_terms: HashSet<&str> = terms
+        .iter()
+        .filter(|t| t.field == field)
+        .map(|t| t.term.as_str())
+        .collect();
+    let old_terms: HashSet<&str> = old_terms.iter().map(|t| t.term.as_str()).collect();
+    for term in old_terms.difference(&new_terms) {
+        if term_stats::get_by_term(connection, term)?.is_empty() {
+            spelling::remove(connection, term)?;
+        }
+    }
+    for term in new_terms.difference(&old_terms) {
+        spelling::add(connection, term)?;
+    }
+    field_lengths::set(connection, url, field, length)?;
+
+    let document_delta = (length > 0) as i64 - (old_length > 0) as i64;
+    field_stats::add(
+        connection,
+        field,
+        document_delta,
+        length as i64 - old_length as i64,
+    )?;
+
+    let has_document = field_lengths::has_url(connection, url)?;
+    if had_document != has_document {
+        collection_stats::add_documents(connection, if has_document { 1 } else { -1 })?;
+    }
+
+    Ok(())
+}
+
+/// Removes the given fields of a document from the index.
+pub fn delete(connection: &Connection, url: &str, fields: &[Field]) -> Result<()> {
+    for field in fields {
+        replace_field(connection, url, *field, &[])?;
+    }
+    Ok(())
+}
+
+/// Recomputes the collection statistics from the postings, for indexes built before they were
+/// maintained or to repair them.
+pub fn rebuild_stats(connection: &Connection) -> Result<()> {
+    term_stats::rebuild(connection)?;
+    field_stats::rebuild(connection)?;
+    collection_stats::rebuild(connection)?;
+    spelling::rebuild(connection)?;
+    Ok(())
+}
+
+/// Indexes the text as the field of a document, for tests.
+#[cfg(test)]
+pub fn index(connection: &Connection, url: &str, field: Field, text: &str) {
+    let analyzer = crate::analysis::Analyzer::default();
+    let terms =
+        crate::actions::crawl::analyze_terms(&analyzer, field, &mut text.as_bytes(), url).unwrap();
+    replace_field(connection, url, field, &terms).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_replace_field() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://a.example.com",
+            Field::Body,
+            "alpha bravo",
+        );
+        index(&connection, "http://a.example.com", Field::Title, "alpha");
+        index(
+            &connection,
+            "http://b.example.com",
+            Field::Body,
+            "alpha charlie delta 2024",
+        );
+
+        assert_eq!(
+            2,
+            collection_stats::get_document_count(&connection).unwrap()
+        );
+        assert_eq!(
+            2,
+            term_stats::get(&connection, Field::Body, "alpha").unwrap()
+        );
+        assert_eq!(
+            1,
+            term_stats::get(&connection, Field::Body, "2024").unwrap()
+        );
+        assert_eq!(
+            Some(&3.0),
+            field_stats::get_average_lengths(&connection)
+                .unwrap()
+                .get(&Field::Body)
+        );
+
+        index(
+            &connection,
+            "http://b.example.com",
+            Field::Body,
+            "echo 2025",
+        );
+        assert_eq!(
+            1,
+            term_stats::get(&connection, Field::Body, "alpha").unwrap()
+        );
+        assert_eq!(
+            0,
+            term_stats::get(&connection, Field::Body, "2024").unwrap()
+        );
+        assert_eq!(
+            1,
+            term_stats::get(&connection, Field::Body, "echo").unwrap()
+        );
+
+        delete(&connection, "http://a.example.com", &Field::ALL).unwrap();
+        assert_eq!(
+            1,
+            collection_stats::get_document_count(&connection).unwrap()
+        );
+        assert_eq!(
+            0,
+            term_stats::get(&connection, Field::Body, "alpha").unwrap()
+        );
+        assert_eq!(
+            None,
+            field_stats::get_average_lengths(&connection)
+                .unwrap()
+                .get(&Field::Title)
+        );
+    }
+
+    #[test]
+    fn test_rebuild_stats() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        index(
+            &connection,
+            "http://a.example.com",
+            Field::Body,
+            "alpha bravo",
+        );
+        index(&connection, "http://b.example.com", Field::Body, "alpha");
+        index(&connection, "http://b.example.com", Field::Anchor, "alpha");
+
+        let count = collection_stats::get_document_count(&connection).unwrap();
+        let lengths = field_stats::get_average_lengths(&connection).unwrap();
+        let alpha = term_stats::get_by_term(&connection, "alpha").unwrap();
+
+        rebuild_stats(&connection).unwrap();
+        assert_eq!(
+            count,
+            collection_stats::get_document_count(&connection).unwrap()
+        );
+        assert_eq!(
+            lengths,
+            field_stats::get_average_lengths(&connection).unwrap()
+        );
+        assert_eq!(
+            alpha,
+            term_stats::get_by_term(&connection, "alpha").unwrap()
+        );
+    }
+}
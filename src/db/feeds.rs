@@ -0,0 +1,229 @@
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::Serialize;
+
+/// How long after a feed was polled it's polled again, blogs don't post often but when they do
+/// the post should be searchable soon after.
+static POLL_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+/// An RSS, Atom or JSON feed, either subscribed to or found linked from a crawled page and
+/// waiting to be. Only subscriptions are polled.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub url: String,
+    pub subscribed: bool,
+    /// The page the feed was found on, for feeds found rather than subscribed to.
+    pub source: Option<String>,
+    pub last_polled: i64,
+}
+
+impl Feed {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            url: row.get(0)?,
+            subscribed: row.get(1)?,
+            source: row.get(2)?,
+            last_polled: row.get(3)?,
+        })
+    }
+}
+
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE feeds (
+            url TEXT NOT NULL,
+            subscribed INTEGER NOT NULL,
+            source TEXT,
+            last_polled INTEGER NOT NULL,
+            PRIMARY KEY (url)
+        )
+        ",
+        params![],
+    )?;
+    create_items_table(connection)
+}
+
+/// The entries listed in each feed. `stub` marks those indexed from what the feed said about
+/// them, until the page itself is crawled.
+pub fn create_items_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE feed_items (
+            url TEXT NOT NULL,
+            feed TEXT NOT NULL,
+            stub INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (feed, url)
+        )
+        ",
+        params![],
+    )?;
+    connection.execute("CREATE INDEX feed_items_url ON feed_items (url)", params![])?;
+    Ok(())
+}
+
+/// Subscribes to a feed, whether or not it was found before.
+pub fn subscribe(connection: &Connection, url: &str) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            feeds (url, subscribed, source, last_polled)
+        VALUES
+            (?1, 1, NULL, -1)
+        ON CONFLICT
+            (url)
+        DO UPDATE
+        SET
+            subscribed = 1
+        ",
+        params![url],
+    )?;
+    Ok(())
+}
+
+/// Records a feed linked from a page, leaving alone one that's already known.
+pub fn discover(connection: &Connection, url: &str, source: &str) -> Result<()> {
+    connection.execute(
+        "INSERT OR IGNORE INTO
+            feeds (url, subscribed, source, last_polled)
+        VALUES
+            (?1, 0, ?2, -1)
+        ",
+        params![url, source],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, url: &str) -> Result<Option<Feed>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, subscribed, source, last_polled
+        FROM
+            feeds
+        WHERE
+            url = ?1
+        ",
+    )?;
+    Ok(statement
+        .query_row(params![url], Feed::from_row)
+        .optional()?)
+}
+
+/// Subscriptions first.
+pub fn get_all(connection: &Connection) -> Result<Vec<Feed>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, subscribed, source, last_polled
+        FROM
+            feeds
+        ORDER BY
+            subscribed DESC, url
+        ",
+    )?;
+    let results: Vec<Feed> = statement
+        .query_map(params![], Feed::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// The subscriptions not polled in the last `POLL_INTERVAL`.
+pub fn get_all_needing_update(connection: &Connection) -> Result<Vec<Feed>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, subscribed, source, last_polled
+        FROM
+            feeds
+        WHERE
+            subscribed = 1 AND
+            last_polled < ?1
+        ORDER BY
+            url
+        ",
+    )?;
+    let last_polled = (Utc::now() - POLL_INTERVAL).timestamp();
+    let results: Vec<Feed> = statement
+        .query_map(params![last_polled], Feed::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn set_polled(connection: &Connection, url: &str, polled_at: i64) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            feeds
+        SET
+            last_polled = ?2
+        WHERE
+            url = ?1
+        ",
+        params![url, polled_at],
+    )?;
+    Ok(())
+}
+
+/// Removes a feed along with the record of the entries seen in it.
+pub fn delete(connection: &Connection, url: &str) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            feeds
+        WHERE
+            url = ?1
+        ",
+        params![url],
+    )?;
+    connection.execute(
+        "DELETE
+        FROM
+            feed_items
+        WHERE
+            feed = ?1
+        ",
+        params![url],
+    )?;
+    Ok(())
+}
+
+/// Records an entry of a feed, one listed by several feeds counts for each of them.
+pub fn add_item(connection: &Connection, feed: &str, url: &str) -> Result<()> {
+    connection.execute(
+        "INSERT OR IGNORE INTO
+            feed_items (url, feed)
+        VALUES
+            (?1, ?2)
+        ",
+        params![url, feed],
+    )?;
+    Ok(())
+}
+
+/// Marks what's indexed under an entry's url as coming from the feed, or clears the mark once
+/// the page itself has been indexed.
+pub fn set_stub(connection: &Connection, url: &str, stub: bool) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            feed_items
+        SET
+            stub = ?2
+        WHERE
+            url = ?1
+        ",
+        params![url, stub],
+    )?;
+    Ok(())
+}
+
+/// Whether what's indexed under the url only came from a feed.
+pub fn is_stub(connection: &Connection, url: &str) -> Result<bool> {
+    let stub: bool = connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM feed_items WHERE url = ?1 AND stub = 1)",
+        params![url],
+        |row| row.get(0),
+    )?;
+    Ok(stub)
+}
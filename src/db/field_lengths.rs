@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::analysis::fields::Field;
+
+/// The number of terms in each field of a document, used to normalize scores so that long
+/// documents don't win just by virtue of containing more words.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE field_lengths (
+            url STRING NOT NULL,
+            field STRING NOT NULL,
+            length INTEGER NOT NULL,
+            PRIMARY KEY (url, field)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Fields without any terms are removed rather than stored as zero, they don't count towards the
+/// average length of the field.
+pub fn set(connection: &Connection, url: &str, field: Field, length: u64) -> Result<()> {
+    if length == 0 {
+        connection.execute(
+            "DELETE
+            FROM
+                field_lengths
+            WHERE
+                url = ?1 AND
+                field = ?2
+            ",
+            params![url, field],
+        )?;
+        return Ok(());
+    }
+
+    connection.execute(
+        "INSERT INTO
+            field_lengths (url, field, length)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (url, field)
+        DO UPDATE
+        SET
+            length = ?3
+        ",
+        params![url, field, length],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, url: &str, field: Field) -> Result<Option<u64>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            length
+        FROM
+            field_lengths
+        WHERE
+            url = ?1 AND
+            field = ?2
+        LIMIT
+            1
+        ",
+    )?;
+
+    let result: Option<u64> = statement
+        .query_row(params![url, field], |row| row.get(0))
+        .optional()?;
+    Ok(result)
+}
+
+/// The lengths of every field containing `term`.
+pub fn get_by_term(connection: &Connection, term: &str) -> Result<HashMap<(String, Field), u64>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            fl.url, fl.field, fl.length
+        FROM
+            term_frequencies AS tf
+        JOIN
+            field_lengths AS fl
+        ON
+            fl.url = tf.url AND
+            fl.field = tf.field
+        WHERE
+            tf.term = ?1
+        ",
+    )?;
+
+    let results: HashMap<(String, Field), u64> = statement
+        .query_map(params![term], |row| {
+            Ok(((row.get(0)?, row.get(1)?), row.get(2)?))
+        })?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// Whether any field of the document has been indexed.
+pub fn has_url(connection: &Connection, url: &str) -> Result<bool> {
+    let exists: bool = connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM field_lengths WHERE url = ?1)",
+        params![url],
+        |row| row.get(0),
+    )?;
+    Ok(exists)
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+use crate::analysis::fields::Field;
+
+/// The number of documents with each field and the total number of terms in it, so that the
+/// average field length is available without scanning every document.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE field_stats (
+            field STRING NOT NULL,
+            document_count INTEGER NOT NULL,
+            total_length INTEGER NOT NULL,
+            PRIMARY KEY (field)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn add(
+    connection: &Connection,
+    field: Field,
+    document_delta: i64,
+    length_delta: i64,
+) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            field_stats (field, document_count, total_length)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (field)
+        DO UPDATE
+        SET
+            document_count = document_count + ?2,
+            total_length = total_length + ?3
+        ",
+        params![field, document_delta, length_delta],
+    )?;
+    Ok(())
+}
+
+pub fn get_average_lengths(connection: &Connection) -> Result<HashMap<Field, f64>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            field, CAST(total_length AS REAL) / document_count
+        FROM
+            field_stats
+        WHERE
+            document_count > 0
+        ",
+    )?;
+
+    let results: HashMap<Field, f64> = statement
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// Recounts every field from the stored field lengths.
+pub fn rebuild(connection: &Connection) -> Result<()> {
+    connection.execute("DELETE FROM field_stats", params![])?;
+    connection.execute(
+        "
+        INSERT INTO
+            field_stats (field, document_count, total_length)
+        SELECT
+            field, COUNT(*), SUM(length)
+        FROM
+            field_lengths
+        GROUP BY
+            field
+        ",
+        params![],
+    )?;
+    Ok(())
+}
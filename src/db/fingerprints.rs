@@ -0,0 +1,295 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::analysis::simhash;
+use crate::db;
+
+/// Documents whose fingerprints differ in at most this many bits are near duplicates.
+pub static MAX_DISTANCE: u32 = 3;
+
+/// A document's SimHash and the cluster of near duplicates it belongs to, named after the
+/// cluster's canonical member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub url: String,
+    pub fingerprint: u64,
+    pub cluster: String,
+}
+
+impl Fingerprint {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let fingerprint: i64 = row.get(1)?;
+        Ok(Self {
+            url: row.get(0)?,
+            fingerprint: fingerprint as u64,
+            cluster: row.get(2)?,
+        })
+    }
+}
+
+/// The fingerprint split into four blocks of 16 bits. Fingerprints within `MAX_DISTANCE` bits
+/// of each other have at least one block in common, so near duplicates are found by looking up
+/// each block rather than comparing against every document.
+fn blocks(fingerprint: u64) -> [i64; 4] {
+    [0, 1, 2, 3].map(|i| ((fingerprint >> (i * 16)) & 0xffff) as i64)
+}
+
+/// The member a cluster is shown as, the shortest url since mirrors and print versions tend to
+/// add to the original's.
+fn canonical<'a>(urls: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    urls.into_iter()
+        .min_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)))
+}
+
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE fingerprints (
+            url TEXT NOT NULL,
+            fingerprint INTEGER NOT NULL,
+            block_0 INTEGER NOT NULL,
+            block_1 INTEGER NOT NULL,
+            block_2 INTEGER NOT NULL,
+            block_3 INTEGER NOT NULL,
+            cluster TEXT NOT NULL,
+            PRIMARY KEY (url)
+        )
+        ",
+        params![],
+    )?;
+    for i in 0..4 {
+        connection.execute(
+            &format!(
+                "CREATE INDEX fingerprints_block_{0} ON fingerprints (block_{0})",
+                i
+            ),
+            params![],
+        )?;
+    }
+    connection.execute(
+        "CREATE INDEX fingerprints_cluster ON fingerprints (cluster)",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, url: &str) -> Result<Option<Fingerprint>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, fingerprint, cluster
+        FROM
+            fingerprints
+        WHERE
+            url = ?1
+        ",
+    )?;
+    Ok(statement
+        .query_row(params![url], Fingerprint::from_row)
+        .optional()?)
+}
+
+/// The fingerprints of those urls that have one.
+pub fn get_by_urls(connection: &Connection, urls: &[&str]) -> Result<HashMap<String, Fingerprint>> {
+    let results = db::query_by_urls(
+        connection,
+        "
+        SELECT
+            url, fingerprint, cluster
+        FROM
+            fingerprints
+        WHERE
+            url IN ({})
+        ",
+        urls,
+        Fingerprint::from_row,
+    )?;
+    Ok(results.into_iter().map(|f| (f.url.clone(), f)).collect())
+}
+
+pub fn get_by_cluster(connection: &Connection, cluster: &str) -> Result<Vec<Fingerprint>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, fingerprint, cluster
+        FROM
+            fingerprints
+        WHERE
+            cluster = ?1
+        ",
+    )?;
+    let results: Vec<Fingerprint> = statement
+        .query_map(params![cluster], Fingerprint::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// The documents within `MAX_DISTANCE` bits of the fingerprint.
+pub fn get_near_duplicates(connection: &Connection, fingerprint: u64) -> Result<Vec<Fingerprint>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            url, fingerprint, cluster
+        FROM
+            fingerprints
+        WHERE
+            block_0 = ?1 OR
+            block_1 = ?2 OR
+            block_2 = ?3 OR
+            block_3 = ?4
+        ",
+    )?;
+    let [b0, b1, b2, b3] = blocks(fingerprint);
+    let results: Vec<Fingerprint> = statement
+        .query_map(params![b0, b1, b2, b3], Fingerprint::from_row)?
+        .flatten()
+        .filter(|f| simhash::distance(f.fingerprint, fingerprint) <= MAX_DISTANCE)
+        .collect();
+    Ok(results)
+}
+
+fn set_cluster(connection: &Connection, url: &str, cluster: &str) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            fingerprints
+        SET
+            cluster = ?2
+        WHERE
+            url = ?1
+        ",
+        params![url, cluster],
+    )?;
+    Ok(())
+}
+
+/// Stores a document's fingerprint and merges the clusters of its near duplicates into one.
+pub fn set(connection: &Connection, url: &str, fingerprint: u64) -> Result<()> {
+    remove(connection, url)?;
+
+    let [b0, b1, b2, b3] = blocks(fingerprint);
+    connection.execute(
+        "INSERT INTO
+            fingerprints (url, fingerprint, block_0, block_1, block_2, block_3, cluster)
+        VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6, ?1)
+        ",
+        params![url, fingerprint as i64, b0, b1, b2, b3],
+    )?;
+
+    let mut members = BTreeSet::from([url.to_string()]);
+    let clusters: BTreeSet<String> = get_near_duplicates(connection, fingerprint)?
+        .into_iter()
+        .map(|f| f.cluster)
+        .collect();
+    for cluster in clusters {
+        members.extend(
+            get_by_cluster(connection, &cluster)?
+                .into_iter()
+                .map(|f| f.url),
+        );
+    }
+
+    let cluster = canonical(members.iter().map(|m| m.as_str())).unwrap_or(url);
+    for member in &members {
+        set_cluster(connection, member, cluster)?;
+    }
+    Ok(())
+}
+
+/// Takes a document out of its cluster. The rest of the cluster may only have been near
+/// duplicates through it, so they're split back up into the groups still near each other.
+pub fn remove(connection: &Connection, url: &str) -> Result<()> {
+    let Some(removed) = get(connection, url)? else {
+        return Ok(());
+    };
+    connection.execute(
+        "DELETE
+        FROM
+            fingerprints
+        WHERE
+            url = ?1
+        ",
+        params![url],
+    )?;
+
+    let mut rest = get_by_cluster(connection, &removed.cluster)?;
+    while let Some(first) = rest.pop() {
+        let mut group = vec![first];
+        let mut i = 0;
+        while i < group.len() {
+            let (near, far): (Vec<Fingerprint>, Vec<Fingerprint>) =
+                rest.into_iter().partition(|f| {
+                    simhash::distance(f.fingerprint, group[i].fingerprint) <= MAX_DISTANCE
+                });
+            group.extend(near);
+            rest = far;
+            i += 1;
+        }
+
+        let cluster = canonical(group.iter().map(|f| f.url.as_str()))
+            .unwrap()
+            .to_string();
+        for member in &group {
+            set_cluster(connection, &member.url, &cluster)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn cluster(connection: &Connection, url: &str) -> String {
+        get(connection, url).unwrap().unwrap().cluster
+    }
+
+    #[test]
+    fn test_clusters() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        let original = 0x0123_4567_89ab_cdef;
+        set(&connection, "http://a.com/article/print", original).unwrap();
+        set(&connection, "http://b.com/other", !original).unwrap();
+        // Both near the original but five bits from each other
+        set(&connection, "http://a.com/article", original ^ 0b11).unwrap();
+        set(
+            &connection,
+            "http://mirror.com/article",
+            original ^ 0b1_1100,
+        )
+        .unwrap();
+
+        for url in [
+            "http://a.com/article/print",
+            "http://a.com/article",
+            "http://mirror.com/article",
+        ] {
+            assert_eq!("http://a.com/article", cluster(&connection, url));
+        }
+        assert_eq!(
+            "http://b.com/other",
+            cluster(&connection, "http://b.com/other")
+        );
+
+        // Without the original the other two are too far apart
+        remove(&connection, "http://a.com/article/print").unwrap();
+        assert_eq!(
+            None,
+            get(&connection, "http://a.com/article/print").unwrap()
+        );
+        assert_eq!(
+            "http://a.com/article",
+            cluster(&connection, "http://a.com/article")
+        );
+        assert_eq!(
+            "http://mirror.com/article",
+            cluster(&connection, "http://mirror.com/article")
+        );
+    }
+}
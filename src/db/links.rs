@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+use crate::db;
+
+/// A link from one page to another along with the text it was given on the page it came from.
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    pub source: String,
+    pub target: String,
+    pub text: String,
+}
+
+impl Link {
+    pub fn new(source: &str, target: &str, text: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            target: target.to_string(),
+            text: text.to_string(),
+        }
+    }
+}
+
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE links (
+            source STRING NOT NULL,
+            target STRING NOT NULL,
+            text TEXT NOT NULL,
+            PRIMARY KEY (source, target, text)
+        )
+        ",
+        params![],
+    )?;
+    connection.execute("CREATE INDEX links_target ON links (target)", params![])?;
+    Ok(())
+}
+
+pub fn insert(connection: &Connection, link: &Link) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            links (source, target, text)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (source, target, text)
+        DO NOTHING
+        ",
+        params![link.source, link.target, link.text],
+    )?;
+    Ok(())
+}
+
+pub fn get_by_target(connection: &Connection, target: &str) -> Result<Vec<Link>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            source, target, text
+        FROM
+            links
+        WHERE
+            target = ?1
+        ORDER BY
+            source
+        ",
+    )?;
+
+    let results: Vec<Link> = statement
+        .query_map(params![target], |row| {
+            Ok(Link {
+                source: row.get(0)?,
+                target: row.get(1)?,
+                text: row.get(2)?,
+            })
+        })?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// The number of distinct pages linking to `target`.
+pub fn count_sources_by_target(connection: &Connection, target: &str) -> Result<u64> {
+    let count: u64 = connection.query_row(
+        "SELECT COUNT(DISTINCT source) FROM links WHERE target = ?1",
+        params![target],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// `count_sources_by_target` for many targets, those without links are left out.
+pub fn count_sources_by_targets(
+    connection: &Connection,
+    targets: &[&str],
+) -> Result<HashMap<String, u64>> {
+    let results = db::query_by_urls(
+        connection,
+        "SELECT
+            target, COUNT(DISTINCT source)
+        FROM
+            links
+        WHERE
+            target IN ({})
+        GROUP BY
+            target
+        ",
+        targets,
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(results.into_iter().collect())
+}
+
+pub fn get_targets_by_source(connection: &Connection, source: &str) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT DISTINCT
+            target
+        FROM
+            links
+        WHERE
+            source = ?1
+        ",
+    )?;
+
+    let results: Vec<String> = statement
+        .query_map(params![source], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn delete_by_source(connection: &Connection, source: &str) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            links
+        WHERE
+            source = ?1
+        ",
+        params![source],
+    )?;
+    Ok(())
+}
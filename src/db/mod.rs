@@ -1,15 +1,68 @@
 use anyhow::Result;
-use rusqlite::Connection;
+use rusqlite::{Connection, Row, params_from_iter};
 
+pub mod canonicals;
+pub mod collection_stats;
 pub mod crawls;
+pub mod documents;
+pub mod feeds;
+pub mod field_lengths;
+pub mod field_stats;
+pub mod fingerprints;
+pub mod links;
+pub mod queries;
+pub mod settings;
+pub mod sitemaps;
+pub mod spelling;
+pub mod synonyms;
 pub mod term_frequencies;
-pub mod tf_idf;
+pub mod term_stats;
 pub mod users;
+pub mod votes;
 
 pub fn initalize_tables(connection: &Connection) -> Result<()> {
     crawls::create_table(&connection)?;
+    documents::create_table(connection)?;
+    settings::create_table(connection)?;
+    links::create_table(connection)?;
+    field_lengths::create_table(connection)?;
     term_frequencies::create_table(&connection)?;
-    tf_idf::create_table(&connection)?;
+    term_stats::create_table(connection)?;
+    field_stats::create_table(connection)?;
+    collection_stats::create_table(connection)?;
+    spelling::create_table(connection)?;
     users::create_table(&connection)?;
+    votes::create_table(connection)?;
+    queries::create_table(connection)?;
+    synonyms::create_table(connection)?;
+    fingerprints::create_table(connection)?;
+    canonicals::create_table(connection)?;
+    sitemaps::create_table(connection)?;
+    feeds::create_table(connection)?;
     Ok(())
 }
+
+/// SQLite caps the number of parameters a statement takes, so lookups of many urls at once are
+/// made this many at a time.
+static URLS_PER_QUERY: usize = 500;
+
+/// Runs a query selecting by a list of urls, written as `IN ({})` in `sql`, for all of `urls`.
+/// Searching looks things up for every candidate, doing it in one go saves a round trip each.
+pub fn query_by_urls<T>(
+    connection: &Connection,
+    sql: &str,
+    urls: &[&str],
+    mut from_row: impl FnMut(&Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>> {
+    let mut results = Vec::with_capacity(urls.len());
+    for chunk in urls.chunks(URLS_PER_QUERY) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let mut statement = connection.prepare(&sql.replace("{}", &placeholders))?;
+        results.extend(
+            statement
+                .query_map(params_from_iter(chunk), &mut from_row)?
+                .flatten(),
+        );
+    }
+    Ok(results)
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+/// Queries people have searched for and found something with, to suggest to the next person
+/// typing the same thing. Queries differing only in case count as the same one.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE queries (
+            query TEXT NOT NULL COLLATE NOCASE PRIMARY KEY,
+            count INTEGER NOT NULL
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn record(connection: &Connection, query: &str) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            queries (query, count)
+        VALUES
+            (?1, 1)
+        ON CONFLICT
+            (query)
+        DO UPDATE
+        SET
+            count = count + 1
+        ",
+        params![query],
+    )?;
+    Ok(())
+}
+
+/// Queries starting with the prefix searched for at least `min_count` times, the most searched
+/// for first.
+pub fn get_by_prefix(
+    connection: &Connection,
+    prefix: &str,
+    min_count: u64,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            query
+        FROM
+            queries
+        WHERE
+            query >= ?1 AND
+            query < ?2 AND
+            count >= ?3
+        ORDER BY
+            count DESC,
+            query
+        LIMIT
+            ?4
+        ",
+    )?;
+
+    let end = format!("{}\u{10FFFF}", prefix);
+    let results: Vec<String> = statement
+        .query_map(params![prefix, end, min_count, limit], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
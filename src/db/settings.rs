@@ -0,0 +1,78 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+/// Settings which belong to an index rather than to a particular process, for instance choices
+/// that have to agree between the time a document is indexed and the time it's searched.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE settings (
+            key STRING NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (key)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, key: &str) -> Result<Option<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            value
+        FROM
+            settings
+        WHERE
+            key = ?1
+        LIMIT
+            1
+        ",
+    )?;
+
+    let result: Option<String> = statement
+        .query_row(params![key], |row| row.get(0))
+        .optional()?;
+    Ok(result)
+}
+
+pub fn set(connection: &Connection, key: &str, value: &str) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            settings (key, value)
+        VALUES
+            (?1, ?2)
+        ON CONFLICT
+            (key)
+        DO UPDATE
+        SET
+            value = ?2
+        ",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Every setting whose key starts with `prefix`, keyed by the rest of the key.
+pub fn get_by_prefix(connection: &Connection, prefix: &str) -> Result<Vec<(String, String)>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            key, value
+        FROM
+            settings
+        WHERE
+            substr(key, 1, length(?1)) = ?1
+        ",
+    )?;
+
+    let results: Vec<(String, String)> = statement
+        .query_map(params![prefix], |row| {
+            let key: String = row.get(0)?;
+            Ok((key[prefix.len()..].to_string(), row.get(1)?))
+        })?
+        .flatten()
+        .collect();
+    Ok(results)
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use rusqlite::{Connection, params};
+
+/// Sites whose sitemaps are read for pages to crawl, again each day so new pages and changes to
+/// old ones are picked up. The site is the root url, `https://example.com/`.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE sitemaps (
+            site STRING NOT NULL,
+            last_read INTEGER NOT NULL,
+            PRIMARY KEY (site)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Adds a site, leaving alone one that's already there.
+pub fn insert(connection: &Connection, site: &str) -> Result<()> {
+    connection.execute(
+        "INSERT OR IGNORE INTO
+            sitemaps (site, last_read)
+        VALUES
+            (?1, -1)
+        ",
+        params![site],
+    )?;
+    Ok(())
+}
+
+/// The sites whose sitemaps weren't read in the last day.
+pub fn get_all_needing_update(connection: &Connection) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            site
+        FROM
+            sitemaps
+        WHERE
+            last_read < ?1
+        ORDER BY
+            site
+        ",
+    )?;
+    let last_read = (Utc::now() - TimeDelta::days(1)).timestamp();
+    let results: Vec<String> = statement
+        .query_map(params![last_read], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn set_read(connection: &Connection, site: &str, read_at: i64) -> Result<()> {
+    connection.execute(
+        "UPDATE
+            sitemaps
+        SET
+            last_read = ?2
+        WHERE
+            site = ?1
+        ",
+        params![site, read_at],
+    )?;
+    Ok(())
+}
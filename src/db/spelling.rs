@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+/// Longer terms are mostly identifiers and the like, which aren't worth correcting towards.
+static MAX_TERM_LENGTH: usize = 20;
+
+/// Terms are stored under enough deletions to be found from anything within this many edits.
+pub static MAX_DISTANCE: usize = 2;
+
+/// Short terms are only corrected by a single edit, otherwise nearly everything would be a
+/// candidate.
+pub fn max_distance(term: &str) -> usize {
+    if term.chars().count() < 5 { 1 } else { 2 }
+}
+
+/// Every string left after deleting up to `distance` characters from the term, the term itself
+/// included. Two terms within `distance` edits of each other always share one of these.
+pub fn deletions(term: &str, distance: usize) -> BTreeSet<String> {
+    let mut deletions = BTreeSet::from([term.to_string()]);
+    let mut last = deletions.clone();
+    for _ in 0..distance {
+        let mut next = BTreeSet::new();
+        for d in &last {
+            let chars: Vec<char> = d.chars().collect();
+            for i in 0..chars.len() {
+                next.insert(
+                    chars[..i]
+                        .iter()
+                        .chain(chars[i + 1..].iter())
+                        .collect::<String>(),
+                );
+            }
+        }
+        deletions.extend(next.iter().cloned());
+        last = next;
+    }
+    deletions
+}
+
+/// A symmetric delete index over the vocabulary, every term is stored under each of its
+/// deletions so that close terms can be looked up without scanning the whole vocabulary.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE spelling_deletions (
+            deletion TEXT NOT NULL,
+            term TEXT NOT NULL,
+            PRIMARY KEY (deletion, term)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn has_term(connection: &Connection, term: &str) -> Result<bool> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            1
+        FROM
+            spelling_deletions
+        WHERE
+            deletion = ?1 AND
+            term = ?1
+        ",
+    )?;
+    let result: Option<i64> = statement
+        .query_row(params![term], |row| row.get(0))
+        .optional()?;
+    Ok(result.is_some())
+}
+
+/// Adds a term to the vocabulary if it isn't there already.
+pub fn add(connection: &Connection, term: &str) -> Result<()> {
+    if term.chars().count() > MAX_TERM_LENGTH || has_term(connection, term)? {
+        return Ok(());
+    }
+    let mut statement = connection.prepare(
+        "INSERT OR IGNORE INTO
+            spelling_deletions (deletion, term)
+        VALUES
+            (?1, ?2)
+        ",
+    )?;
+    for deletion in deletions(term, MAX_DISTANCE) {
+        statement.execute(params![deletion, term])?;
+    }
+    Ok(())
+}
+
+pub fn remove(connection: &Connection, term: &str) -> Result<()> {
+    let mut statement = connection.prepare(
+        "DELETE
+        FROM
+            spelling_deletions
+        WHERE
+            deletion = ?1 AND
+            term = ?2
+        ",
+    )?;
+    for deletion in deletions(term, MAX_DISTANCE) {
+        statement.execute(params![deletion, term])?;
+    }
+    Ok(())
+}
+
+/// The terms stored under any of the deletions.
+pub fn get_terms(
+    connection: &Connection,
+    deletions: &BTreeSet<String>,
+) -> Result<BTreeSet<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            term
+        FROM
+            spelling_deletions
+        WHERE
+            deletion = ?1
+        ",
+    )?;
+    let mut terms = BTreeSet::new();
+    for deletion in deletions {
+        terms.extend(
+            statement
+                .query_map(params![deletion], |row| row.get::<_, String>(0))?
+                .flatten(),
+        );
+    }
+    Ok(terms)
+}
+
+/// Indexes every term in the collection statistics from scratch.
+pub fn rebuild(connection: &Connection) -> Result<()> {
+    connection.execute("DELETE FROM spelling_deletions", params![])?;
+    let terms: Vec<String> = connection
+        .prepare("SELECT DISTINCT term FROM term_stats")?
+        .query_map(params![], |row| row.get(0))?
+        .flatten()
+        .collect();
+    for term in terms {
+        add(connection, &term)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_deletions() {
+        let expected: BTreeSet<String> = ["abc", "ab", "ac", "bc"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(expected, deletions("abc", 1));
+        assert_eq!(7, deletions("abc", 2).len());
+    }
+
+    #[test]
+    fn test_add_remove() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+
+        add(&connection, "rust").unwrap();
+        add(&connection, "python").unwrap();
+        assert!(has_term(&connection, "rust").unwrap());
+
+        let terms = get_terms(&connection, &deletions("ruts", 2)).unwrap();
+        assert!(terms.contains("rust"));
+        assert!(!terms.contains("python"));
+
+        remove(&connection, "rust").unwrap();
+        assert!(!has_term(&connection, "rust").unwrap());
+        let terms = get_terms(&connection, &deletions("ruts", 2)).unwrap();
+        assert!(!terms.contains("rust"));
+    }
+}
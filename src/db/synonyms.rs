@@ -0,0 +1,96 @@
+use anyhow::Result;
+use rusqlite::{Connection, Row, params};
+use serde::Serialize;
+
+/// Searching for the source also searches for the target, with the target's matches scaled by
+/// the weight. Both are analyzed terms joined by single spaces, so they can be several words.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Synonym {
+    pub source: String,
+    pub target: String,
+    pub weight: f64,
+}
+
+impl Synonym {
+    pub fn new(source: &str, target: &str, weight: f64) -> Self {
+        Self {
+            source: source.to_string(),
+            target: target.to_string(),
+            weight,
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            source: row.get(0)?,
+            target: row.get(1)?,
+            weight: row.get(2)?,
+        })
+    }
+}
+
+/// Synonyms only go one way, a pair of rows makes two terms equivalent.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE synonyms (
+            source TEXT NOT NULL,
+            target TEXT NOT NULL,
+            weight REAL NOT NULL,
+            PRIMARY KEY (source, target)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn set(connection: &Connection, synonym: &Synonym) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            synonyms (source, target, weight)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (source, target)
+        DO UPDATE
+        SET
+            weight = ?3
+        ",
+        params![synonym.source, synonym.target, synonym.weight],
+    )?;
+    Ok(())
+}
+
+pub fn delete(connection: &Connection, source: &str, target: &str) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            synonyms
+        WHERE
+            source = ?1 AND
+            target = ?2
+        ",
+        params![source, target],
+    )?;
+    Ok(())
+}
+
+pub fn get_all(connection: &Connection) -> Result<Vec<Synonym>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            source, target, weight
+        FROM
+            synonyms
+        ORDER BY
+            source, target
+        ",
+    )?;
+
+    let results: Vec<Synonym> = statement
+        .query_map(params![], Synonym::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
@@ -1,33 +1,125 @@
-use anyhow::Result;
-use rusqlite::{Connection, params};
+use anyhow::{Result, anyhow};
+use rusqlite::types::Type;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::analysis::fields::Field;
 
 #[derive(Debug, PartialEq)]
 pub struct TermFrequency {
     pub url: String,
+    pub field: Field,
     pub term: String,
     pub count: u64,
     pub frequency: f64,
+    /// Empty for terms indexed before positions were recorded, there's no phrase data for them.
+    pub positions: Vec<u32>,
 }
 
 impl TermFrequency {
-    pub fn new(url: &str, term: &str, count: u64, frequency: f64) -> Self {
+    pub fn new(
+        url: &str,
+        field: Field,
+        term: &str,
+        count: u64,
+        frequency: f64,
+        positions: Vec<u32>,
+    ) -> Self {
         Self {
             url: url.to_string(),
+            field,
             term: term.to_string(),
             count,
             frequency,
+            positions,
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let positions: Option<Vec<u8>> = row.get(5)?;
+        let positions = decode_positions(&positions.unwrap_or_default())
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Blob, e.into()))?;
+        Ok(Self {
+            url: row.get(0)?,
+            field: row.get(1)?,
+            term: row.get(2)?,
+            count: row.get(3)?,
+            frequency: row.get(4)?,
+            positions,
+        })
+    }
+}
+
+/// Positions are stored as the gaps between consecutive positions, each written as a variable
+/// length integer seven bits at a time, so the common case of a term appearing every few hundred
+/// words costs a byte or two per occurrence. The gaps can't be negative, so positions out of
+/// order are sorted first.
+pub fn encode_positions(positions: &[u32]) -> Vec<u8> {
+    let mut sorted = Vec::new();
+    let positions = if positions.is_sorted() {
+        positions
+    } else {
+        sorted.extend_from_slice(positions);
+        sorted.sort_unstable();
+        &sorted
+    };
+    let mut bytes = Vec::with_capacity(positions.len());
+    let mut last = 0;
+
+    for p in positions {
+        let mut gap = p - last;
+        last = *p;
+        while gap >= 0x80 {
+            bytes.push((gap as u8 & 0x7f) | 0x80);
+            gap >>= 7;
+        }
+        bytes.push(gap as u8);
+    }
+
+    bytes
+}
+
+/// Fails on anything `encode_positions` couldn't have written, a gap that doesn't fit in 32 bits,
+/// a position past the end of the range or a gap cut off part way.
+pub fn decode_positions(bytes: &[u8]) -> Result<Vec<u32>> {
+    let mut positions = Vec::new();
+    let mut last: u32 = 0;
+    let mut gap: u64 = 0;
+    let mut shift = 0;
+
+    for b in bytes {
+        if shift > 28 {
+            return Err(anyhow!("position gap longer than 32 bits"));
         }
+        gap |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            last = u32::try_from(gap)
+                .ok()
+                .and_then(|g| last.checked_add(g))
+                .ok_or(anyhow!("position out of range"))?;
+            positions.push(last);
+            gap = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    if shift > 0 {
+        return Err(anyhow!("position gap cut off"));
     }
+
+    Ok(positions)
 }
 
 pub fn create_table(connection: &Connection) -> Result<()> {
     connection.execute(
         "CREATE TABLE term_frequencies (
             url String NOT NULL,
-            term String NOT NULL,
+            field String NOT NULL,
+            term TEXT NOT NULL,
             count INTEGER NOT NULL,
             frequency REAL NOT NULL,
-            PRIMARY KEY (url, term)
+            positions BLOB NOT NULL,
+            PRIMARY KEY (url, field, term)
         )",
         params![],
     )?;
@@ -38,18 +130,132 @@ pub fn insert(connection: &Connection, entry: &TermFrequency) -> Result<()> {
     connection.execute(
         "INSERT INTO
             term_frequencies (
-                url, term, count, frequency
+                url, field, term, count, frequency, positions
             )
         VALUES
-            (?1, ?2, ?3, ?4)
+            (?1, ?2, ?3, ?4, ?5, ?6)
         ON CONFLICT
-            (url, term)
+            (url, field, term)
         DO UPDATE
         SET
-            count = ?3,
-            frequency = ?4
+            count = ?4,
+            frequency = ?5,
+            positions = ?6
         ",
-        params![entry.url, entry.term, entry.count, entry.frequency],
+        params![
+            entry.url,
+            entry.field,
+            entry.term,
+            entry.count,
+            entry.frequency,
+            encode_positions(&entry.positions)
+        ],
     )?;
     Ok(())
 }
+
+pub fn get(
+    connection: &Connection,
+    url: &str,
+    field: Field,
+    term: &str,
+) -> Result<Option<TermFrequency>> {
+    let mut statement = connection.prepare(
+        "SELECT
+            url, field, term, count, frequency, positions
+        FROM
+            term_frequencies
+        WHERE
+            url = ?1 AND
+            field = ?2 AND
+            term = ?3
+        LIMIT
+            1
+        ",
+    )?;
+
+    let result: Option<TermFrequency> = statement
+        .query_row(params![url, field, term], TermFrequency::from_row)
+        .optional()?;
+    Ok(result)
+}
+
+pub fn get_by_term(connection: &Connection, term: &str) -> Result<Vec<TermFrequency>> {
+    let mut statement = connection.prepare(
+        "SELECT
+            url, field, term, count, frequency, positions
+        FROM
+            term_frequencies
+        WHERE
+            term = ?1
+        ",
+    )?;
+
+    let results: Vec<TermFrequency> = statement
+        .query_map(params![term], TermFrequency::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn get_by_url_and_field(
+    connection: &Connection,
+    url: &str,
+    field: Field,
+) -> Result<Vec<TermFrequency>> {
+    let mut statement = connection.prepare(
+        "SELECT
+            url, field, term, count, frequency, positions
+        FROM
+            term_frequencies
+        WHERE
+            url = ?1 AND
+            field = ?2
+        ",
+    )?;
+
+    let results: Vec<TermFrequency> = statement
+        .query_map(params![url, field], TermFrequency::from_row)?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+pub fn delete_by_url_and_field(connection: &Connection, url: &str, field: Field) -> Result<()> {
+    connection.execute(
+        "DELETE
+        FROM
+            term_frequencies
+        WHERE
+            url = ?1 AND
+            field = ?2
+        ",
+        params![url, field],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_positions() {
+        let positions = vec![0, 1, 5, 127, 128, 300, 70000, 70001];
+        let encoded = encode_positions(&positions);
+        assert_eq!(vec![0, 1, 4, 122, 1, 172, 1, 196, 160, 4, 1], encoded);
+        assert_eq!(positions, decode_positions(&encoded).unwrap());
+        assert_eq!(
+            Vec::<u32>::new(),
+            decode_positions(&encode_positions(&[])).unwrap()
+        );
+        assert_eq!(
+            encoded,
+            encode_positions(&[70001, 0, 5, 1, 300, 127, 70000, 128])
+        );
+
+        assert!(decode_positions(&[0x80]).is_err());
+        assert!(decode_positions(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err());
+        assert!(decode_positions(&[0xff, 0xff, 0xff, 0xff, 0x0f, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::analysis::fields::Field;
+use crate::db;
+
+/// The number of documents containing each term in each field. Kept up to date as documents are
+/// indexed so that queries don't need to count postings.
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE term_stats (
+            field STRING NOT NULL,
+            term TEXT NOT NULL,
+            document_frequency INTEGER NOT NULL,
+            PRIMARY KEY (field, term)
+        )
+        ",
+        params![],
+    )?;
+    // Looking terms up across fields, and by prefix for autocomplete
+    connection.execute(
+        "CREATE INDEX term_stats_term ON term_stats (term)",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Adjusts the document frequency of a term, dropping it once no documents contain it.
+pub fn add(connection: &Connection, field: Field, term: &str, delta: i64) -> Result<()> {
+    connection.execute(
+        "INSERT INTO
+            term_stats (field, term, document_frequency)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (field, term)
+        DO UPDATE
+        SET
+            document_frequency = document_frequency + ?3
+        ",
+        params![field, term, delta],
+    )?;
+    connection.execute(
+        "DELETE
+        FROM
+            term_stats
+        WHERE
+            field = ?1 AND
+            term = ?2 AND
+            document_frequency <= 0
+        ",
+        params![field, term],
+    )?;
+    Ok(())
+}
+
+pub fn get(connection: &Connection, field: Field, term: &str) -> Result<u64> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            document_frequency
+        FROM
+            term_stats
+        WHERE
+            field = ?1 AND
+            term = ?2
+        LIMIT
+            1
+        ",
+    )?;
+
+    let result: Option<u64> = statement
+        .query_row(params![field, term], |row| row.get(0))
+        .optional()?;
+    Ok(result.unwrap_or(0))
+}
+
+/// The document frequency of a term in every field it appears in.
+pub fn get_by_term(connection: &Connection, term: &str) -> Result<HashMap<Field, u64>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            field, document_frequency
+        FROM
+            term_stats
+        WHERE
+            term = ?1
+        ",
+    )?;
+
+    let results: HashMap<Field, u64> = statement
+        .query_map(params![term], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// The document frequencies of the terms in every field they appear in.
+pub fn get_by_terms(
+    connection: &Connection,
+    terms: &[&str],
+) -> Result<HashMap<(Field, String), u64>> {
+    let results = db::query_by_urls(
+        connection,
+        "
+        SELECT
+            field, term, document_frequency
+        FROM
+            term_stats
+        WHERE
+            term IN ({})
+        ",
+        terms,
+        |row| Ok(((row.get(0)?, row.get(1)?), row.get(2)?)),
+    )?;
+    Ok(results.into_iter().collect())
+}
+
+/// Terms starting with the prefix in the field, or in any field, the ones in the most documents
+/// first.
+pub fn get_by_prefix(
+    connection: &Connection,
+    field: Option<Field>,
+    prefix: &str,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(
+        "
+        SELECT
+            term
+        FROM
+            term_stats
+        WHERE
+            term >= ?1 AND
+            term < ?2 AND
+            (?3 IS NULL OR field = ?3)
+        GROUP BY
+            term
+        ORDER BY
+            MAX(document_frequency) DESC,
+            term
+        LIMIT
+            ?4
+        ",
+    )?;
+
+    // Every string starting with the prefix sorts before it followed by the last code point
+    let end = format!("{}\u{10FFFF}", prefix);
+    let results: Vec<String> = statement
+        .query_map(params![prefix, end, field, limit], |row| row.get(0))?
+        .flatten()
+        .collect();
+    Ok(results)
+}
+
+/// Recounts every term from the postings.
+pub fn rebuild(connection: &Connection) -> Result<()> {
+    connection.execute("DELETE FROM term_stats", params![])?;
+    connection.execute(
+        "
+        INSERT INTO
+            term_stats (field, term, document_frequency)
+        SELECT
+            field, term, COUNT(*)
+        FROM
+            term_frequencies
+        GROUP BY
+            field, term
+        ",
+        params![],
+    )?;
+    Ok(())
+}
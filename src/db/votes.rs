@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+use crate::db;
+
+/// A user's judgment of a page, one vote per user per page, either up (1) or down (-1).
+pub fn create_table(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "
+        CREATE TABLE votes (
+            url STRING NOT NULL,
+            username STRING NOT NULL,
+            value INTEGER NOT NULL,
+            PRIMARY KEY (url, username)
+        )
+        ",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Records a user's vote, replacing any earlier one. A vote of zero withdraws it.
+pub fn set(connection: &Connection, url: &str, username: &str, value: i64) -> Result<()> {
+    if value == 0 {
+        connection.execute(
+            "DELETE
+            FROM
+                votes
+            WHERE
+                url = ?1 AND
+                username = ?2
+            ",
+            params![url, username],
+        )?;
+        return Ok(());
+    }
+
+    connection.execute(
+        "INSERT INTO
+            votes (url, username, value)
+        VALUES
+            (?1, ?2, ?3)
+        ON CONFLICT
+            (url, username)
+        DO UPDATE
+        SET
+            value = ?3
+        ",
+        params![url, username, value],
+    )?;
+    Ok(())
+}
+
+/// `get_total` for many urls, those without votes are left out.
+pub fn get_totals(connection: &Connection, urls: &[&str]) -> Result<HashMap<String, i64>> {
+    let results = db::query_by_urls(
+        connection,
+        "SELECT
+            url, SUM(value)
+        FROM
+            votes
+        WHERE
+            url IN ({})
+        GROUP BY
+            url
+        ",
+        urls,
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(results.into_iter().collect())
+}
+
+/// Up votes minus down votes.
+pub fn get_total(connection: &Connection, url: &str) -> Result<i64> {
+    let total: i64 = connection.query_row(
+        "SELECT COALESCE(SUM(value), 0) FROM votes WHERE url = ?1",
+        params![url],
+        |row| row.get(0),
+    )?;
+    Ok(total)
+}
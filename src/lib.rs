@@ -2,5 +2,8 @@
 #![feature(try_blocks)]
 
 pub mod actions;
+pub mod analysis;
 pub mod api;
 pub mod db;
+pub mod query;
+pub mod ranking;
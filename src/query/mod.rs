@@ -0,0 +1,154 @@
+use reqwest::Url;
+
+use crate::analysis::fields::Field;
+use crate::db::documents::Metadata;
+
+pub mod parser;
+pub mod spelling;
+pub mod synonyms;
+
+pub use parser::{ParseError, parse, parse_filters};
+
+/// A parsed search query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A single analyzed term, optionally restricted to one field.
+    Term(Option<Field>, String),
+    /// Terms which have to appear next to each other and in order.
+    Phrase(Option<Field>, Vec<String>),
+    /// Any term starting with the analyzed prefix, `prog*`.
+    Prefix(Option<Field>, String),
+    /// Any term within the given number of edits of the analyzed term, `serch~1`.
+    Fuzzy(Option<Field>, String, usize),
+    /// Restricts the results without contributing to their scores.
+    Filter(Filter),
+    /// Documents matching the inner query are left out of the results.
+    Not(Box<Query>),
+    /// Documents have to match every part, `a AND b`.
+    And(Vec<Query>),
+    /// Documents have to match at least one part, `a OR b`.
+    Or(Vec<Query>),
+    /// Parts written next to each other without an operator, combined with the search's default
+    /// operator.
+    Implicit(Vec<Query>),
+    /// What was written alongside its synonyms. Documents are scored by the best alternative they
+    /// match after scaling by its weight.
+    Alternatives(Vec<(Query, f64)>),
+}
+
+impl Query {
+    /// Whether the query only decides which documents match without scoring them, i.e. it's
+    /// made up of nothing but filters and exclusions.
+    pub fn is_predicate(&self) -> bool {
+        match self {
+            Query::Term(..)
+            | Query::Phrase(..)
+            | Query::Prefix(..)
+            | Query::Fuzzy(..)
+            | Query::Alternatives(_) => false,
+            Query::Filter(_) | Query::Not(_) => true,
+            Query::And(qs) | Query::Or(qs) | Query::Implicit(qs) => {
+                !qs.is_empty() && qs.iter().all(|q| q.is_predicate())
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Query::And(qs) | Query::Or(qs) | Query::Implicit(qs) if qs.is_empty())
+    }
+
+    /// Whether the query only matches documents from particular sites, in which case there's no
+    /// point limiting how many results come from each site.
+    pub fn restricts_site(&self) -> bool {
+        match self {
+            Query::Filter(Filter::Site(_)) => true,
+            Query::And(qs) | Query::Implicit(qs) => qs.iter().any(|q| q.restricts_site()),
+            Query::Or(qs) => !qs.is_empty() && qs.iter().all(|q| q.restricts_site()),
+            _ => false,
+        }
+    }
+
+    /// The terms that count towards a document's score, excluded ones don't. Prefixes and
+    /// fuzzy terms aren't included as they stand for whichever terms they expand to.
+    pub fn positive_terms(&self) -> Vec<&str> {
+        match self {
+            Query::Term(_, t) => vec![t.as_str()],
+            Query::Prefix(..) | Query::Fuzzy(..) => vec![],
+            Query::Phrase(_, p) => p.iter().map(|t| t.as_str()).collect(),
+            Query::Filter(_) | Query::Not(_) => vec![],
+            Query::And(qs) | Query::Or(qs) | Query::Implicit(qs) => {
+                qs.iter().flat_map(|q| q.positive_terms()).collect()
+            }
+            Query::Alternatives(alternatives) => alternatives
+                .iter()
+                .flat_map(|(q, _)| q.positive_terms())
+                .collect(),
+        }
+    }
+}
+
+/// Conditions on where a document came from rather than what it says.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// The document's host is the domain or one of its subdomains, `site:example.com`.
+    Site(String),
+    /// `lang:en`
+    Lang(String),
+    /// Either a full MIME type, `type:text/html`, or just its subtype, `type:html`.
+    Type(String),
+    /// Crawled at or after the timestamp, `after:2024-01-31`.
+    After(i64),
+    /// Crawled before the timestamp, `before:2024-01-31`.
+    Before(i64),
+}
+
+impl Filter {
+    /// Documents we haven't crawled ourselves, and so don't have metadata for, only match on
+    /// their url.
+    pub fn matches(&self, url: &str, metadata: Option<&Metadata>) -> bool {
+        match self {
+            Filter::Site(site) => Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_lowercase))
+                .is_some_and(|host| {
+                    host == *site
+                        || host
+                            .strip_suffix(site.as_str())
+                            .is_some_and(|sub| sub.ends_with('.'))
+                }),
+            Filter::Lang(lang) => metadata.is_some_and(|m| m.language.as_ref() == Some(lang)),
+            Filter::Type(t) => metadata.is_some_and(|m| {
+                let mime = m.content_type.to_lowercase();
+                if t.contains('/') {
+                    mime == *t
+                } else {
+                    mime.split_once('/').is_some_and(|(_, sub)| sub == t)
+                }
+            }),
+            Filter::After(at) => metadata.is_some_and(|m| m.crawled_at >= *at),
+            Filter::Before(at) => metadata.is_some_and(|m| m.crawled_at < *at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matches() {
+        let url = "http://docs.example.com/index.html";
+        let metadata = Metadata::new(url, "text/html", Some("en"), 1000);
+
+        assert!(Filter::Site("example.com".into()).matches(url, None));
+        assert!(Filter::Site("docs.example.com".into()).matches(url, None));
+        assert!(!Filter::Site("ample.com".into()).matches(url, None));
+        assert!(Filter::Lang("en".into()).matches(url, Some(&metadata)));
+        assert!(!Filter::Lang("en".into()).matches(url, None));
+        assert!(Filter::Type("html".into()).matches(url, Some(&metadata)));
+        assert!(Filter::Type("text/html".into()).matches(url, Some(&metadata)));
+        assert!(!Filter::Type("plain".into()).matches(url, Some(&metadata)));
+        assert!(Filter::After(1000).matches(url, Some(&metadata)));
+        assert!(!Filter::Before(1000).matches(url, Some(&metadata)));
+    }
+}
@@ -0,0 +1,670 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::analysis::Analyzer;
+use crate::analysis::fields::{self, Field};
+use crate::query::{Filter, Query};
+
+/// A query we couldn't make sense of. The position counts characters from one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: usize, message: &str) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// The text between a pair of quotes along with any prefix directly in front of the opening
+    /// one, `title:"..."`.
+    Phrase(Option<String>, String),
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// A `-` directly in front of something to leave out.
+    Minus,
+}
+
+/// Splits the query into tokens, each paired with its position. Operators have to be written in
+/// capitals so that "and" and "or" can still be searched for.
+fn lex(query: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let position = i + 1;
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((Token::Open, position));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::Close, position));
+                i += 1;
+            }
+            '"' => {
+                let (text, next) = read_phrase(&chars, i)?;
+                tokens.push((Token::Phrase(None, text), position));
+                i = next;
+            }
+            '-' if chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                tokens.push((Token::Minus, position));
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !matches!(chars[i], '(' | ')' | '"')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+
+                if let Some(prefix) = word.strip_suffix(':')
+                    && chars.get(i) == Some(&'"')
+                {
+                    let (text, next) = read_phrase(&chars, i)?;
+                    tokens.push((Token::Phrase(Some(prefix.to_string()), text), position));
+                    i = next;
+                    continue;
+                }
+
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                };
+                tokens.push((token, position));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads from the opening quote at `start` to its closing quote, returning the text between them
+/// and where to carry on from.
+fn read_phrase(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    match chars[start + 1..].iter().position(|c| *c == '"') {
+        Some(length) => Ok((
+            chars[start + 1..start + 1 + length].iter().collect(),
+            start + length + 2,
+        )),
+        None => Err(ParseError::new(start + 1, "Unterminated quote")),
+    }
+}
+
+/// Splits a `field:` prefix off of a word, as long as it names a field we index.
+pub(super) fn split_field(word: &str) -> (Option<Field>, &str) {
+    if let Some((name, rest)) = word.split_once(':')
+        && let Ok(field) = Field::try_from(name.to_lowercase().as_str())
+    {
+        return (Some(field), rest);
+    }
+    (None, word)
+}
+
+/// Urls are indexed as the words between their punctuation so a url scoped query needs to be
+/// broken up the same way before it's analyzed.
+pub(super) fn tokenize_field(analyzer: &Analyzer, field: Option<Field>, text: &str) -> Vec<String> {
+    match field {
+        Some(Field::Url) => analyzer.tokenize(&fields::url_text(text)),
+        _ => analyzer.tokenize(text),
+    }
+}
+
+/// Text which analyzes to several terms, like a url or a run of CJK characters, has to match
+/// them in order.
+pub(super) fn terms(field: Option<Field>, mut terms: Vec<String>) -> Query {
+    match terms.len() {
+        0 => Query::Implicit(vec![]),
+        1 => Query::Term(field, terms.remove(0)),
+        _ => Query::Phrase(field, terms),
+    }
+}
+
+/// `None` when the name isn't one of our filters, in which case it's just part of a word.
+pub(super) fn parse_filter(
+    name: &str,
+    value: &str,
+    position: usize,
+) -> Result<Option<Filter>, ParseError> {
+    let name = name.to_lowercase();
+    let value = value.trim().to_lowercase();
+
+    let filter = match name.as_str() {
+        "site" | "lang" | "type" if value.is_empty() => {
+            return Err(ParseError::new(
+                position,
+                &format!("{}: needs a value", name),
+            ));
+        }
+        "site" => {
+            let site = value.split_once("://").map_or(value.as_str(), |(_, s)| s);
+            Filter::Site(site.trim_end_matches('/').to_string())
+        }
+        "lang" => Filter::Lang(
+            value
+                .split(['-', '_'])
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        "type" => Filter::Type(value),
+        "after" | "before" => {
+            let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d").map_err(|_| {
+                ParseError::new(
+                    position,
+                    &format!(
+                        "{}: expects a date like 2024-01-31, not \"{}\"",
+                        name, value
+                    ),
+                )
+            })?;
+            let timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+            match name.as_str() {
+                "after" => Filter::After(timestamp),
+                _ => Filter::Before(timestamp),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(filter))
+}
+
+/// Builds a group without the parts that analyzed to nothing, unwrapping it when only one part
+/// is left.
+fn group(parts: Vec<Query>, make: fn(Vec<Query>) -> Query) -> Query {
+    let mut parts: Vec<Query> = parts.into_iter().filter(|q| !q.is_empty()).collect();
+    if parts.len() == 1 {
+        return parts.remove(0);
+    }
+    make(parts)
+}
+
+fn not(query: Query) -> Query {
+    if query.is_empty() {
+        return query;
+    }
+    Query::Not(Box::new(query))
+}
+
+/// A query that does nothing but leave documents out has nothing to search for.
+fn only_excludes(query: &Query) -> bool {
+    match query {
+        Query::Not(_) => true,
+        Query::Term(..)
+        | Query::Phrase(..)
+        | Query::Prefix(..)
+        | Query::Fuzzy(..)
+        | Query::Alternatives(_)
+        | Query::Filter(_) => false,
+        Query::And(qs) | Query::Or(qs) | Query::Implicit(qs) => {
+            !qs.is_empty() && qs.iter().all(only_excludes)
+        }
+    }
+}
+
+/// A recursive descent parser. From loosest to tightest binding the grammar is
+///
+/// ```text
+/// sequence := or*
+/// or       := and ("OR" and)*
+/// and      := unary ("AND" unary)*
+/// unary    := ("NOT" | "-") unary | primary
+/// primary  := "(" sequence ")" | phrase | filter | word
+/// ```
+///
+/// so `rust book OR guide` looks for rust along with either book or guide.
+struct Parser<'a> {
+    analyzer: &'a Analyzer,
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(_, p)| *p)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /// Makes sure the operator at `position` is followed by something it can apply to.
+    fn expect_operand(&self, operator: &str, position: usize) -> Result<(), ParseError> {
+        match self.peek() {
+            None | Some(Token::Close | Token::And | Token::Or) => Err(ParseError::new(
+                position,
+                &format!("Expected something to search for after {}", operator),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Parts written one after the other, up to the end of the query or, when `open` is the
+    /// position of an opening parenthesis, up to the one that closes it.
+    fn sequence(&mut self, open: Option<usize>) -> Result<Query, ParseError> {
+        let mut parts: Vec<Query> = Vec::new();
+        loop {
+            match self.peek() {
+                None => match open {
+                    Some(p) => return Err(ParseError::new(p, "Unclosed parenthesis")),
+                    None => break,
+                },
+                Some(Token::Close) => match open {
+                    Some(_) => {
+                        self.next += 1;
+                        break;
+                    }
+                    None => {
+                        return Err(ParseError::new(
+                            self.position(),
+                            "Unexpected closing parenthesis",
+                        ));
+                    }
+                },
+                _ => {
+                    let part = self.or()?;
+                    // Repeating a word shouldn't make it count twice
+                    if !parts.contains(&part) {
+                        parts.push(part);
+                    }
+                }
+            }
+        }
+        Ok(group(parts, Query::Implicit))
+    }
+
+    fn or(&mut self) -> Result<Query, ParseError> {
+        let start = self.position();
+        let mut parts = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            let position = self.position();
+            self.next += 1;
+            self.expect_operand("OR", position)?;
+            parts.push(self.and()?);
+        }
+
+        let parts: Vec<Query> = parts.into_iter().filter(|q| !q.is_empty()).collect();
+        let predicates = parts.iter().filter(|q| q.is_predicate()).count();
+        if predicates > 0 && predicates < parts.len() {
+            return Err(ParseError::new(
+                start,
+                "OR can't combine search terms with filters or exclusions",
+            ));
+        }
+        Ok(group(parts, Query::Or))
+    }
+
+    fn and(&mut self) -> Result<Query, ParseError> {
+        let mut parts = vec![self.unary()?];
+        while self.peek() == Some(&Token::And) {
+            let position = self.position();
+            self.next += 1;
+            self.expect_operand("AND", position)?;
+            parts.push(self.unary()?);
+        }
+        Ok(group(parts, Query::And))
+    }
+
+    fn unary(&mut self) -> Result<Query, ParseError> {
+        match self.peek() {
+            Some(Token::Not) | Some(Token::Minus) => {
+                let (token, position) = self.advance().unwrap();
+                let operator = if token == Token::Not { "NOT" } else { "-" };
+                self.expect_operand(operator, position)?;
+                Ok(not(self.unary()?))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Query, ParseError> {
+        let position = self.position();
+        match self.advance() {
+            Some((Token::Open, _)) => self.sequence(Some(position)),
+            Some((Token::Phrase(prefix, text), _)) => self.phrase(prefix, &text, position),
+            Some((Token::Word(word), _)) => self.word(&word, position),
+            Some((Token::Close, _)) => {
+                Err(ParseError::new(position, "Unexpected closing parenthesis"))
+            }
+            Some((Token::And, _)) => Err(ParseError::new(
+                position,
+                "Expected something to search for before AND",
+            )),
+            Some((Token::Or, _)) => Err(ParseError::new(
+                position,
+                "Expected something to search for before OR",
+            )),
+            _ => Err(ParseError::new(
+                position,
+                "Expected something to search for",
+            )),
+        }
+    }
+
+    fn phrase(
+        &self,
+        prefix: Option<String>,
+        text: &str,
+        position: usize,
+    ) -> Result<Query, ParseError> {
+        let Some(prefix) = prefix else {
+            return Ok(terms(None, self.analyzer.tokenize(text)));
+        };
+
+        if let Some(filter) = parse_filter(&prefix, text, position)? {
+            return Ok(Query::Filter(filter));
+        }
+
+        match Field::try_from(prefix.to_lowercase().as_str()) {
+            Ok(field) => Ok(terms(
+                Some(field),
+                tokenize_field(self.analyzer, Some(field), text),
+            )),
+            // Not a prefix we know, so it's a word of its own
+            Err(_) => Ok(group(
+                vec![
+                    terms(None, self.analyzer.tokenize(&prefix)),
+                    terms(None, self.analyzer.tokenize(text)),
+                ],
+                Query::Implicit,
+            )),
+        }
+    }
+
+    fn word(&self, word: &str, position: usize) -> Result<Query, ParseError> {
+        if let Some((name, value)) = word.split_once(':')
+            && let Some(filter) = parse_filter(name, value, position)?
+        {
+            return Ok(Query::Filter(filter));
+        }
+
+        let (field, rest) = split_field(word);
+
+        // Prefixes and fuzzy terms expand to single terms, anything analyzing to several is
+        // searched for as it is
+        if let Some(stem) = rest.strip_suffix('*')
+            && let [term] = tokenize_field(self.analyzer, field, stem).as_slice()
+        {
+            return Ok(Query::Prefix(field, term.clone()));
+        }
+        if let Some((stem, distance)) = rest.rsplit_once('~')
+            && distance.chars().all(|c| c.is_ascii_digit())
+        {
+            let distance = match distance {
+                "" | "2" => 2,
+                "1" => 1,
+                _ => {
+                    return Err(ParseError::new(
+                        position,
+                        "Fuzzy terms allow one or two edits, term~1 or term~2",
+                    ));
+                }
+            };
+            if let [term] = tokenize_field(self.analyzer, field, stem).as_slice() {
+                return Ok(Query::Fuzzy(field, term.clone(), distance));
+            }
+        }
+
+        Ok(terms(field, tokenize_field(self.analyzer, field, rest)))
+    }
+}
+
+/// Parses a query. Words next to each other are combined with the search's default operator,
+/// `AND`, `OR` and `NOT` (in capitals) or a leading `-` combine them explicitly and parentheses
+/// group them. Anything between double quotes is matched as a phrase. Words and phrases can be
+/// scoped to a field with a prefix, `title:rust` or `title:"not very good"`, and `site:`, `lang:`,
+/// `type:`, `after:` and `before:` filter the results. A trailing `*` matches words by their
+/// prefix and `~1` or `~2` words within that many edits.
+pub fn parse(analyzer: &Analyzer, query: &str) -> Result<Query, ParseError> {
+    let mut parser = Parser {
+        analyzer,
+        tokens: lex(query)?,
+        next: 0,
+        end: query.chars().count() + 1,
+    };
+    let parsed = parser.sequence(None)?;
+
+    if only_excludes(&parsed) {
+        return Err(ParseError::new(
+            1,
+            "Nothing to search for, a query can't only leave things out",
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Parses filters passed alongside a query, each of which has to be nothing but filters, like
+/// the ones facets give.
+pub fn parse_filters(analyzer: &Analyzer, filters: &[String]) -> Result<Vec<Query>, ParseError> {
+    filters
+        .iter()
+        .map(|f| {
+            let query = parse(analyzer, f)?;
+            if !query.is_predicate() {
+                return Err(ParseError::new(
+                    1,
+                    &format!("Expected a filter like site:example.com, not \"{}\"", f),
+                ));
+            }
+            Ok(query)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(t: &str) -> Query {
+        Query::Term(None, t.to_string())
+    }
+
+    fn phrase(terms: &[&str]) -> Query {
+        Query::Phrase(None, terms.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn test_parse() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                phrase(&["not", "very", "good"]),
+                term("search")
+            ])),
+            parse(&analyzer, "\"not very good\" search")
+        );
+        assert_eq!(
+            Ok(Query::Implicit(vec![term("alpha"), term("bravo")])),
+            parse(&analyzer, "\"alpha\" bravo alpha")
+        );
+        assert_eq!(Ok(Query::Implicit(vec![])), parse(&analyzer, " . "));
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                Query::Term(Some(Field::Title), "rust".into()),
+                Query::Phrase(Some(Field::Url), vec!["example".into(), "com".into()]),
+                Query::Phrase(Some(Field::Url), vec!["rust".into(), "lang".into()]),
+                term("nothingsearch"),
+            ])),
+            parse(
+                &analyzer,
+                "TITLE:Rust url:\"example.com\" url:rust-lang nothing:search"
+            )
+        );
+        assert_eq!(
+            Ok(phrase(&["alpha", "bravo"])),
+            parse(&analyzer, "title: \"alpha bravo\"")
+        );
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                term("rust"),
+                Query::Or(vec![term("book"), term("guide")]),
+            ])),
+            parse(&analyzer, "rust book OR guide")
+        );
+        assert_eq!(
+            Ok(Query::Or(vec![
+                Query::And(vec![term("rust"), term("book")]),
+                term("guide"),
+            ])),
+            parse(&analyzer, "rust AND book OR guide")
+        );
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                term("rust"),
+                Query::Not(Box::new(Query::Or(vec![term("game"), term("fungus")]))),
+                Query::Not(Box::new(phrase(&["iron", "oxide"]))),
+            ])),
+            parse(&analyzer, "rust NOT (game OR fungus) -\"iron oxide\"")
+        );
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                term("rust"),
+                term("and"),
+                term("rustlang")
+            ])),
+            parse(&analyzer, "rust and rust-lang")
+        );
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                term("rust"),
+                Query::Filter(Filter::Site("example.com".into())),
+                Query::Filter(Filter::Lang("en".into())),
+                Query::Filter(Filter::Type("html".into())),
+                Query::Filter(Filter::After(1704067200)),
+                Query::Not(Box::new(Query::Filter(Filter::Before(1706659200)))),
+            ])),
+            parse(
+                &analyzer,
+                "rust site:https://Example.com/ lang:en-GB type:html after:2024-01-01 \
+                 -before:2024-01-31"
+            )
+        );
+        assert_eq!(
+            Ok(Query::Or(vec![
+                Query::Filter(Filter::Site("a.com".into())),
+                Query::Filter(Filter::Site("b.com".into())),
+            ])),
+            parse(&analyzer, "site:a.com OR site:b.com")
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let analyzer = Analyzer::default();
+        let error = |q: &str| parse(&analyzer, q).unwrap_err().to_string();
+
+        assert_eq!("Unterminated quote at position 6", error("rust \"book"));
+        assert_eq!("Unclosed parenthesis at position 6", error("rust (book"));
+        assert_eq!(
+            "Unexpected closing parenthesis at position 5",
+            error("rust) book")
+        );
+        assert_eq!(
+            "Expected something to search for after AND at position 6",
+            error("rust AND")
+        );
+        assert_eq!(
+            "Expected something to search for before OR at position 1",
+            error("OR rust")
+        );
+        assert_eq!(
+            "Expected something to search for after NOT at position 6",
+            error("rust NOT )")
+        );
+        assert_eq!("site: needs a value at position 6", error("rust site:"));
+        assert_eq!(
+            "after: expects a date like 2024-01-31, not \"yesterday\" at position 1",
+            error("after:yesterday")
+        );
+        assert_eq!(
+            "OR can't combine search terms with filters or exclusions at position 1",
+            error("rust OR site:example.com")
+        );
+        assert_eq!(
+            "Nothing to search for, a query can't only leave things out at position 1",
+            error("-rust")
+        );
+        assert_eq!(
+            "Fuzzy terms allow one or two edits, term~1 or term~2 at position 6",
+            error("rust serch~3")
+        );
+        assert_eq!(
+            Err(ParseError::new(
+                1,
+                "Expected a filter like site:example.com, not \"rust\""
+            )),
+            parse_filters(&analyzer, &["lang:en".into(), "rust".into()])
+        );
+    }
+
+    #[test]
+    fn test_parse_expansions() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            Ok(Query::Implicit(vec![
+                Query::Prefix(None, "prog".into()),
+                Query::Prefix(Some(Field::Title), "cafe".into()),
+                Query::Fuzzy(None, "serch".into(), 1),
+                Query::Fuzzy(None, "engne".into(), 2),
+            ])),
+            parse(&analyzer, "Prog* title:Café* serch~1 engne~")
+        );
+        // Url prefixes analyze to several terms and so are searched for as a phrase
+        assert_eq!(
+            Ok(Query::Phrase(Some(Field::Url), vec![
+                "rust".into(),
+                "lang".into()
+            ])),
+            parse(&analyzer, "url:rust-lang*")
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::cmp::Reverse;
+
+use anyhow::Result;
+use rusqlite::Connection;
+
+use super::parser::{parse_filter, split_field, tokenize_field};
+use crate::analysis::Analyzer;
+use crate::db::{spelling, term_stats};
+
+/// Terms in at most this many documents are checked for a more likely spelling.
+static MAX_MISSPELLED_FREQUENCY: u64 = 2;
+
+/// Shorter terms have too many close neighbours to guess between.
+static MIN_CORRECTED_LENGTH: usize = 3;
+
+/// The number of insertions, deletions, substitutions and swaps of adjacent characters needed to
+/// turn one string into the other.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            rows[i][j] = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The most documents the term appears in across its fields.
+fn document_frequency(connection: &Connection, term: &str) -> Result<u64> {
+    Ok(term_stats::get_by_term(connection, term)?
+        .into_values()
+        .max()
+        .unwrap_or(0))
+}
+
+/// The closest term in the vocabulary to a rare one, breaking ties by how many documents
+/// contain it. `None` if the term is common enough or nothing more common is close to it.
+pub fn correct(connection: &Connection, term: &str) -> Result<Option<String>> {
+    if term.chars().count() < MIN_CORRECTED_LENGTH {
+        return Ok(None);
+    }
+    let frequency = document_frequency(connection, term)?;
+    if frequency > MAX_MISSPELLED_FREQUENCY {
+        return Ok(None);
+    }
+
+    let mut best = None;
+    let candidates = spelling::get_terms(
+        connection,
+        &spelling::deletions(term, spelling::MAX_DISTANCE),
+    )?;
+    for candidate in candidates {
+        let d = distance(term, &candidate);
+        if d == 0 || d > spelling::max_distance(term).max(spelling::max_distance(&candidate)) {
+            continue;
+        }
+        let df = document_frequency(connection, &candidate)?;
+        if df <= frequency {
+            continue;
+        }
+        let key = (d, Reverse(df));
+        if best.as_ref().is_none_or(|(k, _)| key < *k) {
+            best = Some((key, candidate));
+        }
+    }
+    Ok(best.map(|(_, c)| c))
+}
+
+/// Rewrites the query with its rare words replaced by their corrections, leaving operators,
+/// filters and field prefixes as they were. `None` when there is nothing to correct.
+pub fn suggest(
+    connection: &Connection,
+    analyzer: &Analyzer,
+    query: &str,
+) -> Result<Option<String>> {
+    let mut suggestion = String::with_capacity(query.len());
+    let mut corrected = false;
+    let mut word = String::new();
+
+    for c in query.chars().chain([' ']) {
+        if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+            if let Some(correction) = correct_word(connection, analyzer, &word)? {
+                suggestion.push_str(&correction);
+                corrected = true;
+            } else {
+                suggestion.push_str(&word);
+            }
+            word.clear();
+            suggestion.push(c);
+        } else {
+            word.push(c);
+        }
+    }
+    suggestion.pop();
+
+    Ok(corrected.then_some(suggestion))
+}
+
+fn correct_word(
+    connection: &Connection,
+    analyzer: &Analyzer,
+    word: &str,
+) -> Result<Option<String>> {
+    // Prefix and fuzzy terms are already loose about spelling
+    if matches!(word, "" | "AND" | "OR" | "NOT") || word.ends_with('*') || word.contains('~') {
+        return Ok(None);
+    }
+    let unprefixed = word.strip_prefix('-').unwrap_or(word);
+    if let Some((name, value)) = unprefixed.split_once(':')
+        && !matches!(parse_filter(name, value, 0), Ok(None))
+    {
+        return Ok(None);
+    }
+
+    let (field, rest) = split_field(unprefixed);
+    let terms = tokenize_field(analyzer, field, rest);
+    let [term] = terms.as_slice() else {
+        return Ok(None);
+    };
+    Ok(correct(connection, term)?
+        .map(|correction| format!("{}{}", &word[..word.len() - rest.len()], correction)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::fields::Field;
+    use crate::db;
+    use crate::db::documents::index;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(0, distance("rust", "rust"));
+        assert_eq!(1, distance("rust", "rusty"));
+        assert_eq!(1, distance("rust", "rsut"));
+        assert_eq!(2, distance("rust", "bust!"));
+        assert_eq!(3, distance("", "abc"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let analyzer = Analyzer::default();
+        for (url, text) in [
+            ("http://a.example.com", "search engine"),
+            ("http://b.example.com", "search engine"),
+            ("http://c.example.com", "search engines"),
+            ("http://d.example.com", "seerch"),
+        ] {
+            index(&connection, url, Field::Body, text);
+        }
+
+        assert_eq!(
+            Some("search".to_string()),
+            correct(&connection, "serch").unwrap()
+        );
+        assert_eq!(
+            Some("search".to_string()),
+            correct(&connection, "seerch").unwrap()
+        );
+        assert_eq!(None, correct(&connection, "search").unwrap());
+        assert_eq!(None, correct(&connection, "zzzzzz").unwrap());
+
+        assert_eq!(
+            Some("title:search (engine OR x) -search site:serch.com".to_string()),
+            suggest(
+                &connection,
+                &analyzer,
+                "title:serch (engne OR x) -saerch site:serch.com"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            None,
+            suggest(&connection, &analyzer, "search engine").unwrap()
+        );
+    }
+}
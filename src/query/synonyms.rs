@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::Connection;
+
+use super::Query;
+use super::parser::terms;
+use crate::analysis::fields::Field;
+use crate::db::synonyms;
+
+/// The index's synonyms, ready to expand queries with.
+#[derive(Debug, Default)]
+pub struct Synonyms {
+    /// The alternatives for each source, keyed by its terms.
+    rules: HashMap<Vec<String>, Vec<(Vec<String>, f64)>>,
+    /// The most terms in any source, the longest run of words worth checking.
+    longest: usize,
+}
+
+impl Synonyms {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut synonyms = Self::default();
+        for s in synonyms::get_all(connection)? {
+            let source: Vec<String> = s.source.split(' ').map(String::from).collect();
+            let target: Vec<String> = s.target.split(' ').map(String::from).collect();
+            synonyms.longest = synonyms.longest.max(source.len());
+            synonyms
+                .rules
+                .entry(source)
+                .or_default()
+                .push((target, s.weight));
+        }
+        Ok(synonyms)
+    }
+
+    /// Replaces the terms, phrases and runs of words with synonyms by alternatives between what
+    /// was written and each of its synonyms. The longest run of words with synonyms wins.
+    pub fn expand(&self, query: Query) -> Query {
+        if self.rules.is_empty() {
+            return query;
+        }
+        match query {
+            Query::Term(field, t) => {
+                let original = Query::Term(field, t.clone());
+                self.alternatives(field, &[t], original)
+            }
+            Query::Phrase(field, p) => {
+                let original = Query::Phrase(field, p.clone());
+                self.alternatives(field, &p, original)
+            }
+            Query::Not(q) => Query::Not(Box::new(self.expand(*q))),
+            Query::And(qs) => Query::And(qs.into_iter().map(|q| self.expand(q)).collect()),
+            Query::Or(qs) => Query::Or(qs.into_iter().map(|q| self.expand(q)).collect()),
+            Query::Implicit(qs) => Query::Implicit(self.expand_sequence(qs)),
+            _ => query,
+        }
+    }
+
+    /// Words written next to each other can together be the source of a synonym.
+    fn expand_sequence(&self, parts: Vec<Query>) -> Vec<Query> {
+        let mut expanded = Vec::with_capacity(parts.len());
+        let mut i = 0;
+        while i < parts.len() {
+            let run = (2..=self.longest.min(parts.len() - i))
+                .rev()
+                .find_map(|length| {
+                    let (field, source) = as_terms(&parts[i..i + length])?;
+                    self.rules
+                        .contains_key(&source)
+                        .then_some((length, field, source))
+                });
+
+            match run {
+                Some((length, field, source)) => {
+                    let original = Query::Implicit(parts[i..i + length].to_vec());
+                    expanded.push(self.alternatives(field, &source, original));
+                    i += length;
+                }
+                None => {
+                    expanded.push(self.expand(parts[i].clone()));
+                    i += 1;
+                }
+            }
+        }
+        expanded
+    }
+
+    fn alternatives(&self, field: Option<Field>, source: &[String], original: Query) -> Query {
+        let Some(targets) = self.rules.get(source) else {
+            return original;
+        };
+        let mut alternatives = vec![(original, 1.0)];
+        for (target, weight) in targets {
+            alternatives.push((terms(field, target.clone()), *weight));
+        }
+        Query::Alternatives(alternatives)
+    }
+}
+
+/// The terms of a run of words, if it's nothing but terms in the same field.
+fn as_terms(parts: &[Query]) -> Option<(Option<Field>, Vec<String>)> {
+    let mut field = None;
+    let mut source = Vec::with_capacity(parts.len());
+    for (i, part) in parts.iter().enumerate() {
+        let Query::Term(f, t) = part else {
+            return None;
+        };
+        if i > 0 && *f != field {
+            return None;
+        }
+        field = *f;
+        source.push(t.clone());
+    }
+    Some((field, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Analyzer;
+    use crate::db;
+    use crate::query::parse;
+
+    fn term(t: &str) -> Query {
+        Query::Term(None, t.to_string())
+    }
+
+    #[test]
+    fn test_expand() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        crate::actions::synonyms::add(&connection, "js, javascript", 0.8).unwrap();
+        crate::actions::synonyms::add(&connection, "nyc => new york city", 0.5).unwrap();
+        let synonyms = Synonyms::load(&connection).unwrap();
+        let analyzer = Analyzer::default();
+        let expand = |q: &str| synonyms.expand(parse(&analyzer, q).unwrap());
+
+        assert_eq!(
+            Query::Implicit(vec![
+                Query::Alternatives(vec![(term("js"), 1.0), (term("javascript"), 0.8)]),
+                term("guide"),
+            ]),
+            expand("js guide")
+        );
+        assert_eq!(
+            Query::Implicit(vec![
+                Query::Alternatives(vec![(term("javascript"), 1.0), (term("js"), 0.8)]),
+                Query::Not(Box::new(Query::Alternatives(vec![
+                    (term("nyc"), 1.0),
+                    (
+                        Query::Phrase(None, vec!["new".into(), "york".into(), "city".into()]),
+                        0.5
+                    ),
+                ]))),
+            ]),
+            expand("javascript -nyc")
+        );
+        // One-way rules don't expand their targets
+        assert_eq!(
+            Query::Implicit(vec![term("new"), term("york"), term("city")]),
+            expand("new york city")
+        );
+
+        crate::actions::synonyms::add(&connection, "new york, big apple", 0.8).unwrap();
+        let synonyms = Synonyms::load(&connection).unwrap();
+        assert_eq!(
+            Query::Implicit(vec![
+                Query::Alternatives(vec![
+                    (Query::Implicit(vec![term("new"), term("york")]), 1.0),
+                    (Query::Phrase(None, vec!["big".into(), "apple".into()]), 0.8),
+                ]),
+                term("pizza"),
+            ]),
+            synonyms.expand(parse(&analyzer, "new york pizza").unwrap())
+        );
+    }
+}
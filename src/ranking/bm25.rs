@@ -0,0 +1,93 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::db::settings;
+
+static K1_KEY: &str = "ranking.bm25.k1";
+static B_KEY: &str = "ranking.bm25.b";
+
+/// Okapi BM25. Unlike a raw term frequency the contribution of repeated terms saturates, `k1`
+/// controls how quickly, and the document's length is normalized against the average length of
+/// the field, `b` controls how strongly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25 {
+    pub k1: f64,
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut bm25 = Self::default();
+        if let Some(v) = settings::get(connection, K1_KEY)? {
+            bm25.k1 = v.parse()?;
+        }
+        if let Some(v) = settings::get(connection, B_KEY)? {
+            bm25.b = v.parse()?;
+        }
+        Ok(bm25)
+    }
+
+    /// Always positive, even for terms in more than half of the documents.
+    pub fn idf(document_count: u64, document_frequency: u64) -> f64 {
+        let n = document_count as f64;
+        let df = document_frequency as f64;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    pub fn score(
+        &self,
+        term_count: u64,
+        document_frequency: u64,
+        document_count: u64,
+        length: u64,
+        average_length: f64,
+    ) -> f64 {
+        let tf = term_count as f64;
+        let norm = if average_length > 0.0 {
+            1.0 - self.b + self.b * length as f64 / average_length
+        } else {
+            1.0
+        };
+
+        Self::idf(document_count, document_frequency) * tf * (self.k1 + 1.0) / (tf + self.k1 * norm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saturation() {
+        let bm25 = Bm25::default();
+        let once = bm25.score(1, 1, 10, 100, 100.0);
+        let twice = bm25.score(2, 1, 10, 100, 100.0);
+        let many = bm25.score(50, 1, 10, 100, 100.0);
+        assert!(twice > once);
+        assert!(many < once * (bm25.k1 + 1.0));
+    }
+
+    #[test]
+    fn test_length_normalization() {
+        let bm25 = Bm25::default();
+        assert!(bm25.score(2, 1, 10, 50, 100.0) > bm25.score(2, 1, 10, 200, 100.0));
+
+        let flat = Bm25 { k1: 1.2, b: 0.0 };
+        assert_eq!(
+            flat.score(2, 1, 10, 50, 100.0),
+            flat.score(2, 1, 10, 200, 100.0)
+        );
+    }
+
+    #[test]
+    fn test_idf() {
+        assert!(Bm25::idf(10, 1) > Bm25::idf(10, 5));
+        assert!(Bm25::idf(10, 10) > 0.0);
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// How a score was arrived at, as a tree of the values that went into it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub value: f64,
+    pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    pub fn new(value: f64, description: &str) -> Self {
+        Self {
+            value,
+            description: description.to_string(),
+            details: vec![],
+        }
+    }
+
+    pub fn with_details(value: f64, description: &str, details: Vec<Explanation>) -> Self {
+        Self {
+            value,
+            description: description.to_string(),
+            details,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{}{} = {}",
+            "  ".repeat(depth),
+            self.description,
+            self.value
+        )?;
+        for d in &self.details {
+            d.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per value, indented under the value it went into.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let explanation = Explanation::with_details(2.0, "sum", vec![
+            Explanation::new(1.5, "a"),
+            Explanation::new(0.5, "b"),
+        ]);
+        assert_eq!("sum = 2\n  a = 1.5\n  b = 0.5\n", explanation.to_string());
+    }
+}
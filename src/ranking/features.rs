@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use reqwest::Url;
+use rusqlite::Connection;
+
+use crate::db::{crawls, links, settings, votes};
+
+static TRUST_PREFIX: &str = "trust.";
+
+/// How many days it takes for a page's freshness to halve.
+static FRESHNESS_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// How many net votes it takes to get most of the way to the largest vote feature.
+static VOTE_SCALE: f64 = 5.0;
+
+/// The signals available for ranking a document, each computed independently of the query except
+/// for the text score.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Features {
+    /// How well the document's text matches the query.
+    pub text: f64,
+    /// One for a page crawled just now, halving every thirty days, zero if it was never crawled.
+    pub freshness: f64,
+    /// The log of one plus the number of pages linking to it.
+    pub authority: f64,
+    /// Up votes minus down votes, squashed between minus one and one.
+    pub votes: f64,
+    /// The trust given to the page's domain.
+    pub trust: f64,
+}
+
+impl Features {
+    pub const NAMES: [&'static str; 5] = ["text", "freshness", "authority", "votes", "trust"];
+
+    pub fn load(
+        connection: &Connection,
+        trust: &DomainTrust,
+        url: &str,
+        text: f64,
+        now: i64,
+    ) -> Result<Self> {
+        Ok(Self::load_all(connection, trust, &[(url, text)], now)?[0])
+    }
+
+    /// The features of many documents, given as their urls and text scores, in the same order.
+    pub fn load_all(
+        connection: &Connection,
+        trust: &DomainTrust,
+        documents: &[(&str, f64)],
+        now: i64,
+    ) -> Result<Vec<Self>> {
+        let urls: Vec<&str> = documents.iter().map(|(url, _)| *url).collect();
+        let crawls = crawls::get_by_urls(connection, &urls)?;
+        let sources = links::count_sources_by_targets(connection, &urls)?;
+        let votes = votes::get_totals(connection, &urls)?;
+
+        Ok(documents
+            .iter()
+            .map(|(url, text)| {
+                let freshness = match crawls.get(*url) {
+                    Some(c) if c.last_updated > 0 => {
+                        let age_days = (now - c.last_updated).max(0) as f64 / 86400.0;
+                        0.5f64.powf(age_days / FRESHNESS_HALF_LIFE_DAYS)
+                    }
+                    _ => 0.0,
+                };
+                Self {
+                    text: *text,
+                    freshness,
+                    authority: (sources.get(*url).copied().unwrap_or(0) as f64).ln_1p(),
+                    votes: (votes.get(*url).copied().unwrap_or(0) as f64 / VOTE_SCALE).tanh(),
+                    trust: trust.get(url),
+                }
+            })
+            .collect())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut f64> {
+        match name {
+            "text" => Some(&mut self.text),
+            "freshness" => Some(&mut self.freshness),
+            "authority" => Some(&mut self.authority),
+            "votes" => Some(&mut self.votes),
+            "trust" => Some(&mut self.trust),
+            _ => None,
+        }
+    }
+}
+
+/// How far pages from each domain can be trusted, set per index with `trust.<domain>`. A setting
+/// for a domain covers its subdomains unless they have their own.
+pub struct DomainTrust {
+    domains: HashMap<String, f64>,
+}
+
+impl DomainTrust {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut domains = HashMap::new();
+        for (domain, value) in settings::get_by_prefix(connection, TRUST_PREFIX)? {
+            domains.insert(domain, value.parse()?);
+        }
+        Ok(Self { domains })
+    }
+
+    /// Zero for domains nobody has an opinion about.
+    pub fn get(&self, url: &str) -> f64 {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+        else {
+            return 0.0;
+        };
+
+        let mut domain = host.as_str();
+        loop {
+            if let Some(t) = self.domains.get(domain) {
+                return *t;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return 0.0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::urls::UrlNormalizer;
+    use crate::db;
+
+    #[test]
+    fn test_domain_trust() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        settings::set(&connection, "trust.example.com", "0.5").unwrap();
+        settings::set(&connection, "trust.spam.example.com", "-1").unwrap();
+
+        let trust = DomainTrust::load(&connection).unwrap();
+        assert_eq!(0.5, trust.get("http://example.com/"));
+        assert_eq!(0.5, trust.get("http://www.example.com/a"));
+        assert_eq!(-1.0, trust.get("http://a.spam.example.com/"));
+        assert_eq!(0.0, trust.get("http://example.org/"));
+    }
+
+    #[test]
+    fn test_load() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        let url = "http://example.com/";
+        let trust = DomainTrust::load(&connection).unwrap();
+
+        let features = Features::load(&connection, &trust, url, 1.0, 0).unwrap();
+        assert_eq!(
+            Features {
+                text: 1.0,
+                ..Default::default()
+            },
+            features
+        );
+
+        let crawl = crawls::Crawl::new(url, &UrlNormalizer::default()).unwrap();
+        crawls::insert(&connection, &crawl).unwrap();
+        crawls::set_ready(&connection, url, 1_000_000).unwrap();
+        links::insert(
+            &connection,
+            &links::Link::new("http://a.example.com/", url, "a"),
+        )
+        .unwrap();
+        votes::set(&connection, url, "alice", 1).unwrap();
+        votes::set(&connection, url, "bob", -1).unwrap();
+        votes::set(&connection, url, "carol", 1).unwrap();
+
+        let now = 1_000_000 + 30 * 86400;
+        let features = Features::load(&connection, &trust, url, 1.0, now).unwrap();
+        assert_eq!(0.5, features.freshness);
+        assert_eq!(2.0f64.ln(), features.authority);
+        assert_eq!(0.2f64.tanh(), features.votes);
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::db::settings;
+
+pub mod bm25;
+pub mod explanation;
+pub mod features;
+pub mod ranker;
+pub mod tf_idf;
+
+static FUNCTION_KEY: &str = "ranking.function";
+
+/// How a term's matches in a document are turned into a score.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingFunction {
+    /// The frequency of the term weighted by how rare it is across documents.
+    TfIdf,
+    Bm25,
+}
+
+impl RankingFunction {
+    pub const ALL: [RankingFunction; 2] = [RankingFunction::TfIdf, RankingFunction::Bm25];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RankingFunction::TfIdf => "tf_idf",
+            RankingFunction::Bm25 => "bm25",
+        }
+    }
+
+    /// The index's default, BM25 unless it has been set otherwise.
+    pub fn load(connection: &Connection) -> Result<Self> {
+        match settings::get(connection, FUNCTION_KEY)? {
+            Some(v) => RankingFunction::try_from(v.as_str()).map_err(anyhow::Error::msg),
+            None => Ok(RankingFunction::Bm25),
+        }
+    }
+}
+
+impl TryFrom<&str> for RankingFunction {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, std::string::String> {
+        RankingFunction::ALL
+            .into_iter()
+            .find(|r| r.as_str() == value)
+            .ok_or(format!("Invalid ranking function: {}", value))
+    }
+}
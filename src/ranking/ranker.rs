@@ -0,0 +1,147 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::db::settings;
+use crate::ranking::explanation::Explanation;
+use crate::ranking::features::Features;
+
+static WEIGHT_PREFIX: &str = "ranker.weight.";
+
+/// A document that matched the query, waiting for its final score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub url: String,
+    pub features: Features,
+}
+
+/// Turns the features of the matching documents into their final scores. Rankers see the whole
+/// candidate set at once so they're free to normalize across it.
+pub trait Ranker {
+    /// One score per candidate, in the same order.
+    fn rank(&self, candidates: &[Candidate]) -> Vec<f64>;
+
+    /// How a candidate's score was arrived at given how its text score was. Rankers which
+    /// normalize across candidates should override this, ranking it alone may not give the same
+    /// score.
+    fn explain(&self, candidate: &Candidate, text: Explanation) -> Explanation {
+        let score = self.rank(std::slice::from_ref(candidate))[0];
+        Explanation::with_details(score, "ranker score", vec![text])
+    }
+}
+
+/// A weighted sum of the features. Only the text score counts unless weights are set for the
+/// index with `ranker.weight.<feature>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearRanker {
+    pub weights: Features,
+}
+
+impl Default for LinearRanker {
+    fn default() -> Self {
+        Self {
+            weights: Features {
+                text: 1.0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl LinearRanker {
+    pub fn load(connection: &Connection) -> Result<Self> {
+        let mut ranker = Self::default();
+        for name in Features::NAMES {
+            if let Some(v) = settings::get(connection, &Self::key(name))?
+                && let Some(weight) = ranker.weights.get_mut(name)
+            {
+                *weight = v.parse()?;
+            }
+        }
+        Ok(ranker)
+    }
+
+    pub fn key(name: &str) -> String {
+        format!("{}{}", WEIGHT_PREFIX, name)
+    }
+
+    /// The weight and value of each feature other than the text score.
+    fn weighted(&self, features: &Features) -> [(&'static str, f64, f64); 4] {
+        let w = &self.weights;
+        [
+            ("freshness", w.freshness, features.freshness),
+            ("authority", w.authority, features.authority),
+            ("votes", w.votes, features.votes),
+            ("trust", w.trust, features.trust),
+        ]
+    }
+
+    pub fn score(&self, features: &Features) -> f64 {
+        let w = &self.weights;
+        w.text * features.text
+            + w.freshness * features.freshness
+            + w.authority * features.authority
+            + w.votes * features.votes
+            + w.trust * features.trust
+    }
+}
+
+impl Ranker for LinearRanker {
+    fn rank(&self, candidates: &[Candidate]) -> Vec<f64> {
+        candidates.iter().map(|c| self.score(&c.features)).collect()
+    }
+
+    fn explain(&self, candidate: &Candidate, text: Explanation) -> Explanation {
+        let weight = self.weights.text;
+        let mut details = vec![Explanation::with_details(
+            weight * candidate.features.text,
+            &format!("text × weight {}", weight),
+            vec![text],
+        )];
+        // Features without a weight don't count for anything
+        for (name, weight, value) in self.weighted(&candidate.features) {
+            if weight != 0.0 {
+                details.push(Explanation::with_details(
+                    weight * value,
+                    &format!("{} × weight {}", name, weight),
+                    vec![Explanation::new(value, name)],
+                ));
+            }
+        }
+        Explanation::with_details(
+            self.score(&candidate.features),
+            "weighted sum of features",
+            details,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn test_load() {
+        let connection = Connection::open_in_memory().unwrap();
+        db::initalize_tables(&connection).unwrap();
+        assert_eq!(
+            LinearRanker::default(),
+            LinearRanker::load(&connection).unwrap()
+        );
+
+        settings::set(&connection, &LinearRanker::key("votes"), "0.5").unwrap();
+        let ranker = LinearRanker::load(&connection).unwrap();
+        assert_eq!(0.5, ranker.weights.votes);
+
+        let candidates = [Candidate {
+            url: "http://example.com/".to_string(),
+            features: Features {
+                text: 2.0,
+                votes: -1.0,
+                freshness: 1.0,
+                ..Default::default()
+            },
+        }];
+        assert_eq!(vec![1.5], ranker.rank(&candidates));
+    }
+}
@@ -0,0 +1,13 @@
+/// The frequency of the term in the field, weighted by how rare the term is across documents.
+/// Terms which appear in every document score zero.
+pub fn score(frequency: f64, document_frequency: u64, document_count: u64) -> f64 {
+    frequency * idf(document_count, document_frequency)
+}
+
+/// Zero for terms no document contains.
+pub fn idf(document_count: u64, document_frequency: u64) -> f64 {
+    if document_frequency == 0 {
+        return 0.0;
+    }
+    (document_count as f64 / document_frequency as f64).log10()
+}